@@ -3,7 +3,7 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use axum::http::header;
+use axum::http::{HeaderMap, Method};
 use sqlx::SqlitePool;
 use std::path::Path as StdPath;
 use std::fs::File;
@@ -11,6 +11,7 @@ use std::io::Read;
 use zip::ZipArchive;
 use serde::Serialize;
 use crate::error::AppError;
+use crate::api::range::{self, Content};
 
 
 #[derive(Serialize)]
@@ -51,7 +52,7 @@ pub async fn get_book_pages(
         }
         
         // Sort pages alphabetically for now
-        pages.sort_by(|a, b| a.filename.to_lowercase().cmp(&b.filename.to_lowercase()));
+        pages.sort_by_key(|p| p.filename.to_lowercase());
         
         Ok(Json(pages))
     } else {
@@ -63,17 +64,25 @@ pub async fn get_book_pages(
 pub async fn get_book_page(
     State(pool): State<SqlitePool>,
     Path((id, page_index)): Path<(i64, usize)>,
+    method: Method,
+    headers: HeaderMap,
 ) -> Result<Response, AppError> {
     let result: Option<(String,)> = sqlx::query_as("SELECT file_path FROM media WHERE id = ?")
         .bind(id)
         .fetch_optional(&pool)
         .await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
 
     let (file_path,) = result.ok_or(AppError::NotFound("Media not found".into()))?;
 
-    let path = StdPath::new(&file_path);
+    let content = read_page(StdPath::new(&file_path), page_index)?;
+    Ok(range::respond(&method, &headers, content).await.into_response())
+}
+
+/// One page of a CBZ/ZIP archive, dated like the archive itself.
+fn read_page(path: &StdPath, page_index: usize) -> Result<Content, AppError> {
     let file = File::open(path).map_err(|e| AppError::Internal(format!("Could not open file: {}", e)))?;
+    let modified = file.metadata().and_then(|m| m.modified()).ok();
     let mut archive = ZipArchive::new(file).map_err(|e| AppError::Internal(format!("Could not open zip archive: {}", e)))?;
 
     if page_index >= archive.len() {
@@ -86,11 +95,7 @@ pub async fn get_book_page(
     zip_file.read_to_end(&mut buffer).map_err(|e| AppError::Internal(format!("Could not read page content: {}", e)))?;
 
     let mime_type = mime_guess::from_path(zip_file.name()).first_or_octet_stream();
-
-    Ok((
-        [(header::CONTENT_TYPE, mime_type.as_ref())],
-        buffer
-    ).into_response())
+    Ok(Content::bytes(buffer, modified, mime_type.as_ref()))
 }
 
 fn is_image(filename: &str) -> bool {
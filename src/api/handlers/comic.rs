@@ -22,6 +22,8 @@ pub struct ComicChapter {
     pub id: i64,
     pub title: Option<String>,
     pub chapter_number: Option<i32>,
+    /// Volume from a library parsing rule (stored in season_number)
+    pub volume_number: Option<i32>,
     pub poster_url: Option<String>,
     pub file_path: String,
     pub plot: Option<String>,
@@ -73,13 +75,14 @@ pub async fn get_comic_chapters(
             id,
             title,
             episode_number as chapter_number,
+            season_number as volume_number,
             poster_url,
             file_path,
             plot,
             year
         FROM media
         WHERE media_type = 'book' AND series_name = ?
-        ORDER BY episode_number ASC, title ASC
+        ORDER BY season_number ASC, episode_number ASC, title ASC
         "#
     )
     .bind(&series_name)
@@ -100,13 +103,14 @@ pub async fn get_comic_series_detail(
             id,
             title,
             episode_number as chapter_number,
+            season_number as volume_number,
             poster_url,
             file_path,
             plot,
             year
         FROM media
         WHERE media_type = 'book' AND series_name = ?
-        ORDER BY episode_number ASC, title ASC
+        ORDER BY season_number ASC, episode_number ASC, title ASC
         "#
     )
     .bind(&series_name)
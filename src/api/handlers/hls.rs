@@ -0,0 +1,90 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, Method},
+    response::{IntoResponse, Response},
+};
+use sqlx::SqlitePool;
+use crate::api::range::{self, Content};
+use crate::core::{hls, subtitles};
+use crate::core::subtitles::BurnIn;
+use crate::error::AppError;
+
+const PLAYLIST_TYPE: &str = "application/vnd.apple.mpegurl";
+
+/// `subtitle` is a `/subtitles` track ID ("embedded-5", "Movie.en.sub") of an image track to
+/// burn into the video.
+#[derive(serde::Deserialize)]
+pub struct HlsQuery {
+    subtitle: Option<String>,
+}
+
+impl HlsQuery {
+    /// The query for the playlists' URLs, so every segment is cut with the same subtitle
+    fn passthrough(&self) -> String {
+        self.subtitle.as_deref()
+            .map(|track| format!("?subtitle={}", urlencoding::encode(track)))
+            .unwrap_or_default()
+    }
+}
+
+/// The selected subtitle, if it has to be burned in. Text tracks are refused: the player loads
+/// them as WebVTT.
+async fn burn_in(pool: &SqlitePool, id: i64, source: &hls::Source, query: &HlsQuery) -> Result<Option<BurnIn>, AppError> {
+    let Some(track) = query.subtitle.as_deref() else { return Ok(None) };
+    match subtitles::burn_in_track(pool, id, std::path::Path::new(&source.file_path), track).await? {
+        Some(burn_in) => Ok(Some(burn_in)),
+        None => Err(AppError::BadRequest(format!("Subtitle track {} is text; load it as WebVTT instead of burning it in", track))),
+    }
+}
+
+fn playlist_response(playlist: String) -> Response {
+    (
+        [(header::CONTENT_TYPE, PLAYLIST_TYPE), (header::CACHE_CONTROL, "no-cache")],
+        playlist,
+    ).into_response()
+}
+
+fn rendition(quality: &str) -> Result<hls::Rendition, AppError> {
+    hls::rendition(quality).ok_or_else(|| AppError::NotFound(format!("Unknown quality '{}'", quality)))
+}
+
+pub async fn get_master_playlist(
+    Path(id): Path<i64>,
+    Query(query): Query<HlsQuery>,
+    State(pool): State<SqlitePool>,
+) -> Result<Response, AppError> {
+    let source = hls::source(&pool, id).await?;
+    burn_in(&pool, id, &source, &query).await?;
+    Ok(playlist_response(hls::master_playlist(&source, &query.passthrough())))
+}
+
+pub async fn get_variant_playlist(
+    Path((id, quality)): Path<(i64, String)>,
+    Query(query): Query<HlsQuery>,
+    State(pool): State<SqlitePool>,
+) -> Result<Response, AppError> {
+    rendition(&quality)?;
+    let source = hls::source(&pool, id).await?;
+    Ok(playlist_response(hls::variant_playlist(&source, &query.passthrough())))
+}
+
+pub async fn get_segment(
+    Path((id, quality, segment)): Path<(i64, String, String)>,
+    Query(query): Query<HlsQuery>,
+    State(pool): State<SqlitePool>,
+    method: Method,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let rendition = rendition(&quality)?;
+    let n: u32 = segment.strip_prefix("segment_")
+        .and_then(|s| s.strip_suffix(".ts"))
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| AppError::NotFound(format!("Segment {} not found", segment)))?;
+
+    let source = hls::source(&pool, id).await?;
+    let burn_in = burn_in(&pool, id, &source, &query).await?;
+    let path = hls::segment(id, &source, rendition, burn_in.as_ref(), n).await?;
+    let content = Content::file(&path, "video/mp2t").await
+        .map_err(|e| AppError::Internal(format!("Failed to read segment: {}", e)))?;
+    Ok(range::respond(&method, &headers, content).await.into_response())
+}
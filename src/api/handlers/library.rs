@@ -1,12 +1,20 @@
 use axum::{
     extract::{Path, State},
     http::StatusCode,
+    response::{IntoResponse, Response},
     Json,
 };
 use sqlx::SqlitePool;
 use crate::error::AppError;
 use crate::db::models::{Library, LibraryType};
 use crate::core::scanner::scan_media;
+use crate::core::metadata::KNOWN_PROVIDERS;
+use crate::core::metadata_merge::MergePolicy;
+use crate::core::nfo_export::{self, OverwritePolicy};
+use crate::providers::tvdb::EPISODE_ORDERS;
+use crate::core::episode_numbering::EPISODE_NUMBERINGS;
+use crate::core::parsing_rules::ParsingRules;
+use crate::core::scanner::{parse_path, ParsedPath};
 use super::common::{ListDirectoriesRequest, DirectoryEntry};
 use std::path::Path as StdPath;
 
@@ -15,6 +23,58 @@ pub struct CreateLibraryRequest {
     name: String,
     path: String,
     library_type: LibraryType,
+    metadata_providers: Option<String>,
+    metadata_merge_policy: Option<String>,
+    episode_order: Option<String>,
+    episode_numbering: Option<String>,
+    parsing_rules: Option<String>,
+}
+
+/// Partial update of a library's settings. Omitted fields are left unchanged;
+/// an empty string clears that setting back to the default.
+#[derive(serde::Deserialize)]
+pub struct UpdateLibraryRequest {
+    name: Option<String>,
+    metadata_providers: Option<String>,
+    metadata_merge_policy: Option<String>,
+    episode_order: Option<String>,
+    episode_numbering: Option<String>,
+    parsing_rules: Option<String>,
+}
+
+/// Reject unknown provider names, malformed merge policies or parsing rules, episode orders and
+/// numberings before they're stored.
+fn validate_metadata_options(
+    providers: Option<&str>,
+    merge_policy: Option<&str>,
+    episode_order: Option<&str>,
+    episode_numbering: Option<&str>,
+    parsing_rules: Option<&str>,
+) -> Result<(), AppError> {
+    if let Some(providers) = providers {
+        for name in providers.split(',').map(|p| p.trim().to_lowercase()).filter(|p| !p.is_empty()) {
+            if !KNOWN_PROVIDERS.contains(&name.as_str()) {
+                return Err(AppError::BadRequest(format!("Unknown provider: {}", name)));
+            }
+        }
+    }
+    if let Some(policy) = merge_policy.filter(|p| !p.trim().is_empty()) {
+        MergePolicy::from_json(policy)?;
+    }
+    if let Some(order) = episode_order.filter(|o| !o.is_empty()) {
+        if !EPISODE_ORDERS.contains(&order) {
+            return Err(AppError::BadRequest(format!("Unknown episode order: {}", order)));
+        }
+    }
+    if let Some(numbering) = episode_numbering.filter(|n| !n.is_empty()) {
+        if !EPISODE_NUMBERINGS.contains(&numbering) {
+            return Err(AppError::BadRequest(format!("Unknown episode numbering: {}", numbering)));
+        }
+    }
+    if let Some(rules) = parsing_rules.filter(|r| !r.trim().is_empty()) {
+        ParsingRules::from_json(rules)?;
+    }
+    Ok(())
 }
 
 pub async fn get_libraries(State(pool): State<SqlitePool>) -> Result<Json<Vec<Library>>, AppError> {
@@ -28,10 +88,17 @@ pub async fn create_library(
     State(pool): State<SqlitePool>,
     Json(payload): Json<CreateLibraryRequest>,
 ) -> Result<StatusCode, AppError> {
-    sqlx::query("INSERT INTO libraries (name, path, library_type) VALUES (?, ?, ?)")
+    validate_metadata_options(payload.metadata_providers.as_deref(), payload.metadata_merge_policy.as_deref(), payload.episode_order.as_deref(), payload.episode_numbering.as_deref(), payload.parsing_rules.as_deref())?;
+
+    sqlx::query("INSERT INTO libraries (name, path, library_type, metadata_providers, metadata_merge_policy, episode_order, episode_numbering, parsing_rules) VALUES (?, ?, ?, NULLIF(?, ''), NULLIF(?, ''), NULLIF(?, ''), NULLIF(?, ''), NULLIF(?, ''))")
         .bind(&payload.name)
         .bind(&payload.path)
         .bind(&payload.library_type)
+        .bind(&payload.metadata_providers)
+        .bind(&payload.metadata_merge_policy)
+        .bind(&payload.episode_order)
+        .bind(&payload.episode_numbering)
+        .bind(&payload.parsing_rules)
         .execute(&pool)
         .await?;
 
@@ -44,6 +111,83 @@ pub async fn create_library(
     Ok(StatusCode::CREATED)
 }
 
+pub async fn update_library(
+    Path(id): Path<i64>,
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<UpdateLibraryRequest>,
+) -> Result<Json<Library>, AppError> {
+    validate_metadata_options(payload.metadata_providers.as_deref(), payload.metadata_merge_policy.as_deref(), payload.episode_order.as_deref(), payload.episode_numbering.as_deref(), payload.parsing_rules.as_deref())?;
+
+    sqlx::query(
+        "UPDATE libraries SET
+            name = COALESCE(?, name),
+            metadata_providers = CASE WHEN ? IS NULL THEN metadata_providers ELSE NULLIF(?, '') END,
+            metadata_merge_policy = CASE WHEN ? IS NULL THEN metadata_merge_policy ELSE NULLIF(?, '') END,
+            episode_order = CASE WHEN ? IS NULL THEN episode_order ELSE NULLIF(?, '') END,
+            episode_numbering = CASE WHEN ? IS NULL THEN episode_numbering ELSE NULLIF(?, '') END,
+            parsing_rules = CASE WHEN ? IS NULL THEN parsing_rules ELSE NULLIF(?, '') END
+         WHERE id = ?"
+    )
+    .bind(&payload.name)
+    .bind(&payload.metadata_providers)
+    .bind(&payload.metadata_providers)
+    .bind(&payload.metadata_merge_policy)
+    .bind(&payload.metadata_merge_policy)
+    .bind(&payload.episode_order)
+    .bind(&payload.episode_order)
+    .bind(&payload.episode_numbering)
+    .bind(&payload.episode_numbering)
+    .bind(&payload.parsing_rules)
+    .bind(&payload.parsing_rules)
+    .bind(id)
+    .execute(&pool)
+    .await?;
+
+    let library = sqlx::query_as::<_, Library>("SELECT * FROM libraries WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Library not found".to_string()))?;
+
+    Ok(Json(library))
+}
+
+/// Body for the parser test endpoint. `path` is absolute or relative to the library root;
+/// `parsing_rules` tries unsaved rules in place of the library's own ("" for built-ins only).
+#[derive(serde::Deserialize)]
+pub struct TestParserRequest {
+    path: String,
+    parsing_rules: Option<String>,
+}
+
+/// Show how a path would be parsed during a scan, without touching the database.
+pub async fn test_parser(
+    Path(id): Path<i64>,
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<TestParserRequest>,
+) -> Result<Json<ParsedPath>, AppError> {
+    let library = sqlx::query_as::<_, Library>("SELECT * FROM libraries WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Library not found".to_string()))?;
+
+    let rules = match payload.parsing_rules.as_deref() {
+        Some(rules) if rules.trim().is_empty() => ParsingRules::default(),
+        Some(rules) => ParsingRules::from_json(rules)?,
+        None => ParsingRules::for_library(&library),
+    };
+
+    // `..` isn't resolved by `join`, so a path like "../x" would still start with the library's
+    let relative = StdPath::new(&payload.path);
+    let path = StdPath::new(&library.path).join(relative);
+    if !path.starts_with(&library.path) || relative.components().any(|c| c == std::path::Component::ParentDir) {
+        return Err(AppError::BadRequest("Path is outside the library".to_string()));
+    }
+
+    Ok(Json(parse_path(&path, &library, &rules)))
+}
+
 pub async fn delete_library(
     Path(id): Path<i64>,
     State(pool): State<SqlitePool>,
@@ -54,6 +198,16 @@ pub async fn delete_library(
         .execute(&pool)
         .await?;
 
+    sqlx::query("DELETE FROM metadata_retry_queue WHERE media_id IN (SELECT id FROM media WHERE library_id = ?)")
+        .bind(id)
+        .execute(&pool)
+        .await?;
+
+    sqlx::query("DELETE FROM media_streams WHERE media_id IN (SELECT id FROM media WHERE library_id = ?)")
+        .bind(id)
+        .execute(&pool)
+        .await?;
+
     // 2. Delete all media entries for this library
     sqlx::query("DELETE FROM media WHERE library_id = ?")
         .bind(id)
@@ -69,6 +223,42 @@ pub async fn delete_library(
     Ok(StatusCode::NO_CONTENT)
 }
 
+#[derive(serde::Deserialize)]
+pub struct ExportLibraryRequest {
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    overwrite: OverwritePolicy,
+}
+
+/// Export NFO files and artwork for a library. A dry run returns the report straight away;
+/// a real export runs in the background and is followed with `get_export_status`.
+pub async fn export_library(
+    State(pool): State<SqlitePool>,
+    Path(id): Path<i64>,
+    Json(payload): Json<ExportLibraryRequest>,
+) -> Result<Response, AppError> {
+    let library = sqlx::query_as::<_, Library>("SELECT * FROM libraries WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Library with id {} not found", id)))?;
+
+    if payload.dry_run {
+        let report = nfo_export::export_library(&pool, &library, true, payload.overwrite).await?;
+        return Ok(Json(report).into_response());
+    }
+
+    let job = nfo_export::start_job(pool, library, payload.overwrite)?;
+    Ok((StatusCode::ACCEPTED, Json(job)).into_response())
+}
+
+pub async fn get_export_status(Path(id): Path<i64>) -> Result<Json<nfo_export::ExportJob>, AppError> {
+    nfo_export::job_status(id)
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("No export has run for library {}", id)))
+}
+
 pub async fn scan_all_libraries(State(pool): State<SqlitePool>) -> StatusCode {
     let pool_clone = pool.clone();
     tokio::spawn(async move {
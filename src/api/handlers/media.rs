@@ -1,18 +1,45 @@
 use axum::{
     extract::{Path, State},
+    http::header,
+    response::{IntoResponse, Response},
     Json,
 };
 use sqlx::SqlitePool;
 use crate::error::AppError;
-use crate::core::media_service;
+use crate::core::{extras, media_parts, media_service, media_streams, media_versions};
 use crate::models::db::media::Media;
+use crate::models::media::{ExtraDto, MediaDetailDto};
+use crate::providers::nfo::{self, ArtworkKind};
 
 pub async fn get_library_media(
     Path(id): Path<i64>,
     State(pool): State<SqlitePool>,
+    axum::extract::Query(filter): axum::extract::Query<QualityFilter>,
 ) -> Result<Json<Vec<Media>>, AppError> {
-    let media = sqlx::query_as::<_, Media>("SELECT m.*, l.library_type FROM media m JOIN libraries l ON m.library_id = l.id WHERE m.library_id = ? ORDER BY m.title ASC")
+    let min_height = match filter.resolution.as_deref().map(str::to_lowercase) {
+        None => None,
+        Some(r) if r == "4k" || r == "uhd" => Some(2160),
+        Some(r) => Some(r.trim_end_matches('p').parse::<i64>()
+            .map_err(|_| AppError::BadRequest(format!("Invalid resolution '{}'", r)))?),
+    };
+    let hdr = filter.hdr.map(i64::from);
+
+    // Resolution is the filename's or, failing that, the probed one; codec and HDR come from the probed video stream.
+    // A movie matches when any of its versions does.
+    let media = sqlx::query_as::<_, Media>(
+        "SELECT m.*, l.library_type FROM media m JOIN libraries l ON m.library_id = l.id
+         WHERE m.library_id = ? AND m.version_of IS NULL AND m.part_of IS NULL AND m.extra_type IS NULL
+         AND (? IS NULL OR EXISTS (SELECT 1 FROM media v WHERE COALESCE(v.version_of, v.id) = m.id AND CAST(REPLACE(v.resolution, 'p', '') AS INTEGER) >= ?))
+         AND (? IS NULL OR EXISTS (SELECT 1 FROM media v JOIN media_streams s ON s.media_id = v.id
+                                   WHERE COALESCE(v.version_of, v.id) = m.id AND s.stream_type = 'video' AND LOWER(s.codec) = LOWER(?)))
+         AND (? IS NULL OR ? = EXISTS (SELECT 1 FROM media v JOIN media_streams s ON s.media_id = v.id
+                                       WHERE COALESCE(v.version_of, v.id) = m.id AND s.stream_type = 'video' AND s.hdr IS NOT NULL))
+         ORDER BY m.title ASC"
+    )
         .bind(id)
+        .bind(min_height).bind(min_height)
+        .bind(&filter.video_codec).bind(&filter.video_codec)
+        .bind(hdr).bind(hdr)
         .fetch_all(&pool)
         .await?;
     Ok(Json(media))
@@ -46,10 +73,11 @@ pub async fn get_recently_added(State(pool): State<SqlitePool>) -> Result<Json<V
              ELSE media.backdrop_url END) as backdrop_url,
             NULL as still_url,
             media.runtime,
-            media.genres
+            media.genres,
+            media.original_title
         FROM media
         JOIN libraries l ON media.library_id = l.id
-        WHERE l.library_type != 'other'
+        WHERE l.library_type != 'other' AND media.version_of IS NULL AND media.part_of IS NULL AND media.extra_type IS NULL
         GROUP BY COALESCE(media.series_name, media.id)
         ORDER BY MAX(media.added_at) DESC
         LIMIT 20
@@ -65,20 +93,65 @@ pub async fn get_recently_added(State(pool): State<SqlitePool>) -> Result<Json<V
 pub async fn get_media_details(
     State(pool): State<SqlitePool>,
     Path(id): Path<i64>,
-) -> Result<Json<Media>, AppError> {
+) -> Result<Json<MediaDetailDto>, AppError> {
     let item = sqlx::query_as::<_, Media>("SELECT m.*, l.library_type FROM media m JOIN libraries l ON m.library_id = l.id WHERE m.id = ?")
         .bind(id)
         .fetch_optional(&pool)
         .await?
         .ok_or_else(|| AppError::NotFound(format!("Media with id {} not found", id)))?;
+    let versions = media_versions::list_versions(&pool, id).await?;
+    let parts = media_parts::list_parts(&pool, id).await?;
+    let total_duration = if parts.is_empty() { item.duration } else { media_parts::total_duration(&parts) };
+    let streams = media_streams::list(&pool, id).await?;
     
-    Ok(Json(item))
+    Ok(Json(MediaDetailDto { media: item, versions, parts, total_duration, streams }))
+}
+
+/// Trailers, featurettes and other extras of a movie.
+pub async fn get_media_extras(
+    State(pool): State<SqlitePool>,
+    Path(id): Path<i64>,
+) -> Result<Json<Vec<ExtraDto>>, AppError> {
+    let exists: Option<i64> = sqlx::query_scalar("SELECT id FROM media WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?;
+    if exists.is_none() {
+        return Err(AppError::NotFound(format!("Media with id {} not found", id)));
+    }
+
+    Ok(Json(extras::list_for_media(&pool, id).await?))
+}
+
+/// Serve a local sidecar image (poster, backdrop or still) found next to a media file.
+pub async fn get_media_artwork(
+    State(pool): State<SqlitePool>,
+    Path((id, kind)): Path<(i64, String)>,
+) -> Result<Response, AppError> {
+    let kind = ArtworkKind::parse(&kind)
+        .ok_or_else(|| AppError::BadRequest(format!("Unknown artwork type: {}", kind)))?;
+    let (file_path, library_path, series_name) = media_service::get_media_location(&pool, id).await?
+        .ok_or_else(|| AppError::NotFound(format!("Media with id {} not found", id)))?;
+
+    let video = std::path::Path::new(&file_path);
+    let series_dir = series_name.and_then(|_| nfo::series_dir(video, &library_path));
+    let image = nfo::find_artwork(video, &library_path, series_dir.as_deref(), kind)
+        .ok_or_else(|| AppError::NotFound(format!("No local {} for media {}", kind.as_str(), id)))?;
+
+    let bytes = tokio::fs::read(&image).await
+        .map_err(|e| AppError::Internal(format!("Could not read {}: {}", image.display(), e)))?;
+    let mime_type = mime_guess::from_path(&image).first_or_octet_stream();
+
+    Ok((
+        [(header::CONTENT_TYPE, mime_type.as_ref())],
+        bytes
+    ).into_response())
 }
 
 pub async fn refresh_media_metadata(
     State(pool): State<SqlitePool>,
     Path(id): Path<i64>,
-) -> Result<Json<Media>, AppError> {
+) -> Result<Json<MediaDetailDto>, AppError> {
     use crate::core::metadata::fetch_metadata;
 
     let media = sqlx::query_as::<_, Media>("SELECT * FROM media WHERE id = ?")
@@ -99,15 +172,30 @@ pub async fn refresh_media_metadata(
 
     tracing::info!("Refreshing metadata for: {}", title_to_search);
 
-    let meta = fetch_metadata(&title_to_search, type_hint, &pool).await
+    // Local NFOs and artwork beside the file take priority over the provider chain
+    let video = std::path::Path::new(&media.file_path);
+    let library_path = media_service::get_media_location(&pool, id).await?
+        .map(|(_, library_path, _)| library_path)
+        .ok_or_else(|| AppError::NotFound(format!("Media with id {} not found", id)))?;
+    let series_dir = match &media.series_name {
+        Some(_) => nfo::series_dir(video, &library_path),
+        None => None,
+    };
+    let local = match &series_dir {
+        Some(dir) => nfo::series_nfo(dir),
+        None => nfo::movie_nfo(video, &library_path),
+    };
+
+    let mut meta = fetch_metadata(&title_to_search, type_hint, Some(media.library_id), local, &pool).await
         .map_err(|e| AppError::External(format!("Failed to fetch metadata: {}", e)))?;
+    nfo::apply_local_artwork(&mut meta, id, video, &library_path, series_dir.as_deref());
 
     media_service::update_media_metadata(&pool, id, &meta).await?;
     
     get_media_details(State(pool), Path(id)).await
 }
 
-use crate::dtos::requests::{SearchQuery, IdentifyRequest};
+use crate::dtos::requests::{SearchQuery, IdentifyRequest, QualityFilter};
 
 pub async fn search_handler(
     State(pool): State<SqlitePool>,
@@ -132,7 +220,7 @@ pub async fn identify_media(
     State(pool): State<SqlitePool>,
     Path(id): Path<i64>,
     Json(payload): Json<IdentifyRequest>,
-) -> Result<Json<Media>, AppError> {
+) -> Result<Json<MediaDetailDto>, AppError> {
     use crate::core::metadata::fetch_by_id;
 
     let media_type = payload.media_type.as_deref();
@@ -152,7 +240,7 @@ pub async fn search_library(
     let mut sql = String::from(
         "SELECT m.*, l.library_type FROM media m 
          JOIN libraries l ON m.library_id = l.id 
-         WHERE (m.title LIKE ? OR m.series_name LIKE ? OR m.plot LIKE ?)"
+         WHERE (m.title LIKE ? OR m.series_name LIKE ? OR m.plot LIKE ?) AND m.version_of IS NULL AND m.part_of IS NULL AND m.extra_type IS NULL"
     );
 
     if params.media_type.is_some() {
@@ -174,3 +262,48 @@ pub async fn search_library(
     
     Ok(Json(media))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn quality_filters_match_any_version_of_a_movie() {
+        let dir = std::env::temp_dir().join(format!("vortex-quality-filter-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let pool = crate::db::connect(&format!("sqlite:{}", dir.join("test.db").display())).await;
+        sqlx::query("INSERT INTO libraries (name, path, library_type) VALUES ('Movies', '/movies', 'movies')").execute(&pool).await.unwrap();
+        // Heat's primary is a 1080p H.264 encode with a 2160p HEVC HDR version; Alien is only 720p
+        for (path, title, version_of, resolution, codec, hdr) in [
+            ("/movies/Heat (1995) - 1080p.mkv", "Heat", None, "1080p", "h264", None),
+            ("/movies/Heat (1995) - 2160p.mkv", "Heat", Some(1), "2160p", "hevc", Some("HDR10")),
+            ("/movies/Alien (1979) - 720p.mkv", "Alien", None, "720p", "h264", None),
+        ] {
+            let id = sqlx::query("INSERT INTO media (file_path, title, library_id, media_type, version_of, resolution) VALUES (?, ?, 1, 'movie', ?, ?)")
+                .bind(path).bind(title).bind(version_of).bind(resolution)
+                .execute(&pool).await.unwrap().last_insert_rowid();
+            sqlx::query("INSERT INTO media_streams (media_id, stream_index, stream_type, codec, hdr) VALUES (?, 0, 'video', ?, ?)")
+                .bind(id).bind(codec).bind(hdr).execute(&pool).await.unwrap();
+        }
+
+        let titles = |resolution: Option<&str>, video_codec: Option<&str>, hdr: Option<bool>| {
+            let pool = pool.clone();
+            let filter = QualityFilter { resolution: resolution.map(str::to_string), video_codec: video_codec.map(str::to_string), hdr };
+            async move {
+                let Json(media) = get_library_media(Path(1), State(pool), axum::extract::Query(filter)).await.unwrap();
+                media.into_iter().filter_map(|m| m.title).collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(titles(None, None, None).await, ["Alien", "Heat"]);
+        assert_eq!(titles(Some("4k"), None, None).await, ["Heat"]);
+        assert_eq!(titles(Some("720p"), None, None).await, ["Alien", "Heat"]);
+        assert_eq!(titles(None, Some("HEVC"), None).await, ["Heat"]);
+        assert_eq!(titles(None, None, Some(true)).await, ["Heat"]);
+        assert_eq!(titles(None, None, Some(false)).await, ["Alien"]);
+
+        pool.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -4,6 +4,8 @@ pub mod media;
 pub mod playback;
 pub mod tv;
 pub mod books;
+pub mod hls;
+pub mod trickplay;
 pub mod comic;
 pub mod settings;
 pub mod reading_list;
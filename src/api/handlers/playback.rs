@@ -1,20 +1,62 @@
 use axum::{
     body::Body,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::{header, StatusCode, HeaderMap},
     response::{IntoResponse, Response},
     Json,
 };
 use sqlx::{SqlitePool, FromRow};
-use tokio::fs::File;
-use tokio::io::{AsyncReadExt, AsyncSeekExt, SeekFrom};
 use serde::{Serialize, Deserialize};
 use crate::error::AppError;
+use crate::api::range::{self, Content};
+use crate::core::{device_profile, disc, media_parts, media_streams, media_versions, remux, subtitle_format, subtitles};
+use crate::core::subtitle_format::SubtitleFormat;
+use crate::core::device_profile::DeviceProfile;
+use crate::dtos::requests::{DownloadSubtitleRequest, PlaybackRequest};
+use crate::models::media::PlaybackPlanDto;
+use crate::models::metadata::SubtitleCandidate;
+
+/// `version` picks another file of the same movie (see `/media/:id` versions) to stream.
+/// `start` (seconds) starts a remuxed stream (a disc rip, or any video through
+/// `/stream/:id/remux`) part way in, since those can't be seeked by byte range.
+/// `transcode_audio` makes `/stream/:id/remux` convert audio the device can't decode even
+/// when MP4 could carry it.
+#[derive(serde::Deserialize)]
+pub struct StreamQuery {
+    version: Option<i64>,
+    start: Option<f64>,
+    transcode_audio: Option<bool>,
+}
+
+/// `offset` shifts subtitle timings by that many seconds (negative is earlier) for files that
+/// are out of sync.
+#[derive(serde::Deserialize)]
+pub struct SubtitleQuery {
+    offset: Option<f64>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SubtitleListQuery {
+    language: Option<String>,
+}
 
+/// `language` ("en,fr") defaults to the preferred subtitle languages; `provider` to the
+/// `subtitle_provider` setting.
+#[derive(serde::Deserialize)]
+pub struct SubtitleSearchQuery {
+    language: Option<String>,
+    provider: Option<String>,
+}
+
+const WEBVTT_TYPE: &str = "text/vtt; charset=utf-8";
+
+/// Progress in seconds. For a stacked movie the player may report its position within one
+/// `part` (see `/media/:id` parts) instead; it is mapped onto the combined timeline.
 #[derive(serde::Deserialize)]
 pub struct UpdateProgressRequest {
     position: i64,
     total_duration: i64,
+    part: Option<i32>,
 }
 
 #[derive(Debug, FromRow, Serialize, Deserialize, Clone)]
@@ -36,6 +78,7 @@ pub struct MediaWithProgress {
     pub still_url: Option<String>,
     pub runtime: Option<i32>,
     pub genres: Option<String>,
+    pub original_title: Option<String>,
     pub progress: Option<i64>,
     pub library_type: Option<crate::db::models::LibraryType>,
 }
@@ -47,7 +90,7 @@ pub async fn get_continue_watching(State(pool): State<SqlitePool>) -> Result<Jso
          JOIN playback_progress p ON m.id = p.media_id
          JOIN libraries l ON m.library_id = l.id
          WHERE p.position > 10 AND p.position < (p.total_duration * 0.95)
-         AND l.library_type NOT IN ('other', 'books')
+         AND l.library_type NOT IN ('other', 'books') AND m.extra_type IS NULL
          ORDER BY p.last_watched DESC
          LIMIT 10"
     )
@@ -62,65 +105,114 @@ pub async fn update_progress(
     State(pool): State<SqlitePool>,
     Json(payload): Json<UpdateProgressRequest>,
 ) -> Result<StatusCode, AppError> {
+    // Progress is shared by every version of a movie and kept on the primary
+    let id = media_versions::group_id(&pool, id).await?;
+    let (position, total_duration) = match payload.part {
+        Some(part_number) => combined_position(&pool, id, part_number, payload.position, payload.total_duration).await?,
+        None => (payload.position, payload.total_duration),
+    };
     sqlx::query(
         "INSERT INTO playback_progress (media_id, position, total_duration, last_watched) 
          VALUES (?, ?, ?, CURRENT_TIMESTAMP) 
          ON CONFLICT(media_id) DO UPDATE SET position = ?, total_duration = ?, last_watched = CURRENT_TIMESTAMP"
     )
     .bind(id)
-    .bind(payload.position)
-    .bind(payload.total_duration)
-    .bind(payload.position)
-    .bind(payload.total_duration)
+    .bind(position)
+    .bind(total_duration)
+    .bind(position)
+    .bind(total_duration)
     .execute(&pool)
     .await?;
 
     Ok(StatusCode::OK)
 }
 
+/// Map a position within one part of a stacked movie onto the combined timeline. A part whose
+/// length couldn't be probed learns it from the player's reported duration.
+async fn combined_position(pool: &SqlitePool, id: i64, part_number: i32, position: i64, part_duration: i64) -> Result<(i64, i64), AppError> {
+    let mut parts = media_parts::list_parts(pool, id).await?;
+    let index = parts.iter().position(|p| p.part_number == part_number)
+        .ok_or_else(|| AppError::BadRequest(format!("Media {} has no part {}", id, part_number)))?;
+    if parts[index].duration.is_none() && part_duration > 0 {
+        media_parts::set_duration(pool, parts[index].id, part_duration as f64).await?;
+        parts = media_parts::list_parts(pool, id).await?;
+    }
+
+    let offset = parts.get(index).and_then(|p| p.offset)
+        .ok_or_else(|| AppError::BadRequest(format!("The length of a part before part {} is unknown; report progress for it first", part_number)))?;
+    let total = media_parts::total_duration(&parts).unwrap_or(offset + part_duration as f64);
+    Ok(((offset + position as f64).round() as i64, total.round() as i64))
+}
+
 pub async fn get_media_progress(
     Path(id): Path<i64>,
     State(pool): State<SqlitePool>,
 ) -> Result<Json<serde_json::Value>, AppError> {
+    let id = media_versions::group_id(&pool, id).await?;
     let progress: Option<i64> = sqlx::query_scalar("SELECT position FROM playback_progress WHERE media_id = ?")
         .bind(id)
         .fetch_optional(&pool)
         .await?;
     
-    Ok(Json(serde_json::json!({ "position": progress.unwrap_or(0) })))
+    let position = progress.unwrap_or(0);
+
+    // Stacked movies also say which part to resume and where in it
+    let parts = media_parts::list_parts(&pool, id).await?;
+    if let Some((part, part_position)) = media_parts::locate(&parts, position as f64) {
+        return Ok(Json(serde_json::json!({
+            "position": position,
+            "part": part.part_number,
+            "part_id": part.id,
+            "part_position": part_position.floor() as i64,
+        })));
+    }
+    Ok(Json(serde_json::json!({ "position": position })))
+}
+
+/// File path and disc type of the file to play: `id`, or the requested version of it.
+async fn stream_source(pool: &SqlitePool, id: i64, version: Option<i64>) -> Result<Option<(String, Option<String>)>, sqlx::Error> {
+    // A requested version must belong to the same movie as `id`
+    sqlx::query_as(
+        "SELECT file_path, disc_type FROM media WHERE id = ?
+         AND COALESCE(version_of, id) = (SELECT COALESCE(version_of, id) FROM media WHERE id = ?)"
+    )
+        .bind(version.unwrap_or(id))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// A remuxed stream: a single growing response without byte ranges.
+fn remux_response(method: &axum::http::Method, file_path: &str, input: &[String], start: Option<f64>, container: remux::Container) -> Result<Response, StatusCode> {
+    let mut response = Response::new(Body::empty());
+    if method != axum::http::Method::HEAD {
+        let output = remux::spawn(input, start, container).map_err(|e| {
+            tracing::error!("Remux failed for {}: {}", file_path, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        *response.body_mut() = Body::from_stream(tokio_util::io::ReaderStream::new(output));
+    }
+    response.headers_mut().insert(header::CONTENT_TYPE, container.mime().parse().unwrap());
+    response.headers_mut().insert(header::ACCEPT_RANGES, "none".parse().unwrap());
+    Ok(response)
 }
 
 pub async fn stream_video(
     Path(id): Path<i64>,
+    Query(params): Query<StreamQuery>,
     State(pool): State<SqlitePool>,
     method: axum::http::Method,
     headers: HeaderMap,
 ) -> Result<impl IntoResponse, StatusCode> {
-    let result: Option<(String,)> = sqlx::query_as("SELECT file_path FROM media WHERE id = ?")
-        .bind(id)
-        .fetch_optional(&pool)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let file_path = match result {
-        Some((path,)) => path,
-        None => return Err(StatusCode::NOT_FOUND),
-    };
-
-    let mut file = File::open(&file_path).await.map_err(|_| StatusCode::NOT_FOUND)?;
-    let metadata = file.metadata().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let file_size = metadata.len();
-
-    let range = headers
-        .get(header::RANGE)
-        .and_then(|value| value.to_str().ok())
-        .and_then(|s| {
-            let s = s.strip_prefix("bytes=")?;
-            let mut parts = s.split('-');
-            let start = parts.next()?.parse::<u64>().ok()?;
-            let end = parts.next().and_then(|s| s.parse::<u64>().ok());
-            Some((start, end))
-        });
+    let (file_path, disc_type) = stream_source(&pool, id, params.version).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // Disc rips are remuxed on the fly: a single growing stream without byte ranges
+    if let Some(disc) = disc_type.as_deref().and_then(disc::DiscType::parse) {
+        let input = disc::input_args(std::path::Path::new(&file_path), disc).ok_or(StatusCode::NOT_FOUND)?;
+        return remux_response(&method, &file_path, &input, params.start, remux::Container::Matroska);
+    }
 
     let mime = match std::path::Path::new(&file_path)
         .extension()
@@ -136,44 +228,73 @@ pub async fn stream_video(
         _ => "video/mp4",
     };
 
-    if method == axum::http::Method::HEAD {
-        return Ok(Response::builder()
-            .status(StatusCode::OK)
-            .header(header::CONTENT_TYPE, mime)
-            .header(header::CONTENT_LENGTH, file_size.to_string())
-            .header(header::ACCEPT_RANGES, "bytes")
-            .body(Body::empty())
-            .unwrap());
-    }
+    let content = Content::file(std::path::Path::new(&file_path), mime).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    range::respond(&method, &headers, content).await
+}
 
-    match range {
-        Some((start, end)) => {
-            let end = end.unwrap_or(file_size - 1);
-            let length = end - start + 1;
+/// Direct stream: the video as fragmented MP4 with its video copied and only audio MP4 can't
+/// carry converted to AAC. Far cheaper than an HLS transcode; seek with `start`.
+pub async fn stream_remux(
+    Path(id): Path<i64>,
+    Query(params): Query<StreamQuery>,
+    State(pool): State<SqlitePool>,
+    method: axum::http::Method,
+) -> Result<Response, StatusCode> {
+    let (file_path, disc_type) = stream_source(&pool, id, params.version).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let path = std::path::Path::new(&file_path);
+    let input = match disc_type.as_deref().and_then(disc::DiscType::parse) {
+        Some(disc) => disc::input_args(path, disc).ok_or(StatusCode::NOT_FOUND)?,
+        None if path.is_file() => vec!["-i".to_string(), file_path.clone()],
+        None => return Err(StatusCode::NOT_FOUND),
+    };
 
-            file.seek(SeekFrom::Start(start)).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            
-            let stream = tokio_util::io::ReaderStream::new(file.take(length));
-            let body = Body::from_stream(stream);
+    // Disc rips use the streams of their main title stored at scan time
+    let target = params.version.unwrap_or(id);
+    let info = match disc_type {
+        None => media_streams::media_info_or_probe(&pool, target, path).await.unwrap_or(None),
+        Some(_) => media_streams::media_info(&pool, target).await.unwrap_or(None),
+    };
+    let audio_codec = info.and_then(|i| i.audio_codec);
+    let transcode_audio = params.transcode_audio.unwrap_or(false) || remux::mp4_needs_audio_transcode(audio_codec.as_deref());
+    remux_response(&method, &file_path, &input, params.start, remux::Container::Mp4 { transcode_audio })
+}
+
+/// Playback plan for a media item on the requesting device: direct play, remux or transcode,
+/// with the URL to play and the reason.
+pub async fn get_playback_plan(
+    Path(id): Path<i64>,
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<PlaybackRequest>,
+) -> Result<Json<PlaybackPlanDto>, AppError> {
+    let profile = match (payload.device, payload.profile) {
+        (Some(device), _) => device,
+        (None, Some(name)) => device_profile::builtin(&name)
+            .ok_or_else(|| AppError::BadRequest(format!("Unknown device profile '{}'", name)))?,
+        (None, None) => return Err(AppError::BadRequest("Either a device profile name or device capabilities are required".to_string())),
+    };
 
-            let mut response = Response::new(body);
-            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
-            response.headers_mut().insert(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, file_size).parse().unwrap());
-            response.headers_mut().insert(header::CONTENT_LENGTH, length.to_string().parse().unwrap());
-            response.headers_mut().insert(header::CONTENT_TYPE, mime.parse().unwrap());
-            response.headers_mut().insert(header::ACCEPT_RANGES, "bytes".parse().unwrap());
+    let target = payload.version.unwrap_or(id);
+    let (file_path, disc_type) = stream_source(&pool, id, payload.version).await?.ok_or_else(|| AppError::NotFound(format!("Media with id {} not found", target)))?;
 
-            Ok(response)
-        }
-        None => {
-            let body = Body::from_stream(tokio_util::io::ReaderStream::new(file));
-            let mut response = Response::new(body);
-            response.headers_mut().insert(header::CONTENT_LENGTH, file_size.to_string().parse().unwrap());
-            response.headers_mut().insert(header::CONTENT_TYPE, mime.parse().unwrap());
-            response.headers_mut().insert(header::ACCEPT_RANGES, "bytes".parse().unwrap());
-            Ok(response)
-        }
-    }
+    let disc = disc_type.as_deref().and_then(disc::DiscType::parse);
+    let info = match disc {
+        None => media_streams::media_info_or_probe(&pool, target, std::path::Path::new(&file_path)).await?,
+        // The streams of the main title, stored at scan time
+        Some(_) => media_streams::media_info(&pool, target).await?,
+    };
+    // Text subtitles are the player's to render; image ones have to be burned in
+    let burn_in = match payload.subtitle.as_deref() {
+        Some(track) => subtitles::burn_in_track(&pool, target, std::path::Path::new(&file_path), track).await?.map(|_| track),
+        None => None,
+    };
+    Ok(Json(device_profile::decide(target, &file_path, disc, info.as_ref(), &profile, burn_in)))
+}
+
+/// The built-in device profiles.
+pub async fn get_device_profiles() -> Json<Vec<DeviceProfile>> {
+    Json(device_profile::builtin_profiles())
 }
 
 #[derive(Serialize)]
@@ -181,21 +302,62 @@ pub struct SubtitleTrack {
     pub id: String,
     pub label: String,
     pub language: String,
-    pub source: String, // "url"
+    pub source: String, // "url" for sidecar files, "embedded" for tracks inside the video
+    /// WebVTT to load; empty for tracks that can only be burned in
     pub url: String,
+    /// Stream index within the video, for embedded tracks
+    pub stream_index: Option<i64>,
+    pub codec: Option<String>,
+    /// Image-based (PGS, VobSub) tracks can't be sent as text
+    pub burn_in_only: bool,
+    /// Only translates foreign dialogue and signs
+    pub forced: bool,
+    /// For the deaf and hard of hearing: describes sounds as well
+    pub sdh: bool,
+    pub default: bool,
 }
 
+/// The track of subtitle file `path` if it belongs to the video named `file_stem`: its name is
+/// the video's plus ".<language>.<flags>" (video.srt, video.en.srt, video.pt-BR.sdh.srt).
+fn sidecar_track(id: i64, path: &std::path::Path, file_stem: &str) -> Option<SubtitleTrack> {
+    let ext = path.extension()?.to_string_lossy().to_string();
+    SubtitleFormat::from_extension(&ext)?;
+    let filename = path.file_name()?.to_string_lossy().to_string();
+    let suffix = filename.strip_prefix(file_stem)?
+        .strip_suffix(ext.as_str())?
+        .strip_suffix('.')
+        .filter(|rest| rest.is_empty() || rest.starts_with('.'))?;
+    let info = subtitles::TrackInfo::from_suffix(suffix);
+    let vobsub = subtitles::is_vobsub(path);
+    // Using filename as ID for simplicity
+    Some(SubtitleTrack {
+        id: filename.clone(),
+        label: info.label(),
+        language: info.language.clone().unwrap_or_else(|| "und".to_string()),
+        source: "url".to_string(),
+        url: if vobsub { String::new() } else { format!("/api/v1/stream/{}/subtitle/{}", id, filename) },
+        stream_index: None,
+        codec: Some(ext.to_lowercase()),
+        burn_in_only: vobsub,
+        forced: info.forced,
+        sdh: info.sdh,
+        default: info.default,
+    })
+}
+
+/// `language` ("en,fr") sorts the tracks instead of the `subtitle_language` setting.
 pub async fn get_subtitles(
     Path(id): Path<i64>,
+    Query(params): Query<SubtitleListQuery>,
     State(pool): State<SqlitePool>,
 ) -> Result<Json<Vec<SubtitleTrack>>, AppError> {
-    let result: Option<(String,)> = sqlx::query_as("SELECT file_path FROM media WHERE id = ?")
+    let result: Option<(String, Option<String>)> = sqlx::query_as("SELECT file_path, disc_type FROM media WHERE id = ?")
         .bind(id)
         .fetch_optional(&pool)
         .await?;
 
-    let file_path = match result {
-        Some((path,)) => std::path::PathBuf::from(path),
+    let (file_path, disc_type) = match result {
+        Some((path, disc_type)) => (std::path::PathBuf::from(path), disc_type),
         None => return Err(AppError::NotFound("Media not found".to_string())),
     };
 
@@ -204,57 +366,74 @@ pub async fn get_subtitles(
 
     let mut subtitles = Vec::new();
 
-    if let Ok(mut read_dir) = tokio::fs::read_dir(parent_dir).await {
-        while let Ok(Some(entry)) = read_dir.next_entry().await {
-            let path = entry.path();
-            if path.is_file() {
-                if let Some(ext) = path.extension() {
-                    let ext_str = ext.to_string_lossy().to_lowercase();
-                    if ext_str == "srt" || ext_str == "vtt" {
-                        let filename = path.file_name().unwrap().to_string_lossy().to_string();
-                        // Check if it belongs to this video
-                        // Logic: 
-                        // 1. Exact match: video.srt
-                        // 2. Language match: video.en.srt, video.eng.srt
-                        
-                        if filename.starts_with(&file_stem) {
-                            // It's a match!
-                            let label = if filename == format!("{}.{}", file_stem, ext_str) {
-                                "Default".to_string()
-                            } else {
-                                // Try to extract language code/label from suffix
-                                // e.g., movie.en.srt -> en
-                                let suffix = filename.strip_prefix(&file_stem).unwrap_or("").strip_suffix(&format!(".{}", ext_str)).unwrap_or("");
-                                let clean_suffix = suffix.trim_start_matches('.').trim_end_matches('.');
-                                if clean_suffix.is_empty() {
-                                    "Unknown".to_string()
-                                } else {
-                                    clean_suffix.to_string()
-                                }
-                            };
-                            
-                            // Using filename as ID for simplicity
-                            subtitles.push(SubtitleTrack {
-                                id: filename.clone(),
-                                label,
-                                language: "en".to_string(), // Naive default, real impl would parse code
-                                source: "url".to_string(),
-                                url: format!("/api/v1/stream/{}/subtitle/{}", id, filename),
-                            });
-                        }
-                    }
+    // Sidecar files, then the ones uploaded or downloaded into the managed cache
+    for dir in [parent_dir.to_path_buf(), subtitles::managed_dir(id)] {
+        if let Ok(mut read_dir) = tokio::fs::read_dir(&dir).await {
+            while let Ok(Some(entry)) = read_dir.next_entry().await {
+                let path = entry.path();
+                if path.is_file() {
+                    subtitles.extend(sidecar_track(id, &path, &file_stem));
                 }
             }
         }
     }
 
+    // Tracks muxed into the video. Disc rips (probed through their main title at scan time)
+    // only have picture tracks, which can still be burned in.
+    let embedded = match disc_type {
+        None => subtitles::embedded_tracks(&pool, id, &file_path).await?,
+        Some(_) => media_streams::list(&pool, id).await?
+            .into_iter()
+            .filter(|s| s.stream_type == "subtitle" && !subtitles::is_text_codec(s.codec.as_deref()))
+            .collect(),
+    };
+    for stream in embedded {
+        let text = subtitles::is_text_codec(stream.codec.as_deref());
+        let info = subtitles::TrackInfo::from_stream(&stream);
+        subtitles.push(SubtitleTrack {
+            id: format!("embedded-{}", stream.stream_index),
+            label: match (&info.title, &info.language) {
+                (None, None) => format!("Track {}", stream.stream_index),
+                _ => info.label(),
+            },
+            language: info.language.clone().unwrap_or_else(|| "und".to_string()),
+            source: "embedded".to_string(),
+            url: if text { format!("/api/v1/stream/{}/subtitle/embedded/{}", id, stream.stream_index) } else { String::new() },
+            stream_index: Some(stream.stream_index),
+            codec: stream.codec,
+            burn_in_only: !text,
+            forced: info.forced,
+            sdh: info.sdh,
+            default: info.default,
+        });
+    }
+
+    // Preferred languages first, the rest by language; within a language the default track, then full subtitles
+    // before forced-only ones, then plain before SDH
+    let preferred = match params.language.as_deref() {
+        Some(value) => subtitles::parse_languages(value),
+        None => subtitles::preferred_languages(&pool).await,
+    };
+    let rank = |t: &SubtitleTrack| subtitles::preference_rank((t.language != "und").then_some(t.language.as_str()), &preferred);
+    subtitles.sort_by(|a, b| {
+        rank(a).cmp(&rank(b))
+            .then_with(|| a.language.cmp(&b.language))
+            .then(b.default.cmp(&a.default))
+            .then(a.forced.cmp(&b.forced))
+            .then(a.sdh.cmp(&b.sdh))
+            .then_with(|| a.label.cmp(&b.label))
+    });
+
     Ok(Json(subtitles))
 }
 
 pub async fn stream_subtitle(
     Path((id, filename)): Path<(i64, String)>,
+    Query(params): Query<SubtitleQuery>,
     State(pool): State<SqlitePool>,
-) -> Result<impl IntoResponse, AppError> {
+    method: axum::http::Method,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
     // 1. Get Media Path to verify security/locality
     let result: Option<(String,)> = sqlx::query_as("SELECT file_path FROM media WHERE id = ?")
         .bind(id)
@@ -267,30 +446,178 @@ pub async fn stream_subtitle(
     };
 
     let parent_dir = media_path.parent().ok_or(AppError::Internal("Invalid file path".to_string()))?;
-    let subtitle_path = parent_dir.join(&filename);
 
-    // Security check: Ensure subtitle is actually in the same directory (prevent traversal if filename has ..)
-    if !subtitle_path.starts_with(parent_dir) {
+    // Security check: Ensure subtitle is actually in the same directory (a plain file name, no "..")
+    if std::path::Path::new(&filename).file_name() != Some(std::ffi::OsStr::new(&filename)) {
          return Err(AppError::BadRequest("Invalid subtitle path".to_string()));
     }
-    
-    if !subtitle_path.exists() {
-        return Err(AppError::NotFound("Subtitle not found".to_string()));
+
+    // Next to the video, or in the managed cache
+    let subtitle_path = [parent_dir.to_path_buf(), subtitles::managed_dir(id)].into_iter()
+        .map(|dir| dir.join(&filename))
+        .find(|path| path.exists())
+        .ok_or_else(|| AppError::NotFound("Subtitle not found".to_string()))?;
+
+    let ext = subtitle_path.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+    let format = SubtitleFormat::from_extension(&ext)
+        .filter(|_| !subtitles::is_vobsub(&subtitle_path))
+        .ok_or_else(|| AppError::BadRequest(format!("{} can't be converted to text", filename)))?;
+
+    // Any charset and format in, UTF-8 WebVTT out
+    let bytes = tokio::fs::read(&subtitle_path).await.map_err(|_| AppError::Internal("Failed to read subtitle".to_string()))?;
+    let modified = tokio::fs::metadata(&subtitle_path).await.ok().and_then(|m| m.modified().ok());
+    let vtt = subtitle_format::to_webvtt(&subtitle_format::decode(&bytes), format, params.offset.unwrap_or(0.0));
+    let content = Content::bytes(vtt.into_bytes(), modified, WEBVTT_TYPE);
+    Ok(range::respond(&method, &headers, content).await.into_response())
+}
+
+/// Video file of a media item.
+async fn media_file(pool: &SqlitePool, id: i64) -> Result<std::path::PathBuf, AppError> {
+    let path: Option<String> = sqlx::query_scalar("SELECT file_path FROM media WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    path.map(std::path::PathBuf::from).ok_or_else(|| AppError::NotFound("Media not found".to_string()))
+}
+
+/// Language and flags of a subtitle being stored; an unknown language is refused.
+fn stored_track_info(language: Option<&str>, forced: bool, sdh: bool) -> Result<subtitles::TrackInfo, AppError> {
+    let language = match language.map(str::trim).filter(|l| !l.is_empty()) {
+        Some(code) => Some(crate::core::language::normalize(code)
+            .ok_or_else(|| AppError::BadRequest(format!("Unknown language: {}", code)))?),
+        None => None,
+    };
+    Ok(subtitles::TrackInfo { language, forced, sdh, ..Default::default() })
+}
+
+/// "media" (next to the video) or "cache" (the managed cache).
+fn is_managed_location(location: Option<&str>) -> Result<bool, AppError> {
+    match location.unwrap_or("media") {
+        "media" => Ok(false),
+        "cache" => Ok(true),
+        other => Err(AppError::BadRequest(format!("Unknown subtitle location: {}", other))),
     }
+}
 
-    let content = tokio::fs::read_to_string(&subtitle_path).await.map_err(|_| AppError::Internal("Failed to read subtitle".to_string()))?;
-    
-    // Simple MIME type detection based on extension
-    let mime = if filename.ends_with(".vtt") {
-        "text/vtt"
-    } else {
-        "application/x-subrip" // for .srt
+async fn store_subtitle(id: i64, file_path: &std::path::Path, data: &[u8], format: &str, info: &subtitles::TrackInfo, managed: bool) -> Result<SubtitleTrack, AppError> {
+    let stored = subtitles::store(id, file_path, data, format, info, managed).await?;
+    tracing::info!("Stored subtitle {}", stored.display());
+    let file_stem = file_path.file_stem().ok_or(AppError::Internal("Invalid filename".to_string()))?.to_string_lossy().to_string();
+    sidecar_track(id, &stored, &file_stem).ok_or_else(|| AppError::Internal("Stored subtitle is not listed".to_string()))
+}
+
+/// Upload a subtitle file (multipart `file`) with `language`, `forced`, `sdh` and `location`
+/// fields. It is stored like a sidecar file so it lists and streams like one.
+pub async fn upload_subtitle(
+    Path(id): Path<i64>,
+    State(pool): State<SqlitePool>,
+    mut multipart: axum::extract::Multipart,
+) -> Result<Json<SubtitleTrack>, AppError> {
+    let file_path = media_file(&pool, id).await?;
+
+    let mut file = None;
+    let mut language = None;
+    let mut forced = false;
+    let mut sdh = false;
+    let mut location = None;
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| AppError::BadRequest(e.to_string()))? {
+        let name = field.name().unwrap_or("").to_string();
+        match name.as_str() {
+            "file" => {
+                let format = field.file_name()
+                    .and_then(|f| std::path::Path::new(f).extension())
+                    .map(|e| e.to_string_lossy().to_string())
+                    .ok_or_else(|| AppError::BadRequest("Subtitle file has no extension".to_string()))?;
+                let bytes = field.bytes().await.map_err(|e| AppError::BadRequest(e.to_string()))?;
+                file = Some((bytes, format));
+            },
+            "language" => language = field.text().await.ok(),
+            "forced" => forced = field.text().await.is_ok_and(|v| v == "true" || v == "1"),
+            "sdh" => sdh = field.text().await.is_ok_and(|v| v == "true" || v == "1"),
+            "location" => location = field.text().await.ok(),
+            _ => {
+                // Ignore unknown fields
+                let _ = field.bytes().await;
+            }
+        }
+    }
+
+    let (data, format) = file.ok_or_else(|| AppError::BadRequest("No subtitle file uploaded".to_string()))?;
+    let info = stored_track_info(language.as_deref(), forced, sdh)?;
+    let managed = is_managed_location(location.as_deref())?;
+    Ok(Json(store_subtitle(id, &file_path, &data, &format, &info, managed).await?))
+}
+
+/// Search a subtitle provider for subtitles of a media item, by file hash and provider IDs.
+/// Subtitles made for this exact file come first, then the most downloaded.
+pub async fn search_subtitles(
+    Path(id): Path<i64>,
+    Query(params): Query<SubtitleSearchQuery>,
+    State(pool): State<SqlitePool>,
+) -> Result<Json<Vec<SubtitleCandidate>>, AppError> {
+    let languages = match params.language.as_deref() {
+        Some(value) => subtitles::parse_languages(value),
+        None => subtitles::preferred_languages(&pool).await,
     };
+    let query = subtitles::search_query(&pool, id, languages).await?;
+    let provider = subtitles::get_provider(&pool, params.provider.as_deref()).await?;
 
-    let mut headers = HeaderMap::new();
-    headers.insert(header::CONTENT_TYPE, mime.parse().unwrap());
-    
-    Ok((headers, content))
+    let mut candidates = provider.search(&query).await?;
+    candidates.sort_by(|a, b| b.hash_match.cmp(&a.hash_match).then(b.downloads.cmp(&a.downloads)));
+    Ok(Json(candidates))
+}
+
+/// Download a subtitle from a provider and store it with the media.
+pub async fn download_subtitle(
+    Path(id): Path<i64>,
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<DownloadSubtitleRequest>,
+) -> Result<Json<SubtitleTrack>, AppError> {
+    let file_path = media_file(&pool, id).await?;
+    let info = stored_track_info(payload.language.as_deref(), payload.forced, payload.sdh)?;
+    let managed = is_managed_location(payload.location.as_deref())?;
+
+    let provider = subtitles::get_provider(&pool, payload.provider.as_deref()).await?;
+    let file = provider.download(&payload.id).await?;
+    Ok(Json(store_subtitle(id, &file_path, &file.data, &file.format, &info, managed).await?))
+}
+
+/// An embedded text subtitle track as WebVTT, extracted on first request and cached.
+pub async fn stream_embedded_subtitle(
+    Path((id, stream_index)): Path<(i64, i64)>,
+    Query(params): Query<SubtitleQuery>,
+    State(pool): State<SqlitePool>,
+    method: axum::http::Method,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let file_path: Option<String> = sqlx::query_scalar("SELECT file_path FROM media WHERE id = ? AND disc_type IS NULL")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?;
+    let file_path = std::path::PathBuf::from(file_path.ok_or_else(|| AppError::NotFound("Media not found".to_string()))?);
+
+    let stream = subtitles::embedded_tracks(&pool, id, &file_path).await?
+        .into_iter()
+        .find(|s| s.stream_index == stream_index)
+        .ok_or_else(|| AppError::NotFound(format!("Media {} has no subtitle stream {}", id, stream_index)))?;
+    if !subtitles::is_text_codec(stream.codec.as_deref()) {
+        return Err(AppError::BadRequest(format!(
+            "Subtitle stream {} is {} images and can only be burned in",
+            stream_index, stream.codec.as_deref().unwrap_or("unknown")
+        )));
+    }
+
+    let vtt = subtitles::extract_webvtt(id, &file_path, stream_index).await?;
+    let content = match params.offset.filter(|o| *o != 0.0) {
+        None => Content::file(&vtt, WEBVTT_TYPE).await.map_err(|_| AppError::Internal("Failed to read subtitle".to_string()))?,
+        Some(offset) => {
+            let text = tokio::fs::read_to_string(&vtt).await.map_err(|_| AppError::Internal("Failed to read subtitle".to_string()))?;
+            let modified = tokio::fs::metadata(&vtt).await.ok().and_then(|m| m.modified().ok());
+            Content::bytes(subtitle_format::to_webvtt(&text, SubtitleFormat::WebVtt, offset).into_bytes(), modified, WEBVTT_TYPE)
+        }
+    };
+    Ok(range::respond(&method, &headers, content).await.into_response())
 }
 
 pub async fn get_thumbnail(
@@ -330,45 +657,39 @@ pub async fn get_thumbnail(
         let mut generated = false;
 
         // Try poster first, then backdrop
-        for url_opt in [poster_url, backdrop_url] {
-            if let Some(url) = url_opt {
-                if !url.is_empty() {
-                    // Start download
-                    match reqwest::get(&url).await {
-                        Ok(resp) => {
-                            if resp.status().is_success() {
-                                match resp.bytes().await {
-                                    Ok(bytes) => {
-                                        // Save to thumbnail path
-                                        if let Ok(_) = tokio::fs::write(&thumb_path, &bytes).await {
-                                            tracing::info!("Downloaded thumbnail for {} from {}", id, url);
-                                            generated = true;
-                                            break;
-                                        }
-                                    },
-                                    Err(e) => tracing::warn!("Failed to get bytes for {} from {}: {}", id, url, e)
-                                }
+        for url in [poster_url, backdrop_url].into_iter().flatten() {
+            if !url.is_empty() {
+                // Start download
+                match reqwest::get(&url).await {
+                    Ok(resp) => {
+                        if resp.status().is_success() {
+                            match resp.bytes().await {
+                                Ok(bytes) => {
+                                    // Save to thumbnail path
+                                    if tokio::fs::write(&thumb_path, &bytes).await.is_ok() {
+                                        tracing::info!("Downloaded thumbnail for {} from {}", id, url);
+                                        generated = true;
+                                        break;
+                                    }
+                                },
+                                Err(e) => tracing::warn!("Failed to get bytes for {} from {}: {}", id, url, e)
                             }
-                        },
-                        Err(e) => tracing::warn!("Failed to download thumbnail for {} from {}: {}", id, url, e)
-                    }
+                        }
+                    },
+                    Err(e) => tracing::warn!("Failed to download thumbnail for {} from {}: {}", id, url, e)
                 }
             }
         }
 
         // 4. Fallback to FFmpeg if needed (skip for books)
         if !generated && !is_book {
-             // Find FFmpeg - check common locations first
-            let ffmpeg_paths = [
-                "C:\\ffmpeg\\bin\\ffmpeg.exe",  // Common Windows install
-                "./ffmpeg/ffmpeg.exe",           // Bundled with server (Windows)
-                "./ffmpeg/ffmpeg",               // Bundled with server (Linux/Mac)
-                "ffmpeg",                        // System PATH
-            ];
-            
-            let ffmpeg_cmd = ffmpeg_paths.iter()
-                .find(|p| std::path::Path::new(p).exists() || *p == &"ffmpeg")
-                .unwrap_or(&"ffmpeg");
+            let ffmpeg_cmd = remux::ffmpeg_command();
+            // Disc rips: take the frame from the main title's first file
+            let disc_path = std::path::Path::new(&file_path);
+            let frame_source = disc::detect(disc_path)
+                .and_then(|d| disc::main_title(disc_path, d))
+                .and_then(|t| t.files.into_iter().next())
+                .unwrap_or_else(|| disc_path.to_path_buf());
             
             tracing::info!("Generating thumbnail for {} using FFmpeg", id);
             
@@ -378,7 +699,7 @@ pub async fn get_thumbnail(
                 .arg("-ss")
                 .arg("00:00:05.000")
                 .arg("-i")
-                .arg(&file_path)
+                .arg(&frame_source)
                 .arg("-vframes")
                 .arg("1")
                 .arg("-vf")
@@ -126,7 +126,7 @@ pub async fn add_items_to_list(
     .fetch_optional(&pool)
     .await?;
     
-    let mut current_pos = max_pos.and_then(|r| Some(r.0)).unwrap_or(0);
+    let mut current_pos = max_pos.map(|r| r.0).unwrap_or(0);
     
     for media_id in payload.media_ids {
         current_pos += 1;
@@ -37,6 +37,8 @@ pub async fn update_setting(
 pub async fn reset_database(State(pool): State<SqlitePool>) -> Result<StatusCode, AppError> {
     let mut tx = pool.begin().await?;
     sqlx::query("DELETE FROM playback_progress").execute(&mut *tx).await?;
+    sqlx::query("DELETE FROM metadata_retry_queue").execute(&mut *tx).await?;
+    sqlx::query("DELETE FROM media_streams").execute(&mut *tx).await?;
     sqlx::query("DELETE FROM media").execute(&mut *tx).await?;
     sqlx::query("DELETE FROM libraries").execute(&mut *tx).await?;
     tx.commit().await?;
@@ -0,0 +1,40 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use sqlx::SqlitePool;
+use crate::api::range::{self, Content};
+use crate::core::trickplay;
+use crate::error::AppError;
+
+/// Seek-preview thumbnails: `index.vtt` (a WebVTT thumbnail track), `index.json`, or one of
+/// the sprite sheets they point at. While the sheets are being generated the index answers
+/// 202 Accepted; for a video that gets none, or whose generation failed, it is an error.
+pub async fn get_trickplay(
+    Path((id, file)): Path<(i64, String)>,
+    State(pool): State<SqlitePool>,
+    method: Method,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    if let Some(sheet) = trickplay::sheet_path(id, &file) {
+        let content = Content::file(&sheet, "image/jpeg").await
+            .map_err(|_| AppError::NotFound(format!("Trickplay sheet {} not found", file)))?;
+        return Ok(range::respond(&method, &headers, content).await.into_response());
+    }
+    if file != "index.vtt" && file != "index.json" {
+        return Err(AppError::NotFound(format!("Trickplay file {} not found", file)));
+    }
+
+    let Some(manifest) = trickplay::manifest_or_queue(&pool, id).await? else {
+        return Ok((StatusCode::ACCEPTED, [(header::RETRY_AFTER, "30")], "Trickplay is being generated").into_response());
+    };
+    Ok(match file.as_str() {
+        "index.vtt" => (
+            [(header::CONTENT_TYPE, "text/vtt; charset=utf-8")],
+            trickplay::index_webvtt(id, &manifest),
+        ).into_response(),
+        _ => Json(trickplay::index_json(id, &manifest)).into_response(),
+    })
+}
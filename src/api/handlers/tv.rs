@@ -4,18 +4,24 @@ use axum::{
 };
 use sqlx::SqlitePool;
 use crate::error::AppError;
-use crate::core::media_service;
+use crate::core::{extras, media_service};
 
 use crate::dtos::requests::IdentifyRequest;
+use crate::models::media::ExtraDto;
 use crate::models::tv::{SeriesDto, SeasonDto, EpisodeDto, SeriesDetailDto};
 
+/// (id, title, episode_number, still_url, file_path, plot)
+type EpisodeRow = (i64, Option<String>, Option<i32>, Option<String>, String, Option<String>);
+/// (poster_url, backdrop_url, plot, year, genres)
+type SeriesInfoRow = (Option<String>, Option<String>, Option<String>, Option<i64>, Option<String>);
+
 pub async fn get_all_series(State(pool): State<SqlitePool>) -> Result<Json<Vec<SeriesDto>>, AppError> {
     let series_rows: Vec<(String, i32, Option<String>)> = sqlx::query_as(
         "SELECT media.series_name, COUNT(DISTINCT media.season_number) as season_count, 
                 (SELECT poster_url FROM media m2 JOIN libraries l2 ON m2.library_id = l2.id WHERE m2.series_name = media.series_name AND m2.poster_url IS NOT NULL AND l2.library_type = 'tv_shows' LIMIT 1)
          FROM media 
          JOIN libraries l ON media.library_id = l.id
-         WHERE media.series_name IS NOT NULL AND l.library_type = 'tv_shows'
+         WHERE media.series_name IS NOT NULL AND media.extra_type IS NULL AND l.library_type = 'tv_shows'
          GROUP BY media.series_name 
          ORDER BY media.series_name ASC"
     )
@@ -76,7 +82,7 @@ pub async fn get_season_episodes(
         .unwrap_or(std::borrow::Cow::Borrowed(&encoded_name))
         .into_owned();
 
-    let episode_rows: Vec<(i64, Option<String>, Option<i32>, Option<String>, String, Option<String>)> = sqlx::query_as(
+    let episode_rows: Vec<EpisodeRow> = sqlx::query_as(
         "SELECT media.id, media.title, media.episode_number, media.still_url, media.file_path, media.plot 
         FROM media 
         JOIN libraries l ON media.library_id = l.id
@@ -103,6 +109,17 @@ pub async fn get_season_episodes(
     Ok(Json(episodes))
 }
 
+/// Trailers, featurettes and other extras found in a series' folders.
+pub async fn get_series_extras(
+    Path(encoded_name): Path<String>,
+    State(pool): State<SqlitePool>,
+) -> Result<Json<Vec<ExtraDto>>, AppError> {
+    let series_name = urlencoding::decode(&encoded_name)
+        .unwrap_or(std::borrow::Cow::Borrowed(&encoded_name))
+        .into_owned();
+
+    Ok(Json(extras::list_for_series(&pool, &series_name).await?))
+}
 
 pub async fn get_series_detail(
     Path(encoded_name): Path<String>,
@@ -114,7 +131,7 @@ pub async fn get_series_detail(
     
     tracing::info!("Fetching details for series: '{}'", series_name);
 
-    let series_info: Option<(Option<String>, Option<String>, Option<String>, Option<i64>, Option<String>)> = sqlx::query_as(
+    let series_info: Option<SeriesInfoRow> = sqlx::query_as(
         "SELECT media.poster_url, media.backdrop_url, media.plot, media.year, media.genres 
          FROM media 
          JOIN libraries l ON media.library_id = l.id
@@ -165,37 +182,31 @@ pub async fn refresh_series_metadata(
     Path(encoded_name): Path<String>,
     State(pool): State<SqlitePool>,
 ) -> Result<Json<SeriesDetailDto>, AppError> {
-    use crate::core::metadata::{fetch_metadata, fetch_episodes, get_default_provider};
+    use crate::core::metadata::{fetch_metadata, fetch_episodes};
+    use crate::providers::nfo;
     
     let series_name = urlencoding::decode(&encoded_name)
         .unwrap_or(std::borrow::Cow::Borrowed(&encoded_name))
         .into_owned();
 
-    let meta = fetch_metadata(&series_name, Some("series"), &pool).await
+    let library_id = media_service::get_series_library_id(&pool, &series_name).await?;
+    let series_dir = media_service::get_series_dir(&pool, &series_name).await?;
+    let local = series_dir.as_deref().and_then(nfo::series_nfo);
+    let meta = fetch_metadata(&series_name, Some("series"), library_id, local, &pool).await
         .map_err(|e| AppError::External(format!("Failed to fetch metadata: {}", e)))?;
 
     media_service::update_series_metadata(&pool, &series_name, &meta).await?;
+    if let Some(dir) = &series_dir {
+        media_service::apply_local_series_artwork(&pool, &series_name, dir).await?;
+    }
     
-    // Get the provider name to look up the correct ID
-    let provider_name = get_default_provider(&pool).await;
-    let provider_id = meta.provider_ids.as_ref()
-        .and_then(|ids| ids.get(&provider_name))
-        .and_then(|v| {
-            // Handle both string and number types
-            if let Some(s) = v.as_str() {
-                Some(s.to_string())
-            } else if let Some(i) = v.as_i64() {
-                Some(i.to_string())
-            } else {
-                None
-            }
-        });
-
-    if let Some(id_str) = provider_id {
+    // Episodes come from every provider in the chain that matched the series
+    if let Some(provider_ids) = meta.provider_ids.as_ref() {
+        media_service::resolve_episode_numbers(&pool, &series_name, provider_ids).await?;
         let seasons = media_service::get_series_seasons(&pool, &series_name).await?;
         
         for season_num in seasons {
-            if let Ok(episodes) = fetch_episodes(&id_str, season_num, &pool).await {
+            if let Ok(episodes) = fetch_episodes(provider_ids, season_num, library_id, &pool).await {
                 for ep in episodes {
                     let still_url = ep.still_path.clone();
                     let _ = media_service::update_episode_details(
@@ -211,6 +222,7 @@ pub async fn refresh_series_metadata(
             }
         }
     }
+    media_service::apply_episode_nfos(&pool, &series_name).await?;
     
     get_series_detail(Path(encoded_name), State(pool)).await
 }
@@ -221,7 +233,7 @@ pub async fn identify_series(
     Path(encoded_name): Path<String>,
     Json(payload): Json<IdentifyRequest>,
 ) -> Result<Json<SeriesDetailDto>, AppError> {
-    use crate::core::metadata::{fetch_by_id, fetch_episodes};
+    use crate::core::metadata::{fetch_by_id, fetch_episodes, get_default_provider};
 
     let series_name = urlencoding::decode(&encoded_name)
         .unwrap_or(std::borrow::Cow::Borrowed(&encoded_name))
@@ -234,11 +246,14 @@ pub async fn identify_series(
     media_service::update_series_metadata(&pool, &series_name, &meta).await?;
     
     // Use the provider ID from the payload directly
-    let provider_id = payload.provider_id;
+    let provider_name = get_default_provider(&pool).await;
+    let provider_ids = serde_json::json!({ provider_name: payload.provider_id });
+    let library_id = media_service::get_series_library_id(&pool, &series_name).await?;
+    media_service::resolve_episode_numbers(&pool, &series_name, &provider_ids).await?;
     let seasons = media_service::get_series_seasons(&pool, &series_name).await?;
 
     for season_num in seasons {
-        if let Ok(episodes) = fetch_episodes(&provider_id, season_num, &pool).await {
+        if let Ok(episodes) = fetch_episodes(&provider_ids, season_num, library_id, &pool).await {
             for ep in episodes {
                 let still_url = ep.still_path.clone();
                 let _ = media_service::update_episode_details(
@@ -256,3 +271,45 @@ pub async fn identify_series(
 
     get_series_detail(Path(encoded_name), State(pool)).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn refresh_series_metadata_fills_the_series_and_its_episodes_from_the_offline_catalog() {
+        let dir = std::env::temp_dir().join(format!("vortex-refresh-series-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let pool = crate::db::connect(&format!("sqlite:{}", dir.join("test.db").display())).await;
+        let catalog = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/offline_catalog");
+        sqlx::query("INSERT INTO settings (key, value) VALUES ('metadata_provider', 'offline'), ('offline_catalog_path', ?)")
+            .bind(catalog.to_string_lossy()).execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO libraries (name, path, library_type) VALUES ('Shows', ?, 'tv_shows')")
+            .bind(dir.join("tv").to_string_lossy()).execute(&pool).await.unwrap();
+        for (season, episode) in [(1, 1), (1, 2), (2, 1)] {
+            let file = dir.join(format!("tv/Breaking Bad/Season {}/Breaking.Bad.S{:02}E{:02}.mkv", season, season, episode));
+            sqlx::query("INSERT INTO media (file_path, title, library_id, media_type, series_name, season_number, episode_number) VALUES (?, ?, 1, 'episode', 'Breaking Bad', ?, ?)")
+                .bind(file.to_string_lossy()).bind(format!("Episode {}", episode)).bind(season).bind(episode)
+                .execute(&pool).await.unwrap();
+        }
+
+        let Json(series) = refresh_series_metadata(Path("Breaking%20Bad".to_string()), State(pool.clone())).await.unwrap();
+        assert_eq!(series.name, "Breaking Bad");
+        assert_eq!(series.year, Some(2008));
+        assert_eq!(series.poster_url.as_deref(), Some("https://images.example.com/breaking-bad.jpg"));
+        assert_eq!(series.genres.as_deref(), Some("Drama, Crime"));
+        let seasons: Vec<(i32, i32)> = series.seasons.iter().map(|s| (s.season_number, s.episode_count)).collect();
+        assert_eq!(seasons, [(1, 2), (2, 1)]);
+
+        let episodes: Vec<(String, Option<String>)> = sqlx::query_as("SELECT title, plot FROM media ORDER BY season_number, episode_number")
+            .fetch_all(&pool).await.unwrap();
+        let titles: Vec<&str> = episodes.iter().map(|(title, _)| title.as_str()).collect();
+        assert_eq!(titles, ["Pilot", "Cat's in the Bag...", "Seven Thirty-Seven"]);
+        assert_eq!(episodes[1].1.as_deref(), Some("Walt and Jesse clean up."));
+
+        pool.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,381 @@
+//! Byte ranges and conditional requests (RFC 7232/7233) for responses that serve a file or a
+//! blob: videos, subtitles and book pages.
+//!
+//! Every response carries `ETag`, `Last-Modified` and `Accept-Ranges`. `If-None-Match` and
+//! `If-Modified-Since` give 304, `If-Match` and `If-Unmodified-Since` give 412. A `Range` is
+//! honored unless an `If-Range` validator no longer matches; several ranges are sent as
+//! `multipart/byteranges`, and a range that lies wholly outside the content gives 416.
+
+use axum::body::Body;
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::response::Response;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+
+/// More ranges than this in one request are ignored and the whole content is sent
+const MAX_RANGES: usize = 32;
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+enum Source {
+    File(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+/// Content to serve, with the validators clients use to revalidate it.
+pub struct Content {
+    source: Source,
+    size: u64,
+    etag: String,
+    /// Whole seconds since the epoch, the precision of an HTTP date
+    last_modified: Option<i64>,
+    content_type: String,
+}
+
+impl Content {
+    /// A file on disk. Its ETag is derived from its size and modification time.
+    pub async fn file(path: &Path, content_type: &str) -> std::io::Result<Content> {
+        let metadata = tokio::fs::metadata(path).await?;
+        let modified = metadata.modified().ok();
+        let nanos = modified.and_then(|m| m.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_nanos());
+        Ok(Content {
+            source: Source::File(path.to_path_buf()),
+            size: metadata.len(),
+            etag: format!("\"{:x}-{:x}\"", metadata.len(), nanos),
+            last_modified: modified.and_then(unix_seconds),
+            content_type: content_type.to_string(),
+        })
+    }
+
+    /// Bytes generated or extracted in memory (a converted subtitle, a page from an archive).
+    /// `modified` is the time of the file they came from.
+    pub fn bytes(data: Vec<u8>, modified: Option<SystemTime>, content_type: &str) -> Content {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        Content {
+            size: data.len() as u64,
+            etag: format!("\"{:x}-{:x}\"", data.len(), hasher.finish()),
+            source: Source::Bytes(data),
+            last_modified: modified.and_then(unix_seconds),
+            content_type: content_type.to_string(),
+        }
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> Option<i64> {
+    time.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs() as i64)
+}
+
+fn format_http_date(seconds: i64) -> Option<String> {
+    DateTime::<Utc>::from_timestamp(seconds, 0).map(|d| d.format(HTTP_DATE).to_string())
+}
+
+fn parse_http_date(value: &str) -> Option<i64> {
+    NaiveDateTime::parse_from_str(value.trim(), HTTP_DATE).ok().map(|d| d.and_utc().timestamp())
+}
+
+/// Whether an `If-Match`/`If-None-Match` list names `etag`. Weak comparison unless `strong`.
+fn etag_matches(list: &str, etag: &str, strong: bool) -> bool {
+    list.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || match candidate.strip_prefix("W/") {
+            Some(weak) => !strong && weak == etag,
+            None => candidate == etag,
+        }
+    })
+}
+
+/// The satisfiable ranges of a `Range` header as inclusive (start, end) pairs, sorted and merged.
+/// `None` means the header is to be ignored (not bytes, malformed or too many ranges);
+/// `Some(empty)` means no range overlaps the content.
+fn parse_ranges(value: &str, size: u64) -> Option<Vec<(u64, u64)>> {
+    let specs = value.trim().strip_prefix("bytes=")?;
+    let mut ranges = Vec::new();
+    let mut count = 0;
+
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        count += 1;
+        let (start, end) = spec.split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+        let range = if start.is_empty() {
+            // Suffix range: the last `n` bytes
+            let n: u64 = end.parse().ok()?;
+            (n > 0 && size > 0).then(|| (size.saturating_sub(n), size - 1))
+        } else {
+            let start: u64 = start.parse().ok()?;
+            let end: Option<u64> = if end.is_empty() { None } else { Some(end.parse().ok()?) };
+            if end.is_some_and(|e| e < start) {
+                return None;
+            }
+            (start < size).then(|| (start, end.map_or(size - 1, |e| e.min(size - 1))))
+        };
+        ranges.extend(range);
+    }
+    if count == 0 || count > MAX_RANGES {
+        return None;
+    }
+
+    ranges.sort();
+    let mut merged: Vec<(u64, u64)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    Some(merged)
+}
+
+fn empty_response(status: StatusCode) -> Response {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+/// Answer a GET or HEAD request for `content`, honoring its range and conditional headers.
+pub async fn respond(method: &Method, headers: &HeaderMap, content: Content) -> Result<Response, StatusCode> {
+    let last_modified = content.last_modified.and_then(format_http_date);
+
+    let mut response = evaluate(method, headers, &content).await?;
+    let response_headers = response.headers_mut();
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Ok(etag) = HeaderValue::from_str(&content.etag) {
+        response_headers.insert(header::ETAG, etag);
+    }
+    if let Some(date) = last_modified.and_then(|d| HeaderValue::from_str(&d).ok()) {
+        response_headers.insert(header::LAST_MODIFIED, date);
+    }
+    Ok(response)
+}
+
+async fn evaluate(method: &Method, headers: &HeaderMap, content: &Content) -> Result<Response, StatusCode> {
+    let header_str = |name: header::HeaderName| headers.get(name).and_then(|v| v.to_str().ok());
+
+    // Preconditions, in the order RFC 7232 section 6 evaluates them
+    if let Some(list) = header_str(header::IF_MATCH) {
+        if !etag_matches(list, &content.etag, true) {
+            return Ok(empty_response(StatusCode::PRECONDITION_FAILED));
+        }
+    } else if let (Some(since), Some(modified)) = (header_str(header::IF_UNMODIFIED_SINCE).and_then(parse_http_date), content.last_modified) {
+        if modified > since {
+            return Ok(empty_response(StatusCode::PRECONDITION_FAILED));
+        }
+    }
+    if let Some(list) = header_str(header::IF_NONE_MATCH) {
+        if etag_matches(list, &content.etag, false) {
+            return Ok(empty_response(StatusCode::NOT_MODIFIED));
+        }
+    } else if let (Some(since), Some(modified)) = (header_str(header::IF_MODIFIED_SINCE).and_then(parse_http_date), content.last_modified) {
+        if modified <= since {
+            return Ok(empty_response(StatusCode::NOT_MODIFIED));
+        }
+    }
+
+    // If-Range: a stale validator means the client gets the whole, current content instead
+    let range_applies = match header_str(header::IF_RANGE) {
+        None => true,
+        Some(value) if value.trim().starts_with('"') => value.trim() == content.etag,
+        Some(value) => parse_http_date(value).is_some_and(|date| content.last_modified == Some(date)),
+    };
+    let ranges = header_str(header::RANGE)
+        .filter(|_| range_applies)
+        .and_then(|value| parse_ranges(value, content.size));
+
+    let head = method == Method::HEAD;
+    match ranges.as_deref() {
+        None => full(content, head).await,
+        Some([]) => {
+            let mut response = empty_response(StatusCode::RANGE_NOT_SATISFIABLE);
+            response.headers_mut().insert(header::CONTENT_RANGE, header_value(&format!("bytes */{}", content.size))?);
+            Ok(response)
+        }
+        Some([range]) => single(content, *range, head).await,
+        Some(ranges) => multipart(content, ranges, head).await,
+    }
+}
+
+fn header_value(value: &str) -> Result<HeaderValue, StatusCode> {
+    HeaderValue::from_str(value).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// A reader over bytes `start..=end` of the content.
+async fn read_range(content: &Content, start: u64, end: u64) -> Result<Box<dyn AsyncRead + Send + Unpin>, StatusCode> {
+    let length = end + 1 - start;
+    match &content.source {
+        Source::File(path) => {
+            let mut file = tokio::fs::File::open(path).await.map_err(|_| StatusCode::NOT_FOUND)?;
+            file.seek(SeekFrom::Start(start)).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            Ok(Box::new(file.take(length)))
+        }
+        Source::Bytes(data) => Ok(Box::new(Cursor::new(data[start as usize..=end as usize].to_vec()))),
+    }
+}
+
+fn build(status: StatusCode, content_type: &str, length: u64, reader: Option<Box<dyn AsyncRead + Send + Unpin>>) -> Result<Response, StatusCode> {
+    let body = match reader {
+        Some(reader) => Body::from_stream(tokio_util::io::ReaderStream::new(reader)),
+        None => Body::empty(),
+    };
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    response.headers_mut().insert(header::CONTENT_TYPE, header_value(content_type)?);
+    response.headers_mut().insert(header::CONTENT_LENGTH, header_value(&length.to_string())?);
+    Ok(response)
+}
+
+async fn full(content: &Content, head: bool) -> Result<Response, StatusCode> {
+    let reader = if head || content.size == 0 { None } else { Some(read_range(content, 0, content.size - 1).await?) };
+    build(StatusCode::OK, &content.content_type, content.size, reader)
+}
+
+async fn single(content: &Content, (start, end): (u64, u64), head: bool) -> Result<Response, StatusCode> {
+    let reader = if head { None } else { Some(read_range(content, start, end).await?) };
+    let mut response = build(StatusCode::PARTIAL_CONTENT, &content.content_type, end + 1 - start, reader)?;
+    response.headers_mut().insert(header::CONTENT_RANGE, header_value(&format!("bytes {}-{}/{}", start, end, content.size))?);
+    Ok(response)
+}
+
+/// `multipart/byteranges`: each range with its own Content-Type and Content-Range headers.
+async fn multipart(content: &Content, ranges: &[(u64, u64)], head: bool) -> Result<Response, StatusCode> {
+    let mut hasher = DefaultHasher::new();
+    (&content.etag, ranges).hash(&mut hasher);
+    let boundary = format!("vortex-{:016x}", hasher.finish());
+
+    let part_header = |(start, end): (u64, u64)| format!(
+        "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+        boundary, content.content_type, start, end, content.size
+    );
+    let closing = format!("\r\n--{}--\r\n", boundary);
+    let length = ranges.iter().map(|&r| part_header(r).len() as u64 + r.1 + 1 - r.0).sum::<u64>() + closing.len() as u64;
+
+    let reader = if head {
+        None
+    } else {
+        let mut reader: Box<dyn AsyncRead + Send + Unpin> = Box::new(Cursor::new(Vec::new()));
+        for &range in ranges {
+            let part = read_range(content, range.0, range.1).await?;
+            reader = Box::new(reader.chain(Cursor::new(part_header(range).into_bytes())).chain(part));
+        }
+        Some(Box::new(reader.chain(Cursor::new(closing.into_bytes()))) as Box<dyn AsyncRead + Send + Unpin>)
+    };
+    build(StatusCode::PARTIAL_CONTENT, &format!("multipart/byteranges; boundary={}", boundary), length, reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODIFIED: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+
+    fn content(data: &[u8]) -> Content {
+        let modified = UNIX_EPOCH + std::time::Duration::from_secs(parse_http_date(MODIFIED).unwrap() as u64);
+        Content::bytes(data.to_vec(), Some(modified), "text/plain")
+    }
+
+    async fn get(content: &Content, headers: &[(header::HeaderName, &str)]) -> (StatusCode, HeaderMap, Vec<u8>) {
+        let headers: HeaderMap = headers.iter().map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap())).collect();
+        let response = evaluate(&Method::GET, &headers, content).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        (parts.status, parts.headers, body.to_vec())
+    }
+
+    #[test]
+    fn parse_ranges_handles_suffixes_lists_and_unsatisfiable_ranges() {
+        assert_eq!(parse_ranges("bytes=0-99", 1000), Some(vec![(0, 99)]));
+        assert_eq!(parse_ranges("bytes=900-", 1000), Some(vec![(900, 999)]));
+        assert_eq!(parse_ranges("bytes=990-2000", 1000), Some(vec![(990, 999)]));
+        // Suffix ranges, including one longer than the content
+        assert_eq!(parse_ranges("bytes=-100", 1000), Some(vec![(900, 999)]));
+        assert_eq!(parse_ranges("bytes=-5000", 1000), Some(vec![(0, 999)]));
+        assert_eq!(parse_ranges("bytes=-0", 1000), Some(vec![]));
+        // Sorted, overlapping and adjacent ranges merged, unsatisfiable ones dropped
+        assert_eq!(parse_ranges("bytes=500-599, 0-9, 5-20, 21-30, 2000-", 1000), Some(vec![(0, 30), (500, 599)]));
+        assert_eq!(parse_ranges("bytes=1000-", 1000), Some(vec![]));
+
+        // Nothing to take a range of in an empty file
+        assert_eq!(parse_ranges("bytes=0-", 0), Some(vec![]));
+        assert_eq!(parse_ranges("bytes=-10", 0), Some(vec![]));
+
+        assert_eq!(parse_ranges("items=0-9", 1000), None);
+        assert_eq!(parse_ranges("bytes=9-0", 1000), None);
+        assert_eq!(parse_ranges("bytes=a-b", 1000), None);
+        assert_eq!(parse_ranges("bytes=", 1000), None);
+        let too_many = (0..=MAX_RANGES).map(|i| format!("{}-{}", i * 10, i * 10)).collect::<Vec<_>>().join(",");
+        assert_eq!(parse_ranges(&format!("bytes={}", too_many), 1000), None);
+    }
+
+    #[tokio::test]
+    async fn evaluate_serves_ranges_and_refuses_unsatisfiable_ones() {
+        let content = content(b"0123456789");
+
+        let (status, headers, body) = get(&content, &[]).await;
+        assert_eq!((status, body.as_slice()), (StatusCode::OK, &b"0123456789"[..]));
+        assert_eq!(headers[header::CONTENT_LENGTH], "10");
+
+        let (status, headers, body) = get(&content, &[(header::RANGE, "bytes=-3")]).await;
+        assert_eq!((status, body.as_slice()), (StatusCode::PARTIAL_CONTENT, &b"789"[..]));
+        assert_eq!(headers[header::CONTENT_RANGE], "bytes 7-9/10");
+        assert_eq!(headers[header::CONTENT_LENGTH], "3");
+
+        let (status, headers, body) = get(&content, &[(header::RANGE, "bytes=20-")]).await;
+        assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(headers[header::CONTENT_RANGE], "bytes */10");
+        assert!(body.is_empty());
+
+        // An empty file is served whole, and any range of it is unsatisfiable
+        let empty = Content::bytes(Vec::new(), None, "text/plain");
+        let (status, headers, body) = get(&empty, &[]).await;
+        assert_eq!((status, body.len()), (StatusCode::OK, 0));
+        assert_eq!(headers[header::CONTENT_LENGTH], "0");
+        let (status, headers, _) = get(&empty, &[(header::RANGE, "bytes=-1")]).await;
+        assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(headers[header::CONTENT_RANGE], "bytes */0");
+    }
+
+    #[tokio::test]
+    async fn evaluate_sends_several_ranges_as_multipart() {
+        let content = content(b"0123456789");
+        let (status, headers, body) = get(&content, &[(header::RANGE, "bytes=0-1,-2")]).await;
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(headers[header::CONTENT_LENGTH], body.len().to_string().as_str());
+
+        let content_type = headers[header::CONTENT_TYPE].to_str().unwrap();
+        let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap();
+        let expected = format!(
+            "\r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\
+             \r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\
+             \r\n--{b}--\r\n",
+            b = boundary
+        );
+        assert_eq!(String::from_utf8(body).unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn evaluate_honors_conditional_headers() {
+        let content = content(b"0123456789");
+        let etag = content.etag.as_str();
+        let weak = format!("W/{}", etag);
+        let earlier = "Sat, 05 Nov 1994 08:49:37 GMT";
+        assert_eq!(get(&content, &[(header::IF_NONE_MATCH, etag)]).await.0, StatusCode::NOT_MODIFIED);
+        assert_eq!(get(&content, &[(header::IF_NONE_MATCH, &format!("\"other\", {}", weak))]).await.0, StatusCode::NOT_MODIFIED);
+        assert_eq!(get(&content, &[(header::IF_NONE_MATCH, "\"other\"")]).await.0, StatusCode::OK);
+        assert_eq!(get(&content, &[(header::IF_MODIFIED_SINCE, MODIFIED)]).await.0, StatusCode::NOT_MODIFIED);
+
+        assert_eq!(get(&content, &[(header::IF_MATCH, "\"other\"")]).await.0, StatusCode::PRECONDITION_FAILED);
+        // If-Match compares strongly
+        assert_eq!(get(&content, &[(header::IF_MATCH, &weak)]).await.0, StatusCode::PRECONDITION_FAILED);
+        assert_eq!(get(&content, &[(header::IF_MATCH, etag)]).await.0, StatusCode::OK);
+        assert_eq!(get(&content, &[(header::IF_UNMODIFIED_SINCE, earlier)]).await.0, StatusCode::PRECONDITION_FAILED);
+
+        // A current If-Range validator keeps the range, a stale one gets the whole content
+        let range = (header::RANGE, "bytes=0-1");
+        assert_eq!(get(&content, &[range.clone(), (header::IF_RANGE, etag)]).await.0, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(get(&content, &[range.clone(), (header::IF_RANGE, MODIFIED)]).await.0, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(get(&content, &[range.clone(), (header::IF_RANGE, "\"stale\"")]).await.0, StatusCode::OK);
+        assert_eq!(get(&content, &[range, (header::IF_RANGE, earlier)]).await.0, StatusCode::OK);
+    }
+}
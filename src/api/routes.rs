@@ -4,12 +4,14 @@ use axum::{
 };
 use sqlx::SqlitePool;
 use crate::api::handlers::{
-    library::{get_libraries, create_library, delete_library, scan_all_libraries, list_directories, browse_library},
-    media::{get_recently_added, get_library_media, get_media_details, refresh_media_metadata, search_handler, identify_media, search_library},
-    playback::{stream_video, update_progress, get_continue_watching, get_media_progress, get_subtitles, stream_subtitle, get_thumbnail},
+    library::{get_libraries, create_library, update_library, delete_library, scan_all_libraries, list_directories, browse_library, export_library, get_export_status, test_parser},
+    media::{get_recently_added, get_library_media, get_media_details, get_media_artwork, get_media_extras, refresh_media_metadata, search_handler, identify_media, search_library},
+    playback::{stream_video, stream_remux, get_playback_plan, get_device_profiles, update_progress, get_continue_watching, get_media_progress, get_subtitles, upload_subtitle, search_subtitles, download_subtitle, stream_subtitle, stream_embedded_subtitle, get_thumbnail},
     settings::{get_settings, update_setting, reset_database},
-    tv::{get_all_series, get_series_seasons, get_season_episodes, get_series_detail, refresh_series_metadata, identify_series},
+    tv::{get_all_series, get_series_seasons, get_season_episodes, get_series_detail, get_series_extras, refresh_series_metadata, identify_series},
     books::{get_book_pages, get_book_page},
+    hls::{get_master_playlist, get_variant_playlist, get_segment},
+    trickplay::get_trickplay,
 };
 
 pub fn app(pool: SqlitePool) -> Router {
@@ -17,14 +19,28 @@ pub fn app(pool: SqlitePool) -> Router {
         .route("/api/v1/recent", get(get_recently_added))
         .route("/api/v1/directories", axum::routing::post(list_directories))
         .route("/api/v1/stream/:id", get(stream_video).head(stream_video))
-        .route("/api/v1/stream/:id/subtitles", get(get_subtitles))
+        .route("/api/v1/stream/:id/remux", get(stream_remux).head(stream_remux))
+        .route("/api/v1/stream/:id/subtitles", get(get_subtitles).post(upload_subtitle))
+        .route("/api/v1/stream/:id/subtitles/search", get(search_subtitles))
+        .route("/api/v1/stream/:id/subtitles/download", axum::routing::post(download_subtitle))
         .route("/api/v1/stream/:id/subtitle/:filename", get(stream_subtitle))
+        .route("/api/v1/stream/:id/subtitle/embedded/:index", get(stream_embedded_subtitle))
+        .route("/api/v1/hls/:id/master.m3u8", get(get_master_playlist))
+        .route("/api/v1/hls/:id/:quality/index.m3u8", get(get_variant_playlist))
+        .route("/api/v1/hls/:id/:quality/:segment", get(get_segment))
+        .route("/api/v1/trickplay/:id/:file", get(get_trickplay))
         .route("/api/v1/libraries", get(get_libraries).post(create_library))
-        .route("/api/v1/libraries/:id", axum::routing::delete(delete_library))
+        .route("/api/v1/libraries/:id", axum::routing::delete(delete_library).patch(update_library))
         .route("/api/v1/libraries/:id/media", get(get_library_media))
         .route("/api/v1/libraries/:id/browse", get(browse_library))
+        .route("/api/v1/libraries/:id/export", get(get_export_status).post(export_library))
+        .route("/api/v1/libraries/:id/parse", axum::routing::post(test_parser))
         .route("/api/v1/media/:id", get(get_media_details))
         .route("/api/v1/media/:id/thumbnail", get(get_thumbnail))
+        .route("/api/v1/media/:id/artwork/:kind", get(get_media_artwork))
+        .route("/api/v1/media/:id/extras", get(get_media_extras))
+        .route("/api/v1/media/:id/playback", axum::routing::post(get_playback_plan))
+        .route("/api/v1/profiles", get(get_device_profiles))
         .route("/api/v1/media/:id/refresh", axum::routing::post(refresh_media_metadata))
         .route("/api/v1/media/:id/identify", axum::routing::post(identify_media))
         .route("/api/v1/media/:id/pages", get(get_book_pages))
@@ -40,6 +56,7 @@ pub fn app(pool: SqlitePool) -> Router {
         .route("/api/v1/series", get(get_all_series))
         .route("/api/v1/series/:name/seasons", get(get_series_seasons))
         .route("/api/v1/series/:name/detail", get(get_series_detail))
+        .route("/api/v1/series/:name/extras", get(get_series_extras))
         .route("/api/v1/series/:name/refresh", axum::routing::post(refresh_series_metadata))
         .route("/api/v1/series/:name/identify", axum::routing::post(identify_series))
         .route("/api/v1/series/:name/season/:num", get(get_season_episodes))
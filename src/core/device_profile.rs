@@ -0,0 +1,270 @@
+//! Device profiles - what a client can play, and the playback plan for a media item on it.
+//!
+//! A plan is one of three methods, cheapest first: direct play (`/stream/:id` as is), remux
+//! (`/stream/:id/remux`, fragmented MP4 with the video copied) when only the container or
+//! audio is the problem, and transcode (`/hls/:id/master.m3u8`) when the video itself can't
+//! be played or is over the device's bitrate or resolution, or an image subtitle track (PGS,
+//! VobSub) has to be burned in. Codec names are FFprobe's ("h264", "hevc", "aac", "dts", ...)
+//! and containers are file extensions. Disc rips have no file to play as is, so the best they
+//! get is a remux of their main title: Matroska from `/stream/:id`, or MP4 from the remux URL.
+
+use crate::core::disc::DiscType;
+use crate::core::probe::MediaInfo;
+use crate::core::remux;
+use crate::models::media::PlaybackPlanDto;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceProfile {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub containers: Vec<String>,
+    pub video_codecs: Vec<String>,
+    pub audio_codecs: Vec<String>,
+    /// Bits per second
+    #[serde(default)]
+    pub max_bitrate: Option<u64>,
+    #[serde(default)]
+    pub max_height: Option<u32>,
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+/// The Android app (ExoPlayer).
+pub fn android() -> DeviceProfile {
+    DeviceProfile {
+        name: Some("android".to_string()),
+        containers: strings(&["mp4", "mkv", "webm", "mov", "ts"]),
+        video_codecs: strings(&["h264", "hevc", "vp8", "vp9", "av1", "mpeg4"]),
+        audio_codecs: strings(&["aac", "mp3", "ac3", "eac3", "opus", "vorbis", "flac"]),
+        max_bitrate: None,
+        max_height: None,
+    }
+}
+
+/// Web browsers: what Chrome, Firefox and Safari all play.
+pub fn web() -> DeviceProfile {
+    DeviceProfile {
+        name: Some("web".to_string()),
+        containers: strings(&["mp4", "webm"]),
+        video_codecs: strings(&["h264", "vp8", "vp9", "av1"]),
+        audio_codecs: strings(&["aac", "mp3", "opus", "vorbis", "flac"]),
+        max_bitrate: None,
+        max_height: None,
+    }
+}
+
+pub fn builtin_profiles() -> Vec<DeviceProfile> {
+    vec![android(), web()]
+}
+
+pub fn builtin(name: &str) -> Option<DeviceProfile> {
+    builtin_profiles().into_iter().find(|p| p.name.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(name)))
+}
+
+fn supports(list: &[String], value: &str) -> bool {
+    list.iter().any(|v| v.eq_ignore_ascii_case(value))
+}
+
+/// Container of a file by extension, with aliases folded together.
+fn container_of(path: &str) -> Option<String> {
+    let ext = Path::new(path).extension()?.to_str()?.to_lowercase();
+    Some(match ext.as_str() {
+        "m4v" => "mp4".to_string(),
+        "m2ts" | "mts" => "ts".to_string(),
+        _ => ext,
+    })
+}
+
+fn mbps(bits: u64) -> String {
+    format!("{:.1} Mbps", bits as f64 / 1_000_000.0)
+}
+
+/// The playback plan for media `id` on a device. `info` is `None` when the file couldn't be
+/// probed; the container alone then decides between direct play and remux. `burn_in` is the
+/// selected subtitle track when it is images, which only a transcode can show. Disc rips go
+/// through the same checks on the streams of their main title, which `/stream/:id` serves as
+/// Matroska, so they are only transcoded when the video itself can't be played.
+pub fn decide(id: i64, file_path: &str, disc: Option<DiscType>, info: Option<&MediaInfo>, profile: &DeviceProfile, burn_in: Option<&str>) -> PlaybackPlanDto {
+    let container = if disc.is_some() { Some("mkv".to_string()) } else { container_of(file_path) };
+    let video_codec = info.and_then(|i| i.video_codec.clone());
+    let audio_codec = info.and_then(|i| i.audio_codec.clone());
+    let plan = |method: &str, url: String, reason: String, transcode_audio: bool| PlaybackPlanDto {
+        media_id: id,
+        method: method.to_string(),
+        url,
+        reason,
+        container: container.clone(),
+        video_codec: video_codec.clone(),
+        audio_codec: audio_codec.clone(),
+        transcode_audio,
+        burn_in_subtitle: burn_in.map(str::to_string),
+    };
+    let transcode_url = match burn_in {
+        Some(track) => format!("/api/v1/hls/{}/master.m3u8?subtitle={}", id, urlencoding::encode(track)),
+        None => format!("/api/v1/hls/{}/master.m3u8", id),
+    };
+    let transcode = |reason: String| plan("transcode", transcode_url.clone(), reason, true);
+
+    let disc_name = disc.map(|d| match d {
+        DiscType::Dvd => "DVD",
+        DiscType::Bluray => "Blu-ray",
+    });
+    if let Some(track) = burn_in {
+        return transcode(format!("Subtitle {} is images the device can't render; it is burned into the video", track));
+    }
+    if let (Some(max), Some(bitrate)) = (profile.max_bitrate, info.and_then(|i| i.bitrate)) {
+        if bitrate > max {
+            return transcode(format!("Bitrate {} is over the device's {}", mbps(bitrate), mbps(max)));
+        }
+    }
+    if let (Some(max), Some(height)) = (profile.max_height, info.and_then(|i| i.height)) {
+        if height > max {
+            return transcode(format!("Resolution {}p is over the device's {}p", height, max));
+        }
+    }
+    if let Some(codec) = video_codec.as_deref().filter(|c| !supports(&profile.video_codecs, c)) {
+        return transcode(format!("Video codec {} is not supported", codec));
+    }
+
+    let unsupported_audio = audio_codec.as_deref().filter(|c| !supports(&profile.audio_codecs, c));
+    let container_ok = container.as_deref().is_some_and(|c| supports(&profile.containers, c));
+    if container_ok && unsupported_audio.is_none() {
+        if let Some(name) = disc_name {
+            let reason = format!("{} rips are remuxed from their main title to Matroska", name);
+            return plan("remux", format!("/api/v1/stream/{}", id), reason, false);
+        }
+        let reason = if info.is_some() {
+            "Container and codecs are supported".to_string()
+        } else {
+            "Container is supported; the codecs could not be probed".to_string()
+        };
+        return plan("direct_play", format!("/api/v1/stream/{}", id), reason, false);
+    }
+
+    if !supports(&profile.containers, "mp4") {
+        return transcode(format!("Container {} is not supported and MP4 isn't either", container.as_deref().unwrap_or("unknown")));
+    }
+    let reason = match (unsupported_audio, disc_name) {
+        (Some(codec), _) => format!("Audio codec {} is not supported; it is converted to AAC", codec),
+        (None, Some(name)) => format!("{} rips are remuxed from their main title to MP4", name),
+        (None, None) => format!("Container {} is not supported; the streams are remuxed to MP4", container.as_deref().unwrap_or("unknown")),
+    };
+    let forced = unsupported_audio.is_some();
+    let transcode_audio = forced || remux::mp4_needs_audio_transcode(audio_codec.as_deref());
+    let url = if forced {
+        format!("/api/v1/stream/{}/remux?transcode_audio=true", id)
+    } else {
+        format!("/api/v1/stream/{}/remux", id)
+    };
+    plan("remux", url, reason, transcode_audio)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(video: &str, audio: &str) -> MediaInfo {
+        MediaInfo { video_codec: Some(video.to_string()), audio_codec: Some(audio.to_string()), bitrate: Some(8_000_000), height: Some(1080) }
+    }
+
+    // Method, URL and whether the audio is converted
+    fn outcome(plan: &PlaybackPlanDto) -> (&str, &str, bool) {
+        (plan.method.as_str(), plan.url.as_str(), plan.transcode_audio)
+    }
+
+    #[test]
+    fn decide_prefers_direct_play_then_remux_then_transcode() {
+        let web = web();
+        let plan = decide(1, "/m/a.mp4", None, Some(&info("h264", "aac")), &web, None);
+        assert_eq!(outcome(&plan), ("direct_play", "/api/v1/stream/1", false));
+
+        // Only the container is the problem
+        let plan = decide(1, "/m/a.mkv", None, Some(&info("h264", "aac")), &web, None);
+        assert_eq!(outcome(&plan), ("remux", "/api/v1/stream/1/remux", false));
+        // Audio the device plays but MP4 can't hold is converted without being forced
+        let plan = decide(1, "/m/a.mkv", None, Some(&info("vp9", "vorbis")), &web, None);
+        assert_eq!(outcome(&plan), ("remux", "/api/v1/stream/1/remux", true));
+        // Audio the device can't play is converted, the video copied
+        let plan = decide(1, "/m/a.mp4", None, Some(&info("h264", "dts")), &web, None);
+        assert_eq!(outcome(&plan), ("remux", "/api/v1/stream/1/remux?transcode_audio=true", true));
+        assert_eq!(plan.reason, "Audio codec dts is not supported; it is converted to AAC");
+
+        let plan = decide(1, "/m/a.mp4", None, Some(&info("hevc", "aac")), &web, None);
+        assert_eq!(outcome(&plan), ("transcode", "/api/v1/hls/1/master.m3u8", true));
+        assert_eq!(plan.reason, "Video codec hevc is not supported");
+
+        // Nothing probed: the container decides
+        let plan = decide(1, "/m/a.m4v", None, None, &web, None);
+        assert_eq!(outcome(&plan), ("direct_play", "/api/v1/stream/1", false));
+        let plan = decide(1, "/m/a.avi", None, None, &web, None);
+        assert_eq!(outcome(&plan), ("remux", "/api/v1/stream/1/remux", true));
+    }
+
+    #[test]
+    fn decide_transcodes_over_the_device_limits() {
+        let limited = DeviceProfile { max_bitrate: Some(4_000_000), max_height: Some(720), ..android() };
+        let plan = decide(1, "/m/a.mkv", None, Some(&info("h264", "aac")), &limited, None);
+        assert_eq!(plan.method, "transcode");
+        assert_eq!(plan.reason, "Bitrate 8.0 Mbps is over the device's 4.0 Mbps");
+
+        let limited = DeviceProfile { max_bitrate: Some(10_000_000), ..limited };
+        let plan = decide(1, "/m/a.mkv", None, Some(&info("h264", "aac")), &limited, None);
+        assert_eq!(plan.method, "transcode");
+        assert_eq!(plan.reason, "Resolution 1080p is over the device's 720p");
+
+        let limited = DeviceProfile { max_height: Some(1080), ..limited };
+        assert_eq!(decide(1, "/m/a.mkv", None, Some(&info("h264", "aac")), &limited, None).method, "direct_play");
+
+        // Without MP4 there is nothing to remux to
+        let webm_only = DeviceProfile { containers: strings(&["webm"]), ..web() };
+        let plan = decide(1, "/m/a.mkv", None, Some(&info("vp9", "opus")), &webm_only, None);
+        assert_eq!(plan.method, "transcode");
+        assert_eq!(plan.reason, "Container mkv is not supported and MP4 isn't either");
+    }
+
+    #[test]
+    fn decide_burns_in_image_subtitles() {
+        let plan = decide(7, "/m/a.mp4", None, Some(&info("h264", "aac")), &web(), Some("2"));
+        assert_eq!(outcome(&plan), ("transcode", "/api/v1/hls/7/master.m3u8?subtitle=2", true));
+        assert_eq!(plan.burn_in_subtitle.as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn decide_remuxes_disc_rips_from_their_main_title() {
+        // Matroska from the stream URL where the device plays it, MP4 otherwise
+        let plan = decide(3, "/m/Ran (1985)", Some(DiscType::Dvd), Some(&info("mpeg2video", "ac3")), &DeviceProfile {
+            video_codecs: strings(&["h264", "mpeg2video"]),
+            ..android()
+        }, None);
+        assert_eq!(outcome(&plan), ("remux", "/api/v1/stream/3", false));
+        assert_eq!(plan.reason, "DVD rips are remuxed from their main title to Matroska");
+
+        let plan = decide(3, "/m/Heat (1995)", Some(DiscType::Bluray), Some(&info("h264", "aac")), &web(), None);
+        assert_eq!(outcome(&plan), ("remux", "/api/v1/stream/3/remux", false));
+        assert_eq!(plan.reason, "Blu-ray rips are remuxed from their main title to MP4");
+        assert_eq!(plan.container.as_deref(), Some("mkv"));
+
+        let plan = decide(3, "/m/Heat (1995)", Some(DiscType::Bluray), Some(&info("vc1", "aac")), &web(), None);
+        assert_eq!(plan.method, "transcode");
+    }
+
+    #[test]
+    fn builtin_profiles_are_found_by_name() {
+        assert_eq!(builtin("Android").and_then(|p| p.name), Some("android".to_string()));
+        assert_eq!(builtin("web").and_then(|p| p.name), Some("web".to_string()));
+        assert!(builtin("roku").is_none());
+
+        // What sets them apart: Matroska, HEVC and AC-3 on Android only
+        let (android, web) = (android(), web());
+        let plan = |profile: &DeviceProfile| decide(1, "/m/a.mkv", None, Some(&info("hevc", "ac3")), profile, None).method;
+        assert_eq!(plan(&android), "direct_play");
+        assert_eq!(plan(&web), "transcode");
+        let plan = |profile: &DeviceProfile| decide(1, "/m/a.mkv", None, Some(&info("h264", "ac3")), profile, None);
+        assert_eq!(outcome(&plan(&web)), ("remux", "/api/v1/stream/1/remux?transcode_audio=true", true));
+        assert_eq!(plan(&android).method, "direct_play");
+    }
+}
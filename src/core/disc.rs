@@ -0,0 +1,335 @@
+//! Disc - DVD (`VIDEO_TS`) and Blu-ray (`BDMV`) folder rips and `.iso` images.
+//!
+//! A disc is scanned as a single movie whose `file_path` is the disc's root folder (the one
+//! holding `VIDEO_TS`/`BDMV`) or the image file, with `media.disc_type` set. Playback picks the
+//! main title - the largest DVD title set or the longest Blu-ray playlist - and streams it
+//! through the remux pipeline in `core::remux`.
+
+use crate::core::probe;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Blu-ray timestamps count 45 kHz ticks
+const MPLS_TICKS_PER_SECOND: f64 = 45_000.0;
+/// Larger than a dual-layer DVD (8.5 GB), so an image this big that can't be read is a Blu-ray
+const DVD_MAX_BYTES: u64 = 9_000_000_000;
+const ISO_SECTOR: u64 = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiscType {
+    Dvd,
+    Bluray,
+}
+
+impl DiscType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiscType::Dvd => "dvd",
+            DiscType::Bluray => "bluray",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<DiscType> {
+        match value {
+            "dvd" => Some(DiscType::Dvd),
+            "bluray" => Some(DiscType::Bluray),
+            _ => None,
+        }
+    }
+
+    /// The disc type whose structure lives in a folder of this name.
+    pub fn from_folder(name: &str) -> Option<DiscType> {
+        if name.eq_ignore_ascii_case("VIDEO_TS") {
+            Some(DiscType::Dvd)
+        } else if name.eq_ignore_ascii_case("BDMV") {
+            Some(DiscType::Bluray)
+        } else {
+            None
+        }
+    }
+}
+
+/// The title a disc plays as its movie.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MainTitle {
+    /// Files to play back to back (VOBs of a title set, or a playlist's clips)
+    pub files: Vec<PathBuf>,
+    /// Known for Blu-ray playlists; DVD title sets are chosen by size
+    pub duration: Option<f64>,
+}
+
+pub fn is_iso(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("iso"))
+}
+
+/// The disc type of a disc root folder or image, `None` for anything else.
+pub fn detect(path: &Path) -> Option<DiscType> {
+    if is_iso(path) {
+        return Some(iso_type(path));
+    }
+    fs::read_dir(path).ok()?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .find_map(|e| DiscType::from_folder(&e.file_name().to_string_lossy()))
+}
+
+/// Child of `dir` named `name`, ignoring case (rips differ in VIDEO_TS vs video_ts).
+fn child(dir: &Path, name: &str) -> Option<PathBuf> {
+    fs::read_dir(dir).ok()?
+        .filter_map(|e| e.ok())
+        .find(|e| e.file_name().to_string_lossy().eq_ignore_ascii_case(name))
+        .map(|e| e.path())
+}
+
+/// The main title of a disc root folder. Images are read by FFmpeg itself and have none.
+pub fn main_title(root: &Path, disc: DiscType) -> Option<MainTitle> {
+    match disc {
+        DiscType::Dvd => dvd_main_title(&child(root, "VIDEO_TS")?),
+        DiscType::Bluray => bluray_main_title(&child(root, "BDMV")?),
+    }
+}
+
+/// Length in seconds of a disc's main title. Blu-ray playlists carry it; DVD title sets and
+/// images need FFprobe.
+pub async fn duration(path: &Path, disc: DiscType) -> Option<f64> {
+    if is_iso(path) {
+        return probe::duration(path).await;
+    }
+    let title = main_title(path, disc)?;
+    if title.duration.is_some() {
+        return title.duration;
+    }
+    let mut total = 0.0;
+    for file in &title.files {
+        total += probe::duration(file).await?;
+    }
+    Some(total)
+}
+
+/// Total size of a DVD title set and its numbered VOBs
+type TitleSet = (u64, Vec<(u32, PathBuf)>);
+
+/// The title set with the most video: VTS_nn_1.VOB, VTS_nn_2.VOB, ... (VTS_nn_0 is its menu).
+fn dvd_main_title(video_ts: &Path) -> Option<MainTitle> {
+    let mut title_sets: HashMap<String, TitleSet> = HashMap::new();
+    for entry in fs::read_dir(video_ts).ok()?.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().to_uppercase();
+        let Some(rest) = name.strip_prefix("VTS_").and_then(|r| r.strip_suffix(".VOB")) else { continue };
+        let Some((set, part)) = rest.split_once('_') else { continue };
+        let Some(part) = part.parse::<u32>().ok().filter(|p| *p > 0) else { continue };
+        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+
+        let (total, files) = title_sets.entry(set.to_string()).or_default();
+        *total += size;
+        files.push((part, entry.path()));
+    }
+
+    let (_, mut files) = title_sets.into_values().max_by_key(|(size, _)| *size)?;
+    files.sort();
+    Some(MainTitle { files: files.into_iter().map(|(_, p)| p).collect(), duration: None })
+}
+
+/// The longest playlist in BDMV/PLAYLIST whose clips are all present in BDMV/STREAM.
+fn bluray_main_title(bdmv: &Path) -> Option<MainTitle> {
+    let stream_dir = child(bdmv, "STREAM")?;
+    fs::read_dir(child(bdmv, "PLAYLIST")?).ok()?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|x| x.eq_ignore_ascii_case("mpls")))
+        .filter_map(|e| {
+            let (clips, duration) = parse_mpls(&fs::read(e.path()).ok()?)?;
+            let files = clips.iter()
+                .map(|clip| child(&stream_dir, &format!("{}.m2ts", clip)))
+                .collect::<Option<Vec<_>>>()?;
+            Some(MainTitle { files, duration: Some(duration) })
+        })
+        .max_by(|a, b| a.duration.partial_cmp(&b.duration).unwrap_or(std::cmp::Ordering::Equal))
+}
+
+/// Clip names and total duration of an MPLS playlist.
+fn parse_mpls(data: &[u8]) -> Option<(Vec<String>, f64)> {
+    let u16_at = |pos: usize| data.get(pos..pos + 2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize);
+    let u32_at = |pos: usize| data.get(pos..pos + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+    if data.get(0..4)? != b"MPLS" {
+        return None;
+    }
+
+    // PlayList: length (4), reserved (2), item count (2), sub-path count (2), then the items
+    let playlist = u32_at(8)? as usize;
+    let item_count = u16_at(playlist + 6)?;
+    let mut pos = playlist + 10;
+    let mut clips = Vec::new();
+    let mut ticks = 0u64;
+
+    for _ in 0..item_count {
+        // PlayItem: length (2), clip name (5), codec (4), flags (2), STC id (1), IN (4), OUT (4)
+        let length = u16_at(pos)?;
+        let item = pos + 2;
+        let clip = String::from_utf8_lossy(data.get(item..item + 5)?).to_string();
+        let (in_time, out_time) = (u32_at(item + 12)?, u32_at(item + 16)?);
+        ticks += u64::from(out_time.saturating_sub(in_time));
+        if clips.last() != Some(&clip) {
+            clips.push(clip);
+        }
+        pos = item + length;
+    }
+
+    (!clips.is_empty()).then(|| (clips, ticks as f64 / MPLS_TICKS_PER_SECOND))
+}
+
+/// DVD or Blu-ray image: an ISO 9660 root directory with VIDEO_TS or BDMV tells; UDF-only
+/// images (most Blu-rays) are told apart by size.
+fn iso_type(path: &Path) -> DiscType {
+    iso9660_root_type(path).unwrap_or_else(|| {
+        match fs::metadata(path).map(|m| m.len()) {
+            Ok(size) if size > DVD_MAX_BYTES => DiscType::Bluray,
+            _ => DiscType::Dvd,
+        }
+    })
+}
+
+fn iso9660_root_type(path: &Path) -> Option<DiscType> {
+    let mut file = File::open(path).ok()?;
+    let mut descriptor = [0u8; ISO_SECTOR as usize];
+    file.seek(SeekFrom::Start(16 * ISO_SECTOR)).ok()?;
+    file.read_exact(&mut descriptor).ok()?;
+    if descriptor[0] != 1 || &descriptor[1..6] != b"CD001" {
+        return None;
+    }
+
+    // Root directory record: extent location and size, little-endian halves of both-endian fields
+    let root = &descriptor[156..190];
+    let extent = u32::from_le_bytes(root[2..6].try_into().ok()?) as u64;
+    let size = u32::from_le_bytes(root[10..14].try_into().ok()?).min(64 * ISO_SECTOR as u32) as usize;
+    let mut directory = vec![0u8; size];
+    file.seek(SeekFrom::Start(extent * ISO_SECTOR)).ok()?;
+    file.read_exact(&mut directory).ok()?;
+
+    let mut pos = 0;
+    while pos < directory.len() {
+        let length = directory[pos] as usize;
+        if length == 0 {
+            // Records don't cross sectors; the rest of this one is padding
+            pos = (pos / ISO_SECTOR as usize + 1) * ISO_SECTOR as usize;
+            continue;
+        }
+        let name_len = *directory.get(pos + 32)? as usize;
+        let name = String::from_utf8_lossy(directory.get(pos + 33..pos + 33 + name_len)?);
+        if let Some(disc) = DiscType::from_folder(name.split(';').next().unwrap_or_default()) {
+            return Some(disc);
+        }
+        pos += length;
+    }
+    None
+}
+
+/// FFmpeg input arguments that read a disc's main title.
+pub fn input_args(path: &Path, disc: DiscType) -> Option<Vec<String>> {
+    let path_str = path.to_string_lossy().to_string();
+    if is_iso(path) {
+        return Some(match disc {
+            // Needs FFmpeg's DVD demuxer (7.1+) or libbluray; both pick the main title themselves
+            DiscType::Dvd => vec!["-f".into(), "dvdvideo".into(), "-i".into(), path_str],
+            DiscType::Bluray => vec!["-i".into(), format!("bluray:{}", path_str)],
+        });
+    }
+
+    let title = main_title(path, disc)?;
+    let files: Vec<String> = title.files.iter().map(|f| f.to_string_lossy().to_string()).collect();
+    Some(vec!["-fflags".into(), "+genpts".into(), "-i".into(), format!("concat:{}", files.join("|"))])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An MPLS file whose PlayItems are padded to `item_length` bytes, as real ones are
+    fn mpls(items: &[(&str, u32, u32)], item_length: usize) -> Vec<u8> {
+        let playlist = 0x3A;
+        let mut data = b"MPLS0200".to_vec();
+        data.extend((playlist as u32).to_be_bytes());
+        data.resize(playlist + 6, 0);
+        data.extend((items.len() as u16).to_be_bytes());
+        data.extend(0u16.to_be_bytes());
+        for (clip, in_time, out_time) in items {
+            let start = data.len();
+            data.extend((item_length as u16).to_be_bytes());
+            data.extend(clip.as_bytes());
+            data.extend(b"M2TS\0\0\0");
+            data.extend(in_time.to_be_bytes());
+            data.extend(out_time.to_be_bytes());
+            data.resize(start + 2 + item_length, 0xFF);
+        }
+        data
+    }
+
+    #[test]
+    fn parse_mpls_follows_item_lengths_and_merges_repeated_clips() {
+        let items = [("00001", 0, 90_000), ("00001", 90_000, 180_000), ("00002", 45_000, 450_000), ("00001", 0, 45_000)];
+        for item_length in [20, 64] {
+            let (clips, duration) = parse_mpls(&mpls(&items, item_length)).unwrap();
+            assert_eq!(clips, ["00001", "00002", "00001"], "item length {}", item_length);
+            assert_eq!(duration, 14.0);
+        }
+
+        let data = mpls(&items, 64);
+        assert_eq!(parse_mpls(&data[..data.len() - 70]), None);
+        assert_eq!(parse_mpls(&mpls(&[], 20)), None);
+        assert_eq!(parse_mpls(b"MPLS"), None);
+        assert_eq!(parse_mpls(b"NOPE0200"), None);
+    }
+
+    fn directory_record(name: &str) -> Vec<u8> {
+        let mut record = vec![0u8; 33];
+        record[32] = name.len() as u8;
+        record.extend(name.as_bytes());
+        if record.len() % 2 == 1 {
+            record.push(0);
+        }
+        record[0] = record.len() as u8;
+        record
+    }
+
+    // An ISO 9660 image whose root directory spans two sectors
+    fn iso(first: &[&str], second: &[&str]) -> Vec<u8> {
+        let sector = ISO_SECTOR as usize;
+        let mut data = vec![0u8; 20 * sector];
+        let descriptor = 16 * sector;
+        data[descriptor] = 1;
+        data[descriptor + 1..descriptor + 6].copy_from_slice(b"CD001");
+        data[descriptor + 158..descriptor + 162].copy_from_slice(&18u32.to_le_bytes());
+        data[descriptor + 166..descriptor + 170].copy_from_slice(&(2 * sector as u32).to_le_bytes());
+        for (start, names) in [(18 * sector, first), (19 * sector, second)] {
+            let records: Vec<u8> = names.iter().flat_map(|name| directory_record(name)).collect();
+            data[start..start + records.len()].copy_from_slice(&records);
+        }
+        data
+    }
+
+    #[test]
+    fn iso9660_root_type_finds_the_disc_folder() {
+        let dir = std::env::temp_dir().join(format!("vortex-disc-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let root_type = |name: &str, data: &[u8]| {
+            let path = dir.join(name);
+            std::fs::write(&path, data).unwrap();
+            iso9660_root_type(&path)
+        };
+
+        assert_eq!(root_type("dvd.iso", &iso(&["\0", "\u{1}", "AUDIO_TS", "VIDEO_TS"], &[])), Some(DiscType::Dvd));
+        // Found past the padding at the end of the first sector, with a version suffix
+        assert_eq!(root_type("bluray.iso", &iso(&["\0", "\u{1}"], &["CERTIFICATE", "BDMV;1"])), Some(DiscType::Bluray));
+        assert_eq!(root_type("data.iso", &iso(&["\0", "\u{1}", "README.TXT"], &[])), None);
+
+        let mut not_iso = iso(&["VIDEO_TS"], &[]);
+        not_iso[16 * ISO_SECTOR as usize + 1] = b'X';
+        assert_eq!(root_type("not-iso.iso", &not_iso), None);
+        // Cut off inside the root directory
+        assert_eq!(root_type("truncated.iso", &iso(&["VIDEO_TS"], &[])[..19 * ISO_SECTOR as usize]), None);
+        assert_eq!(root_type("empty.iso", &[]), None);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,161 @@
+//! Episode Numbering - maps episodes that filenames identify without a season onto the
+//! provider's seasons: absolute numbers (`[Group] Show - 137 [1080p]`) by their position in
+//! the provider's episode list, and air dates (`Show.2024.03.15`) by episode air dates.
+//! Selected per library with `libraries.episode_numbering`.
+
+use crate::core::metadata::fetch_episodes;
+use crate::error::AppError;
+use crate::models::metadata::EpisodeMetadata;
+use chrono::{Datelike, NaiveDate};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Values accepted in `libraries.episode_numbering`.
+pub const EPISODE_NUMBERINGS: &[&str] = &["standard", "absolute", "date"];
+
+/// Stop walking seasons here even if the provider keeps returning episodes.
+const MAX_SEASONS: i32 = 100;
+
+/// How long a series' full episode list is reused, so a scan fetches each season once.
+const EPISODE_LIST_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// A series' episode list and when it was fetched
+type CachedEpisodes = (Instant, Arc<Vec<EpisodeMetadata>>);
+
+// Only held to look up or store a list, never across provider requests, so one slow series
+// doesn't hold up every other scan or refresh.
+static EPISODE_LISTS: Lazy<Mutex<HashMap<String, CachedEpisodes>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EpisodeNumbering {
+    /// "S01E05" / "1x05", with the season from the filename or folder
+    #[default]
+    Standard,
+    /// A running episode count across seasons
+    Absolute,
+    /// The air date
+    Date,
+}
+
+impl EpisodeNumbering {
+    /// Read a library's setting; unset or unknown values mean standard numbering.
+    pub fn parse(value: Option<&str>) -> Self {
+        match value {
+            Some("absolute") => EpisodeNumbering::Absolute,
+            Some("date") => EpisodeNumbering::Date,
+            _ => EpisodeNumbering::Standard,
+        }
+    }
+}
+
+/// How a filename identifies an episode when it carries no season.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EpisodeKey {
+    Absolute(i32),
+    AirDate(NaiveDate),
+}
+
+impl EpisodeKey {
+    /// Season (if the key implies one) and episode to store until the key is resolved against a
+    /// provider: absolute numbers keep their number, dates become season = year, episode = MMDD
+    /// so unmatched daily episodes still sort chronologically.
+    pub fn provisional(&self) -> (Option<i32>, i32) {
+        match self {
+            EpisodeKey::Absolute(n) => (None, *n),
+            EpisodeKey::AirDate(date) => (Some(date.year()), date.month() as i32 * 100 + date.day() as i32),
+        }
+    }
+}
+
+/// Season and episode number of the provider episode `key` refers to, or `None` if the
+/// provider doesn't list it. Absolute numbers count regular-season episodes in order; air
+/// dates prefer a regular episode over a special aired the same day.
+pub async fn resolve(
+    key: &EpisodeKey,
+    provider_ids: &Value,
+    library_id: Option<i64>,
+    pool: &SqlitePool,
+) -> Result<Option<(i32, i32)>, AppError> {
+    let episodes = all_episodes(provider_ids, library_id, pool).await?;
+
+    let found = match key {
+        EpisodeKey::Absolute(n) => usize::try_from(*n - 1).ok()
+            .and_then(|index| episodes.iter().filter(|e| e.season_number > 0).nth(index)),
+        EpisodeKey::AirDate(date) => {
+            let date = date.format("%Y-%m-%d").to_string();
+            episodes.iter()
+                .filter(|e| e.air_date.as_deref().is_some_and(|a| a.starts_with(&date)))
+                .min_by_key(|e| e.season_number == 0)
+        }
+    };
+    Ok(found.map(|e| (e.season_number, e.episode_number)))
+}
+
+/// Every episode of a series, specials included, in season/episode order. Seasons are fetched
+/// from 1 until one comes back empty. Transient provider errors are returned so the caller can
+/// retry later rather than settle for the provisional numbering.
+async fn all_episodes(provider_ids: &Value, library_id: Option<i64>, pool: &SqlitePool) -> Result<Arc<Vec<EpisodeMetadata>>, AppError> {
+    let cache_key = format!("{}:{}", library_id.unwrap_or_default(), provider_ids);
+    if let Some((fetched, list)) = EPISODE_LISTS.lock().unwrap().get(&cache_key) {
+        if fetched.elapsed() < EPISODE_LIST_TTL {
+            return Ok(list.clone());
+        }
+    }
+
+    let mut episodes = fetch_episodes(provider_ids, 0, library_id, pool).await.unwrap_or_default();
+    for season in 1..=MAX_SEASONS {
+        match fetch_episodes(provider_ids, season, library_id, pool).await {
+            Ok(list) if !list.is_empty() => episodes.extend(list),
+            Err(e @ AppError::External(_)) => return Err(e),
+            _ => break,
+        }
+    }
+    episodes.sort_by_key(|e| (e.season_number, e.episode_number));
+
+    let list = Arc::new(episodes);
+    let mut cache = EPISODE_LISTS.lock().unwrap();
+    cache.retain(|_, (fetched, _)| fetched.elapsed() < EPISODE_LIST_TTL);
+    cache.insert(cache_key, (Instant::now(), list.clone()));
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn resolve_maps_absolute_numbers_and_air_dates_onto_the_offline_catalog() {
+        let dir = std::env::temp_dir().join(format!("vortex-episode-numbering-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let pool = crate::db::connect(&format!("sqlite:{}", dir.join("test.db").display())).await;
+        let catalog = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/offline_catalog");
+        sqlx::query("INSERT INTO settings (key, value) VALUES ('metadata_provider', 'offline'), ('offline_catalog_path', ?)")
+            .bind(catalog.to_string_lossy()).execute(&pool).await.unwrap();
+
+        let ids = json!({ "offline": "breaking-bad" });
+        let resolve = |key: EpisodeKey| {
+            let (ids, pool) = (ids.clone(), pool.clone());
+            async move { resolve(&key, &ids, None, &pool).await.unwrap() }
+        };
+        let date = |y, m, d| EpisodeKey::AirDate(NaiveDate::from_ymd_opt(y, m, d).unwrap());
+
+        // Absolute numbers count regular episodes across seasons, skipping the special
+        assert_eq!(resolve(EpisodeKey::Absolute(1)).await, Some((1, 1)));
+        assert_eq!(resolve(EpisodeKey::Absolute(3)).await, Some((2, 1)));
+        assert_eq!(resolve(EpisodeKey::Absolute(4)).await, None);
+
+        assert_eq!(resolve(date(2008, 1, 27)).await, Some((1, 2)));
+        // The special aired the same day as a regular episode
+        assert_eq!(resolve(date(2009, 3, 8)).await, Some((2, 1)));
+        assert_eq!(resolve(date(2010, 1, 1)).await, None);
+
+        pool.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,241 @@
+//! Extras - trailers, featurettes, deleted scenes and other bonus videos.
+//!
+//! A video is an extra when it sits in a recognized folder (`Extras/`, `Trailers/`,
+//! `Behind The Scenes/`, ...) within a movie or series folder, or its name ends in a type
+//! suffix (`Movie (2010)-trailer.mkv`).
+//! Extras are stored with `media.extra_type` and hidden from listings. Movie extras point at
+//! their movie through `media.extra_of`, resolved after each library scan since extras may be
+//! walked before the movie itself; series extras carry the series' `series_name`.
+
+use crate::error::AppError;
+use crate::models::media::ExtraDto;
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExtraType {
+    Trailer,
+    BehindTheScenes,
+    DeletedScene,
+    Featurette,
+    Interview,
+    Scene,
+    Short,
+    Clip,
+    Other,
+}
+
+/// How an extra was recognized, and the folder whose movie or series it belongs to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectedExtra {
+    pub extra_type: ExtraType,
+    pub owner_dir: PathBuf,
+    /// Recognized by a filename suffix rather than a folder
+    pub by_suffix: bool,
+}
+
+impl ExtraType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExtraType::Trailer => "trailer",
+            ExtraType::BehindTheScenes => "behind_the_scenes",
+            ExtraType::DeletedScene => "deleted_scene",
+            ExtraType::Featurette => "featurette",
+            ExtraType::Interview => "interview",
+            ExtraType::Scene => "scene",
+            ExtraType::Short => "short",
+            ExtraType::Clip => "clip",
+            ExtraType::Other => "other",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExtraType::Trailer => "Trailer",
+            ExtraType::BehindTheScenes => "Behind the Scenes",
+            ExtraType::DeletedScene => "Deleted Scene",
+            ExtraType::Featurette => "Featurette",
+            ExtraType::Interview => "Interview",
+            ExtraType::Scene => "Scene",
+            ExtraType::Short => "Short",
+            ExtraType::Clip => "Clip",
+            ExtraType::Other => "Extra",
+        }
+    }
+
+    /// Parse a stored `extra_type` value.
+    pub fn parse(value: &str) -> ExtraType {
+        [
+            ExtraType::Trailer, ExtraType::BehindTheScenes, ExtraType::DeletedScene, ExtraType::Featurette,
+            ExtraType::Interview, ExtraType::Scene, ExtraType::Short, ExtraType::Clip,
+        ]
+        .into_iter()
+        .find(|t| t.as_str() == value)
+        .unwrap_or(ExtraType::Other)
+    }
+
+    fn from_folder(name: &str) -> Option<ExtraType> {
+        match normalize(name).as_str() {
+            "trailers" => Some(ExtraType::Trailer),
+            "behindthescenes" => Some(ExtraType::BehindTheScenes),
+            "deletedscenes" => Some(ExtraType::DeletedScene),
+            "featurettes" => Some(ExtraType::Featurette),
+            "interviews" => Some(ExtraType::Interview),
+            "scenes" => Some(ExtraType::Scene),
+            "shorts" => Some(ExtraType::Short),
+            "clips" => Some(ExtraType::Clip),
+            "extras" | "other" | "others" | "specialfeatures" | "bonus" => Some(ExtraType::Other),
+            _ => None,
+        }
+    }
+
+    fn from_suffix(suffix: &str) -> Option<ExtraType> {
+        match normalize(suffix).as_str() {
+            "trailer" => Some(ExtraType::Trailer),
+            "behindthescenes" => Some(ExtraType::BehindTheScenes),
+            "deleted" | "deletedscene" => Some(ExtraType::DeletedScene),
+            "featurette" => Some(ExtraType::Featurette),
+            "interview" => Some(ExtraType::Interview),
+            "scene" => Some(ExtraType::Scene),
+            "short" => Some(ExtraType::Short),
+            "clip" => Some(ExtraType::Clip),
+            "other" | "extra" => Some(ExtraType::Other),
+            _ => None,
+        }
+    }
+}
+
+/// Lowercase with spaces and separators removed, so "Behind The Scenes" == "behind.the.scenes"
+fn normalize(value: &str) -> String {
+    value.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_lowercase()).collect()
+}
+
+/// Recognize an extra by its folder or filename. Extras folders only count inside a movie or
+/// series folder, so a top-level "Shorts" or "Bonus" folder of films stays a set of movies.
+pub fn detect(path: &Path, library_path: &str) -> Option<DetectedExtra> {
+    let relative = path.strip_prefix(library_path).ok()?;
+    let folders: Vec<_> = relative.parent()?.components().collect();
+
+    for (depth, folder) in folders.iter().enumerate().skip(1) {
+        if let Some(extra_type) = ExtraType::from_folder(&folder.as_os_str().to_string_lossy()) {
+            let owner_dir = folders[..depth].iter().fold(PathBuf::from(library_path), |p, c| p.join(c));
+            return Some(DetectedExtra { extra_type, owner_dir, by_suffix: false });
+        }
+    }
+
+    let stem = path.file_stem()?.to_string_lossy().to_string();
+    let suffix = stem.rsplit_once('-').map(|(_, s)| s).unwrap_or(&stem);
+    let extra_type = ExtraType::from_suffix(suffix)?;
+    // A bare suffix ("trailer.mkv") only counts for the exact word, not e.g. "Scene.mkv" titles
+    if !stem.contains('-') && extra_type != ExtraType::Trailer {
+        return None;
+    }
+    Some(DetectedExtra { extra_type, owner_dir: path.parent()?.to_path_buf(), by_suffix: true })
+}
+
+/// Display title for an extra: the file name, minus a type suffix. A bare "trailer.mkv" is
+/// titled by its type.
+pub fn extra_title(path: &Path, detected: &DetectedExtra) -> String {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    if !detected.by_suffix {
+        return stem;
+    }
+    match stem.rsplit_once('-').map(|(title, _)| title.trim()) {
+        Some(title) if !title.is_empty() => title.to_string(),
+        _ => detected.extra_type.label().to_string(),
+    }
+}
+
+/// Attach a library's movie extras that don't have a parent yet to the movie in their owner
+/// folder. A suffix extra prefers the movie whose filename it extends ("Movie-trailer"), and
+/// is only linked by that name when it sits loose in the library folder.
+pub async fn link_extras(pool: &SqlitePool, library_id: i64, library_path: &str) -> Result<(), AppError> {
+    let orphans: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT id, file_path FROM media WHERE library_id = ? AND extra_type IS NOT NULL AND extra_of IS NULL AND series_name IS NULL"
+    )
+    .bind(library_id)
+    .fetch_all(pool)
+    .await?;
+    if orphans.is_empty() {
+        return Ok(());
+    }
+
+    let movies: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT id, file_path FROM media WHERE library_id = ? AND extra_type IS NULL AND version_of IS NULL AND part_of IS NULL AND series_name IS NULL ORDER BY id"
+    )
+    .bind(library_id)
+    .fetch_all(pool)
+    .await?;
+
+    for (id, file_path) in orphans {
+        let path = Path::new(&file_path);
+        let Some(detected) = detect(path, library_path) else { continue };
+        let in_owner_dir: Vec<&(i64, String)> = movies.iter()
+            .filter(|(_, m)| Path::new(m).parent() == Some(detected.owner_dir.as_path()))
+            .collect();
+
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let prefix = stem.rsplit_once('-').map(|(p, _)| p.trim().to_string());
+        let by_name = prefix.filter(|_| detected.by_suffix).and_then(|prefix| in_owner_dir.iter()
+            .find(|(_, m)| Path::new(m).file_stem().is_some_and(|s| s.to_string_lossy().eq_ignore_ascii_case(&prefix))));
+
+        let in_movie_folder = detected.owner_dir != Path::new(library_path);
+        if let Some((movie_id, _)) = by_name.or(in_owner_dir.first().filter(|_| in_movie_folder)) {
+            sqlx::query("UPDATE media SET extra_of = ? WHERE id = ?")
+                .bind(movie_id)
+                .bind(id)
+                .execute(pool)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Detach extras from a movie that is about to be removed, so the next scan can re-link them.
+pub async fn unlink_extras(pool: &SqlitePool, media_id: i64) -> Result<(), AppError> {
+    sqlx::query("UPDATE media SET extra_of = NULL WHERE extra_of = ?")
+        .bind(media_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+type ExtraRow = (i64, Option<String>, String, String, Option<i32>);
+
+fn to_dto((id, title, extra_type, file_path, runtime): ExtraRow) -> ExtraDto {
+    let kind = ExtraType::parse(&extra_type);
+    ExtraDto {
+        id,
+        title,
+        extra_type: kind.as_str().to_string(),
+        type_label: kind.label().to_string(),
+        file_path,
+        runtime,
+    }
+}
+
+/// Extras of a movie (any of its versions).
+pub async fn list_for_media(pool: &SqlitePool, media_id: i64) -> Result<Vec<ExtraDto>, AppError> {
+    let rows: Vec<ExtraRow> = sqlx::query_as(
+        "SELECT id, title, extra_type, file_path, runtime FROM media
+         WHERE extra_of = (SELECT COALESCE(version_of, id) FROM media WHERE id = ?)
+         ORDER BY extra_type, title"
+    )
+    .bind(media_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(to_dto).collect())
+}
+
+/// Extras of a TV series.
+pub async fn list_for_series(pool: &SqlitePool, series_name: &str) -> Result<Vec<ExtraDto>, AppError> {
+    let rows: Vec<ExtraRow> = sqlx::query_as(
+        "SELECT id, title, extra_type, file_path, runtime FROM media
+         WHERE series_name = ? AND extra_type IS NOT NULL AND media_type = 'extra'
+         ORDER BY extra_type, title"
+    )
+    .bind(series_name)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(to_dto).collect())
+}
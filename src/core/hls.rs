@@ -0,0 +1,329 @@
+//! HLS - adaptive streaming with on-the-fly FFmpeg transcoding for sources a player can't
+//! decode itself (HEVC, DTS, ...).
+//!
+//! The master playlist offers the renditions of `LADDER` that don't exceed the source's
+//! height. Each rendition's playlist is a complete VOD list of `SEGMENT_SECONDS` segments
+//! computed from the media's duration; segments are encoded on demand by one FFmpeg session
+//! per media and rendition. A request far from what the session is producing (a seek)
+//! restarts FFmpeg at that segment. Sessions nobody has asked anything of for
+//! `IDLE_TIMEOUT` are killed and their files removed by `cleanup_idle_sessions`.
+//!
+//! Image subtitles (PGS, VobSub) players can't render are burned in: the playlists carry
+//! the selected track (`?subtitle=`) down to the segments, and each burned-in track gets its
+//! own session.
+
+use crate::core::disc::{self, DiscType};
+use crate::core::{media_parts, probe, remux};
+use crate::core::subtitles::BurnIn;
+use crate::error::AppError;
+use once_cell::sync::Lazy;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::process::Child;
+use tokio::sync::Mutex;
+
+pub const SEGMENT_SECONDS: f64 = 6.0;
+const TRANSCODE_DIR: &str = "transcodes";
+/// How far past the last finished segment a request may be before it counts as a seek
+const SEEK_THRESHOLD: u32 = 4;
+const SEGMENT_TIMEOUT: Duration = Duration::from_secs(60);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+const AUDIO_KBPS: u32 = 128;
+/// FFmpeg's own playlist of the segments it has finished
+const FFMPEG_PLAYLIST: &str = "ffmpeg.m3u8";
+
+/// One step of the bitrate ladder.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rendition {
+    pub name: &'static str,
+    pub height: u32,
+    pub video_kbps: u32,
+}
+
+pub const LADDER: &[Rendition] = &[
+    Rendition { name: "1080p", height: 1080, video_kbps: 6000 },
+    Rendition { name: "720p", height: 720, video_kbps: 3000 },
+    Rendition { name: "480p", height: 480, video_kbps: 1500 },
+    Rendition { name: "360p", height: 360, video_kbps: 800 },
+];
+
+pub fn rendition(name: &str) -> Option<Rendition> {
+    LADDER.iter().copied().find(|r| r.name == name)
+}
+
+struct Session {
+    child: Child,
+    dir: PathBuf,
+    start_segment: u32,
+    /// Tells a restarted session (after a seek) from the one a request started waiting on
+    generation: u64,
+    last_access: Instant,
+}
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Media, rendition and burned-in subtitle (`BurnIn::key`)
+type SessionKey = (i64, &'static str, Option<String>);
+
+static SESSIONS: Lazy<Mutex<HashMap<SessionKey, Session>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// What the transcoder needs to know about a media item.
+pub struct Source {
+    pub file_path: String,
+    pub disc_type: Option<String>,
+    pub duration: f64,
+    /// Video height, probed or from the filename ("1080p"), when known
+    pub height: Option<u32>,
+}
+
+#[derive(sqlx::FromRow)]
+struct SourceRow {
+    file_path: String,
+    disc_type: Option<String>,
+    duration: Option<f64>,
+    resolution: Option<String>,
+    height: Option<i64>,
+}
+
+/// Load a media item for transcoding. Its duration is probed (and stored) if not yet known.
+pub async fn source(pool: &SqlitePool, id: i64) -> Result<Source, AppError> {
+    let row: Option<SourceRow> = sqlx::query_as(
+        "SELECT file_path, disc_type, duration, resolution,
+                (SELECT MAX(height) FROM media_streams WHERE media_id = media.id AND stream_type = 'video') AS height
+         FROM media WHERE id = ?"
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    let SourceRow { file_path, disc_type, duration, resolution, height } = row.ok_or_else(|| AppError::NotFound(format!("Media with id {} not found", id)))?;
+
+    let duration = match duration {
+        Some(duration) => duration,
+        None => {
+            let path = Path::new(&file_path);
+            let probed = match disc_type.as_deref().and_then(DiscType::parse) {
+                Some(disc) => disc::duration(path, disc).await,
+                None => probe::duration(path).await,
+            }
+                .ok_or_else(|| AppError::Internal(format!("Could not determine the duration of {} (is FFprobe installed?)", file_path)))?;
+            media_parts::set_duration(pool, id, probed).await?;
+            probed
+        }
+    };
+    let height = height.map(|h| h as u32)
+        .or_else(|| resolution.and_then(|r| r.trim_end_matches(['p', 'i']).parse().ok()));
+    Ok(Source { file_path, disc_type, duration, height })
+}
+
+/// Master playlist: every rendition up to the source's height (at least the smallest one).
+/// `query` ("?subtitle=...", or empty) is passed on to the rendition playlists.
+pub fn master_playlist(source: &Source, query: &str) -> String {
+    let mut renditions: Vec<&Rendition> = LADDER.iter()
+        .filter(|r| source.height.is_none_or(|h| r.height <= h))
+        .collect();
+    if renditions.is_empty() {
+        renditions.extend(LADDER.last());
+    }
+
+    let mut playlist = String::from("#EXTM3U\n#EXT-X-VERSION:3\n");
+    for r in renditions {
+        let width = (r.height * 16 / 9).next_multiple_of(2);
+        let _ = writeln!(
+            playlist,
+            "#EXT-X-STREAM-INF:BANDWIDTH={},RESOLUTION={}x{},CODECS=\"avc1.4d401f,mp4a.40.2\"\n{}/index.m3u8{}",
+            (r.video_kbps + AUDIO_KBPS) * 1000, width, r.height, r.name, query
+        );
+    }
+    playlist
+}
+
+fn segment_count(duration: f64) -> u32 {
+    (duration / SEGMENT_SECONDS).ceil().max(1.0) as u32
+}
+
+/// A rendition's playlist: the whole timeline as segments, so players can seek anywhere.
+/// `query` is passed on to the segments.
+pub fn variant_playlist(source: &Source, query: &str) -> String {
+    let count = segment_count(source.duration);
+    let mut playlist = format!(
+        "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:{}\n#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n",
+        SEGMENT_SECONDS.ceil() as u32
+    );
+    for n in 0..count {
+        let length = (source.duration - n as f64 * SEGMENT_SECONDS).min(SEGMENT_SECONDS);
+        let _ = writeln!(playlist, "#EXTINF:{:.3},\nsegment_{}.ts{}", length, n, query);
+    }
+    playlist.push_str("#EXT-X-ENDLIST\n");
+    playlist
+}
+
+/// The segments FFmpeg has finished, from its own playlist.
+fn finished_segments(dir: &Path) -> Vec<u32> {
+    std::fs::read_to_string(dir.join(FFMPEG_PLAYLIST))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| l.strip_prefix("segment_")?.strip_suffix(".ts")?.parse().ok())
+        .collect()
+}
+
+fn spawn_ffmpeg(source: &Source, rendition: Rendition, burn_in: Option<&BurnIn>, dir: &Path, start_segment: u32) -> Result<Child, AppError> {
+    let path = Path::new(&source.file_path);
+    let input = match source.disc_type.as_deref().and_then(DiscType::parse) {
+        Some(disc) => disc::input_args(path, disc).ok_or_else(|| AppError::NotFound(format!("No playable title in {}", source.file_path)))?,
+        None => vec!["-i".to_string(), source.file_path.clone()],
+    };
+    let start = start_segment as f64 * SEGMENT_SECONDS;
+    let kbps = rendition.video_kbps;
+    let scale = format!("scale=-2:'min({},ih)'", rendition.height);
+
+    // Subtitles are overlaid at the source's size, where their pictures were drawn, then scaled
+    let mut video: Vec<String> = Vec::new();
+    match burn_in {
+        None => video.extend(["-map".into(), "0:v:0".into(), "-vf".into(), scale]),
+        Some(BurnIn::Stream(index)) => video.extend([
+            "-filter_complex".into(), format!("[0:v:0][0:{}]overlay,{}[v]", index, scale),
+            "-map".into(), "[v]".into(),
+        ]),
+        Some(BurnIn::VobSub(idx)) => video.extend([
+            "-ss".into(), format!("{:.3}", start), "-i".into(), idx.to_string_lossy().to_string(),
+            "-filter_complex".into(), format!("[0:v:0][1:s:0]overlay,{}[v]", scale),
+            "-map".into(), "[v]".into(),
+        ]),
+    }
+
+    tokio::process::Command::new(remux::ffmpeg_command())
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .arg("-ss").arg(format!("{:.3}", start))
+        .args(&input)
+        .args(&video)
+        .args(["-map", "0:a:0?"])
+        .args(["-c:v", "libx264", "-preset", "veryfast", "-pix_fmt", "yuv420p"])
+        .arg("-b:v").arg(format!("{}k", kbps))
+        .arg("-maxrate").arg(format!("{}k", kbps * 11 / 10))
+        .arg("-bufsize").arg(format!("{}k", kbps * 2))
+        // A keyframe at every segment boundary so segments cut exactly where the playlist says
+        .arg("-force_key_frames").arg(format!("expr:gte(t,n_forced*{})", SEGMENT_SECONDS))
+        .args(["-sc_threshold", "0"])
+        .args(["-c:a", "aac", "-ac", "2"])
+        .arg("-b:a").arg(format!("{}k", AUDIO_KBPS))
+        .args(["-f", "hls", "-hls_list_size", "0", "-hls_segment_type", "mpegts"])
+        .arg("-hls_time").arg(SEGMENT_SECONDS.to_string())
+        .arg("-start_number").arg(start_segment.to_string())
+        .arg("-output_ts_offset").arg(format!("{:.3}", start))
+        .arg("-hls_segment_filename").arg(dir.join("segment_%d.ts"))
+        .arg(dir.join(FFMPEG_PLAYLIST))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| AppError::Internal(format!("Failed to start FFmpeg: {}", e)))
+}
+
+/// Path of segment `n` of a rendition, with `burn_in` overlaid, transcoding it first if needed.
+pub async fn segment(id: i64, source: &Source, rendition: Rendition, burn_in: Option<&BurnIn>, n: u32) -> Result<PathBuf, AppError> {
+    if n >= segment_count(source.duration) {
+        return Err(AppError::NotFound(format!("Segment {} not found", n)));
+    }
+    let burn_in_key = burn_in.map(BurnIn::key);
+    let dir = Path::new(TRANSCODE_DIR).join(match &burn_in_key {
+        Some(subtitle) => format!("{}-{}-{}", id, rendition.name, subtitle),
+        None => format!("{}-{}", id, rendition.name),
+    });
+    let key = (id, rendition.name, burn_in_key);
+    let segment_path = dir.join(format!("segment_{}.ts", n));
+
+    let mut generation = {
+        let mut sessions = SESSIONS.lock().await;
+        let finished = finished_segments(&dir);
+        let running = match sessions.get_mut(&key) {
+            Some(session) => {
+                session.last_access = Instant::now();
+                if finished.contains(&n) {
+                    return Ok(segment_path);
+                }
+                let produced = finished.iter().max().copied().unwrap_or(session.start_segment);
+                let alive = matches!(session.child.try_wait(), Ok(None));
+                alive && n >= session.start_segment && n <= produced + SEEK_THRESHOLD
+            }
+            None => false,
+        };
+
+        if !running {
+            // Start (or restart, after a seek) at the requested segment from a clean directory
+            if let Some(mut old) = sessions.remove(&key) {
+                let _ = old.child.kill().await;
+            }
+            let _ = tokio::fs::remove_dir_all(&dir).await;
+            tokio::fs::create_dir_all(&dir).await
+                .map_err(|e| AppError::Internal(format!("Could not create {}: {}", dir.display(), e)))?;
+            let subtitle = key.2.as_ref().map(|k| format!(", burning in subtitle {}", k)).unwrap_or_default();
+            tracing::info!("Transcoding media {} at {} from segment {}{}", id, rendition.name, n, subtitle);
+            let child = spawn_ffmpeg(source, rendition, burn_in, &dir, n)?;
+            let generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+            sessions.insert(key.clone(), Session { child, dir: dir.clone(), start_segment: n, generation, last_access: Instant::now() });
+        }
+        sessions.get(&key).map_or(0, |s| s.generation)
+    };
+
+    let deadline = Instant::now() + SEGMENT_TIMEOUT;
+    loop {
+        if finished_segments(&dir).contains(&n) {
+            return Ok(segment_path);
+        }
+        {
+            let mut sessions = SESSIONS.lock().await;
+            let Some(session) = sessions.get_mut(&key) else {
+                return Err(AppError::Internal("Transcode session ended".to_string()));
+            };
+            session.last_access = Instant::now();
+            if session.generation != generation {
+                // Another client's seek restarted the transcode; it only gets here if it started before `n`
+                if n < session.start_segment {
+                    return Err(AppError::Internal(format!(
+                        "Transcode restarted at segment {} before segment {} was ready", session.start_segment, n
+                    )));
+                }
+                generation = session.generation;
+            }
+            if let Ok(Some(status)) = session.child.try_wait() {
+                // FFmpeg may finish the last segments and exit between two polls
+                if finished_segments(&dir).contains(&n) {
+                    return Ok(segment_path);
+                }
+                return Err(AppError::Internal(format!("FFmpeg exited ({}) before segment {} was ready", status, n)));
+            }
+        }
+        if Instant::now() > deadline {
+            return Err(AppError::Internal(format!("Timed out waiting for segment {}", n)));
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+}
+
+/// Background task: stop idle transcodes and delete their segments. Leftovers of a previous
+/// run are removed on start.
+pub async fn cleanup_idle_sessions() {
+    let _ = tokio::fs::remove_dir_all(TRANSCODE_DIR).await;
+    let mut interval = tokio::time::interval(Duration::from_secs(30));
+    loop {
+        interval.tick().await;
+        let mut sessions = SESSIONS.lock().await;
+        let idle: Vec<_> = sessions.iter()
+            .filter(|(_, s)| s.last_access.elapsed() > IDLE_TIMEOUT)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in idle {
+            if let Some(mut session) = sessions.remove(&key) {
+                tracing::info!("Stopping idle transcode of media {} at {}", key.0, key.1);
+                let _ = session.child.kill().await;
+                let _ = tokio::fs::remove_dir_all(&session.dir).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Language code lookups between ISO 639-1 ("de"), ISO 639-2/T ("deu") and ISO 639-2/B ("ger").
+
+pub struct Language {
+    /// `None` for the few languages that only have three-letter codes
+    pub iso639_1: Option<&'static str>,
+    pub iso639_2t: &'static str,
+    pub iso639_2b: &'static str,
+    /// English name
+    pub name: &'static str,
+}
+
+const fn lang(iso639_1: Option<&'static str>, iso639_2t: &'static str, iso639_2b: &'static str, name: &'static str) -> Language {
+    Language { iso639_1, iso639_2t, iso639_2b, name }
+}
+
+impl Language {
+    /// The shortest code: ISO 639-1, else ISO 639-2/T.
+    pub fn code(&self) -> &'static str {
+        self.iso639_1.unwrap_or(self.iso639_2t)
+    }
+}
+
+// Every ISO 639-1 language, and three-letter-only languages subtitle sites use
+static LANGUAGES: &[Language] = &[
+    lang(Some("aa"), "aar", "aar", "Afar"),
+    lang(Some("ab"), "abk", "abk", "Abkhazian"),
+    lang(Some("ae"), "ave", "ave", "Avestan"),
+    lang(Some("af"), "afr", "afr", "Afrikaans"),
+    lang(Some("ak"), "aka", "aka", "Akan"),
+    lang(Some("am"), "amh", "amh", "Amharic"),
+    lang(Some("an"), "arg", "arg", "Aragonese"),
+    lang(Some("ar"), "ara", "ara", "Arabic"),
+    lang(Some("as"), "asm", "asm", "Assamese"),
+    lang(Some("av"), "ava", "ava", "Avaric"),
+    lang(Some("ay"), "aym", "aym", "Aymara"),
+    lang(Some("az"), "aze", "aze", "Azerbaijani"),
+    lang(Some("ba"), "bak", "bak", "Bashkir"),
+    lang(Some("be"), "bel", "bel", "Belarusian"),
+    lang(Some("bg"), "bul", "bul", "Bulgarian"),
+    lang(Some("bh"), "bih", "bih", "Bihari"),
+    lang(Some("bi"), "bis", "bis", "Bislama"),
+    lang(Some("bm"), "bam", "bam", "Bambara"),
+    lang(Some("bn"), "ben", "ben", "Bengali"),
+    lang(Some("bo"), "bod", "tib", "Tibetan"),
+    lang(Some("br"), "bre", "bre", "Breton"),
+    lang(Some("bs"), "bos", "bos", "Bosnian"),
+    lang(Some("ca"), "cat", "cat", "Catalan"),
+    lang(Some("ce"), "che", "che", "Chechen"),
+    lang(Some("ch"), "cha", "cha", "Chamorro"),
+    lang(Some("co"), "cos", "cos", "Corsican"),
+    lang(Some("cr"), "cre", "cre", "Cree"),
+    lang(Some("cs"), "ces", "cze", "Czech"),
+    lang(Some("cu"), "chu", "chu", "Church Slavonic"),
+    lang(Some("cv"), "chv", "chv", "Chuvash"),
+    lang(Some("cy"), "cym", "wel", "Welsh"),
+    lang(Some("da"), "dan", "dan", "Danish"),
+    lang(Some("de"), "deu", "ger", "German"),
+    lang(Some("dv"), "div", "div", "Divehi"),
+    lang(Some("dz"), "dzo", "dzo", "Dzongkha"),
+    lang(Some("ee"), "ewe", "ewe", "Ewe"),
+    lang(Some("el"), "ell", "gre", "Greek"),
+    lang(Some("en"), "eng", "eng", "English"),
+    lang(Some("eo"), "epo", "epo", "Esperanto"),
+    lang(Some("es"), "spa", "spa", "Spanish"),
+    lang(Some("et"), "est", "est", "Estonian"),
+    lang(Some("eu"), "eus", "baq", "Basque"),
+    lang(Some("fa"), "fas", "per", "Persian"),
+    lang(Some("ff"), "ful", "ful", "Fulah"),
+    lang(Some("fi"), "fin", "fin", "Finnish"),
+    lang(Some("fj"), "fij", "fij", "Fijian"),
+    lang(Some("fo"), "fao", "fao", "Faroese"),
+    lang(Some("fr"), "fra", "fre", "French"),
+    lang(Some("fy"), "fry", "fry", "Western Frisian"),
+    lang(Some("ga"), "gle", "gle", "Irish"),
+    lang(Some("gd"), "gla", "gla", "Scottish Gaelic"),
+    lang(Some("gl"), "glg", "glg", "Galician"),
+    lang(Some("gn"), "grn", "grn", "Guarani"),
+    lang(Some("gu"), "guj", "guj", "Gujarati"),
+    lang(Some("gv"), "glv", "glv", "Manx"),
+    lang(Some("ha"), "hau", "hau", "Hausa"),
+    lang(Some("he"), "heb", "heb", "Hebrew"),
+    lang(Some("hi"), "hin", "hin", "Hindi"),
+    lang(Some("ho"), "hmo", "hmo", "Hiri Motu"),
+    lang(Some("hr"), "hrv", "hrv", "Croatian"),
+    lang(Some("ht"), "hat", "hat", "Haitian Creole"),
+    lang(Some("hu"), "hun", "hun", "Hungarian"),
+    lang(Some("hy"), "hye", "arm", "Armenian"),
+    lang(Some("hz"), "her", "her", "Herero"),
+    lang(Some("ia"), "ina", "ina", "Interlingua"),
+    lang(Some("id"), "ind", "ind", "Indonesian"),
+    lang(Some("ie"), "ile", "ile", "Interlingue"),
+    lang(Some("ig"), "ibo", "ibo", "Igbo"),
+    lang(Some("ii"), "iii", "iii", "Sichuan Yi"),
+    lang(Some("ik"), "ipk", "ipk", "Inupiaq"),
+    lang(Some("io"), "ido", "ido", "Ido"),
+    lang(Some("is"), "isl", "ice", "Icelandic"),
+    lang(Some("it"), "ita", "ita", "Italian"),
+    lang(Some("iu"), "iku", "iku", "Inuktitut"),
+    lang(Some("ja"), "jpn", "jpn", "Japanese"),
+    lang(Some("jv"), "jav", "jav", "Javanese"),
+    lang(Some("ka"), "kat", "geo", "Georgian"),
+    lang(Some("kg"), "kon", "kon", "Kongo"),
+    lang(Some("ki"), "kik", "kik", "Kikuyu"),
+    lang(Some("kj"), "kua", "kua", "Kuanyama"),
+    lang(Some("kk"), "kaz", "kaz", "Kazakh"),
+    lang(Some("kl"), "kal", "kal", "Greenlandic"),
+    lang(Some("km"), "khm", "khm", "Khmer"),
+    lang(Some("kn"), "kan", "kan", "Kannada"),
+    lang(Some("ko"), "kor", "kor", "Korean"),
+    lang(Some("kr"), "kau", "kau", "Kanuri"),
+    lang(Some("ks"), "kas", "kas", "Kashmiri"),
+    lang(Some("ku"), "kur", "kur", "Kurdish"),
+    lang(Some("kv"), "kom", "kom", "Komi"),
+    lang(Some("kw"), "cor", "cor", "Cornish"),
+    lang(Some("ky"), "kir", "kir", "Kyrgyz"),
+    lang(Some("la"), "lat", "lat", "Latin"),
+    lang(Some("lb"), "ltz", "ltz", "Luxembourgish"),
+    lang(Some("lg"), "lug", "lug", "Ganda"),
+    lang(Some("li"), "lim", "lim", "Limburgish"),
+    lang(Some("ln"), "lin", "lin", "Lingala"),
+    lang(Some("lo"), "lao", "lao", "Lao"),
+    lang(Some("lt"), "lit", "lit", "Lithuanian"),
+    lang(Some("lu"), "lub", "lub", "Luba-Katanga"),
+    lang(Some("lv"), "lav", "lav", "Latvian"),
+    lang(Some("mg"), "mlg", "mlg", "Malagasy"),
+    lang(Some("mh"), "mah", "mah", "Marshallese"),
+    lang(Some("mi"), "mri", "mao", "Maori"),
+    lang(Some("mk"), "mkd", "mac", "Macedonian"),
+    lang(Some("ml"), "mal", "mal", "Malayalam"),
+    lang(Some("mn"), "mon", "mon", "Mongolian"),
+    lang(Some("mr"), "mar", "mar", "Marathi"),
+    lang(Some("ms"), "msa", "may", "Malay"),
+    lang(Some("mt"), "mlt", "mlt", "Maltese"),
+    lang(Some("my"), "mya", "bur", "Burmese"),
+    lang(Some("na"), "nau", "nau", "Nauru"),
+    lang(Some("nb"), "nob", "nob", "Norwegian Bokmål"),
+    lang(Some("nd"), "nde", "nde", "North Ndebele"),
+    lang(Some("ne"), "nep", "nep", "Nepali"),
+    lang(Some("ng"), "ndo", "ndo", "Ndonga"),
+    lang(Some("nl"), "nld", "dut", "Dutch"),
+    lang(Some("nn"), "nno", "nno", "Norwegian Nynorsk"),
+    lang(Some("no"), "nor", "nor", "Norwegian"),
+    lang(Some("nr"), "nbl", "nbl", "South Ndebele"),
+    lang(Some("nv"), "nav", "nav", "Navajo"),
+    lang(Some("ny"), "nya", "nya", "Chichewa"),
+    lang(Some("oc"), "oci", "oci", "Occitan"),
+    lang(Some("oj"), "oji", "oji", "Ojibwa"),
+    lang(Some("om"), "orm", "orm", "Oromo"),
+    lang(Some("or"), "ori", "ori", "Oriya"),
+    lang(Some("os"), "oss", "oss", "Ossetian"),
+    lang(Some("pa"), "pan", "pan", "Punjabi"),
+    lang(Some("pi"), "pli", "pli", "Pali"),
+    lang(Some("pl"), "pol", "pol", "Polish"),
+    lang(Some("ps"), "pus", "pus", "Pashto"),
+    lang(Some("pt"), "por", "por", "Portuguese"),
+    lang(Some("qu"), "que", "que", "Quechua"),
+    lang(Some("rm"), "roh", "roh", "Romansh"),
+    lang(Some("rn"), "run", "run", "Rundi"),
+    lang(Some("ro"), "ron", "rum", "Romanian"),
+    lang(Some("ru"), "rus", "rus", "Russian"),
+    lang(Some("rw"), "kin", "kin", "Kinyarwanda"),
+    lang(Some("sa"), "san", "san", "Sanskrit"),
+    lang(Some("sc"), "srd", "srd", "Sardinian"),
+    lang(Some("sd"), "snd", "snd", "Sindhi"),
+    lang(Some("se"), "sme", "sme", "Northern Sami"),
+    lang(Some("sg"), "sag", "sag", "Sango"),
+    lang(Some("si"), "sin", "sin", "Sinhala"),
+    lang(Some("sk"), "slk", "slo", "Slovak"),
+    lang(Some("sl"), "slv", "slv", "Slovenian"),
+    lang(Some("sm"), "smo", "smo", "Samoan"),
+    lang(Some("sn"), "sna", "sna", "Shona"),
+    lang(Some("so"), "som", "som", "Somali"),
+    lang(Some("sq"), "sqi", "alb", "Albanian"),
+    lang(Some("sr"), "srp", "srp", "Serbian"),
+    lang(Some("ss"), "ssw", "ssw", "Swati"),
+    lang(Some("st"), "sot", "sot", "Southern Sotho"),
+    lang(Some("su"), "sun", "sun", "Sundanese"),
+    lang(Some("sv"), "swe", "swe", "Swedish"),
+    lang(Some("sw"), "swa", "swa", "Swahili"),
+    lang(Some("ta"), "tam", "tam", "Tamil"),
+    lang(Some("te"), "tel", "tel", "Telugu"),
+    lang(Some("tg"), "tgk", "tgk", "Tajik"),
+    lang(Some("th"), "tha", "tha", "Thai"),
+    lang(Some("ti"), "tir", "tir", "Tigrinya"),
+    lang(Some("tk"), "tuk", "tuk", "Turkmen"),
+    lang(Some("tl"), "tgl", "tgl", "Tagalog"),
+    lang(Some("tn"), "tsn", "tsn", "Tswana"),
+    lang(Some("to"), "ton", "ton", "Tongan"),
+    lang(Some("tr"), "tur", "tur", "Turkish"),
+    lang(Some("ts"), "tso", "tso", "Tsonga"),
+    lang(Some("tt"), "tat", "tat", "Tatar"),
+    lang(Some("tw"), "twi", "twi", "Twi"),
+    lang(Some("ty"), "tah", "tah", "Tahitian"),
+    lang(Some("ug"), "uig", "uig", "Uyghur"),
+    lang(Some("uk"), "ukr", "ukr", "Ukrainian"),
+    lang(Some("ur"), "urd", "urd", "Urdu"),
+    lang(Some("uz"), "uzb", "uzb", "Uzbek"),
+    lang(Some("ve"), "ven", "ven", "Venda"),
+    lang(Some("vi"), "vie", "vie", "Vietnamese"),
+    lang(Some("vo"), "vol", "vol", "Volapük"),
+    lang(Some("wa"), "wln", "wln", "Walloon"),
+    lang(Some("wo"), "wol", "wol", "Wolof"),
+    lang(Some("xh"), "xho", "xho", "Xhosa"),
+    lang(Some("yi"), "yid", "yid", "Yiddish"),
+    lang(Some("yo"), "yor", "yor", "Yoruba"),
+    lang(Some("za"), "zha", "zha", "Zhuang"),
+    lang(Some("zh"), "zho", "chi", "Chinese"),
+    lang(Some("zu"), "zul", "zul", "Zulu"),
+    // No ISO 639-1 code
+    lang(None, "fil", "fil", "Filipino"),
+];
+
+/// Find a language by any of its codes. Region suffixes ("de-DE", "pt_BR") are ignored.
+pub fn lookup(code: &str) -> Option<&'static Language> {
+    let base = code.split(['-', '_']).next()?.trim().to_lowercase();
+    LANGUAGES.iter().find(|l| l.iso639_1 == Some(base.as_str()) || l.iso639_2t == base || l.iso639_2b == base)
+}
+
+/// Convert any supported code to ISO 639-2/T (the form TheTVDB uses).
+pub fn to_iso639_2(code: &str) -> Option<&'static str> {
+    lookup(code).map(|l| l.iso639_2t)
+}
+
+/// Find a language by its English name ("german", "Portuguese").
+pub fn lookup_name(name: &str) -> Option<&'static Language> {
+    LANGUAGES.iter().find(|l| l.name.eq_ignore_ascii_case(name.trim()))
+}
+
+/// A code as ISO 639-1 (or ISO 639-2/T without one) with its region, if any: "eng" -> "en",
+/// "pt_br" -> "pt-BR".
+pub fn normalize(code: &str) -> Option<String> {
+    let language = lookup(code)?;
+    let region = code.split(['-', '_']).nth(1).map(str::trim).filter(|r| r.len() == 2 && r.chars().all(|c| c.is_ascii_alphabetic()));
+    Some(match region {
+        Some(region) => format!("{}-{}", language.code(), region.to_uppercase()),
+        None => language.code().to_string(),
+    })
+}
+
+/// English name of a code, with its region: "de" -> "German", "pt-BR" -> "Portuguese (BR)".
+pub fn display_name(code: &str) -> Option<String> {
+    let language = lookup(code)?;
+    Some(match normalize(code)?.split_once('-') {
+        Some((_, region)) => format!("{} ({})", language.name, region),
+        None => language.name.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_of_either_part_of_iso_639_2_find_the_language() {
+        for (code, expected) in [("te", "te"), ("tel", "te"), ("sqi", "sq"), ("alb", "sq"), ("mkd", "mk"), ("mac", "mk"), ("bn", "bn"), ("fil", "fil"), ("ZH_tw", "zh-TW")] {
+            assert_eq!(normalize(code).as_deref(), Some(expected), "{}", code);
+        }
+        assert_eq!(normalize("xx"), None);
+        assert_eq!(to_iso639_2("ger"), Some("deu"));
+        assert_eq!(to_iso639_2("eu"), Some("eus"));
+        assert_eq!(display_name("ur-PK").as_deref(), Some("Urdu (PK)"));
+        assert_eq!(lookup_name("malayalam").map(Language::code), Some("ml"));
+        assert_eq!(lookup_name("Filipino").map(Language::code), Some("fil"));
+    }
+}
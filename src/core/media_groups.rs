@@ -0,0 +1,112 @@
+//! Media Groups - the bookkeeping shared by version groups (`core::media_versions`) and part
+//! stacks (`core::media_parts`).
+//!
+//! Both link secondary rows to a primary row through a column of `media` (`version_of` or
+//! `part_of`); the primary carries the metadata and the playback progress of the whole group.
+
+use crate::core::media_versions;
+use crate::error::AppError;
+use sqlx::SqlitePool;
+use std::path::Path;
+
+/// The column of `media` that links a row to the primary of its group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grouping {
+    Versions,
+    Parts,
+}
+
+impl Grouping {
+    fn column(self) -> &'static str {
+        match self {
+            Grouping::Versions => "version_of",
+            Grouping::Parts => "part_of",
+        }
+    }
+
+    // Rows that may be the primary of a new member
+    fn candidate_filter(self) -> &'static str {
+        match self {
+            Grouping::Versions => "version_of IS NULL AND series_name IS NULL",
+            Grouping::Parts => "part_of IS NULL AND part_number IS NOT NULL",
+        }
+    }
+
+    // Order in which members take over as primary
+    fn succession(self) -> &'static str {
+        match self {
+            Grouping::Versions => "id",
+            Grouping::Parts => "part_number, id",
+        }
+    }
+}
+
+/// The oldest possible primary in the same library and folder as `path` whose file stem
+/// satisfies `matches`.
+pub async fn find_primary(pool: &SqlitePool, grouping: Grouping, library_id: i64, path: &Path, matches: impl Fn(&str) -> bool) -> Result<Option<i64>, AppError> {
+    let Some(dir) = path.parent() else { return Ok(None) };
+    let dir_str = dir.to_string_lossy().to_string();
+
+    let candidates: Vec<(i64, String)> = sqlx::query_as(&format!(
+        "SELECT id, file_path FROM media
+         WHERE library_id = ? AND {} AND file_path != ? AND substr(file_path, 1, length(?)) = ?
+         ORDER BY id",
+        grouping.candidate_filter()
+    ))
+    .bind(library_id)
+    .bind(path.to_string_lossy())
+    .bind(&dir_str)
+    .bind(&dir_str)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(candidates.into_iter()
+        .find(|(_, file_path)| {
+            let other = Path::new(file_path);
+            other.parent() == Some(dir) && other.file_stem().is_some_and(|s| matches(&s.to_string_lossy()))
+        })
+        .map(|(id, _)| id))
+}
+
+/// Whether `id` is a movie that belongs to no group or stack yet.
+pub async fn is_ungrouped(pool: &SqlitePool, id: i64) -> Result<bool, AppError> {
+    let ungrouped: Option<i64> = sqlx::query_scalar(
+        "SELECT id FROM media WHERE id = ? AND version_of IS NULL AND part_of IS NULL AND extra_type IS NULL"
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(ungrouped.is_some())
+}
+
+/// Make `id`, with any members of its own, a member of `primary_id`'s group and give it the
+/// primary's metadata.
+pub async fn join(pool: &SqlitePool, grouping: Grouping, primary_id: i64, id: i64) -> Result<(), AppError> {
+    let column = grouping.column();
+
+    // The primary's progress wins if both were watched
+    let mut tx = pool.begin().await?;
+    sqlx::query(&format!("UPDATE media SET {column} = ? WHERE id = ? OR {column} = ?")).bind(primary_id).bind(id).bind(id).execute(&mut *tx).await?;
+    sqlx::query("UPDATE OR IGNORE playback_progress SET media_id = ? WHERE media_id = ?").bind(primary_id).bind(id).execute(&mut *tx).await?;
+    sqlx::query("DELETE FROM playback_progress WHERE media_id = ?").bind(id).execute(&mut *tx).await?;
+    tx.commit().await?;
+    media_versions::copy_metadata(pool, primary_id, id).await
+}
+
+/// Before a primary row is deleted, hand its role (and the shared progress) to the next member
+/// so the group doesn't vanish from listings.
+pub async fn promote_next(pool: &SqlitePool, grouping: Grouping, primary_id: i64) -> Result<(), AppError> {
+    let column = grouping.column();
+    let next: Option<i64> = sqlx::query_scalar(&format!("SELECT id FROM media WHERE {column} = ? ORDER BY {} LIMIT 1", grouping.succession()))
+        .bind(primary_id)
+        .fetch_optional(pool)
+        .await?;
+    let Some(next) = next else { return Ok(()) };
+
+    let mut tx = pool.begin().await?;
+    sqlx::query(&format!("UPDATE media SET {column} = NULL WHERE id = ?")).bind(next).execute(&mut *tx).await?;
+    sqlx::query(&format!("UPDATE media SET {column} = ? WHERE {column} = ?")).bind(next).bind(primary_id).execute(&mut *tx).await?;
+    sqlx::query("UPDATE OR IGNORE playback_progress SET media_id = ? WHERE media_id = ?").bind(next).bind(primary_id).execute(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,242 @@
+//! Media Parts - stacks a movie split across several files (`Movie.cd1.avi`, `Movie.cd2.avi`,
+//! `Movie - part1.mkv`, ...) into one logical item.
+//!
+//! Like versions, the first part scanned is the primary row and carries the metadata and the
+//! playback progress; the other parts point at it through `media.part_of` and are hidden from
+//! listings. Every part stores its `part_number` and, once known, its `duration` so a position
+//! on the combined timeline can be mapped onto a part and back.
+//!
+//! "Part"/"pt" markers also end the titles of separate films ("Deathly Hallows Part 1"), so
+//! those only stack within a movie folder, never loose in the library folder.
+
+use crate::core::media_groups::{self, Grouping};
+use crate::error::AppError;
+use crate::models::media::MediaPartDto;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use sqlx::{FromRow, SqlitePool};
+use std::path::Path;
+
+// "cd1", "part 2", "pt.3", "disc1" at the end of the stem
+static RE_PART: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)^(.*?)[\s._\-\[(]+(cd|dvd|part|pt|disc|disk)[\s._\-]*(\d{1,2})[\])]?$").unwrap());
+static RE_NON_ALNUM: Lazy<Regex> = Lazy::new(|| Regex::new(r"[^\p{L}\p{N}]+").unwrap());
+
+/// What a filename says about which part of a stacked movie it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartInfo {
+    /// The stem without the part marker, used as the title and search term
+    pub title: String,
+    /// Normalized title shared by every part of the movie
+    pub key: String,
+    pub part_number: i32,
+    /// Marked "part"/"pt", which may just as well be a film's title
+    pub ambiguous: bool,
+}
+
+/// Read the part marker from a file stem. Stems that are nothing but a marker ("CD1") don't stack.
+pub fn part_info(stem: &str) -> Option<PartInfo> {
+    let caps = RE_PART.captures(stem)?;
+    let title = caps[1].trim_end_matches(|c: char| c.is_whitespace() || "._-".contains(c)).to_string();
+    let part_number = caps[3].parse::<i32>().ok().filter(|n| *n > 0)?;
+    let key = RE_NON_ALNUM.replace_all(&title.to_lowercase(), " ").trim().to_string();
+    if key.is_empty() {
+        return None;
+    }
+    let ambiguous = ["part", "pt"].contains(&caps[2].to_lowercase().as_str());
+    Some(PartInfo { title, key, part_number, ambiguous })
+}
+
+/// The part a movie file in a library is, if it stacks with its siblings.
+pub fn detect(path: &Path, stem: &str, library_path: &str) -> Option<PartInfo> {
+    part_info(stem).filter(|part| !part.ambiguous || path.parent() != Some(Path::new(library_path)))
+}
+
+/// Find the primary row of the stack `path` belongs to: a part in the same library and folder
+/// whose filename gives the same key.
+pub async fn find_stack(pool: &SqlitePool, library_id: i64, path: &Path, key: &str) -> Result<Option<i64>, AppError> {
+    media_groups::find_primary(pool, Grouping::Parts, library_id, path, |stem| part_info(stem).is_some_and(|p| p.key == key)).await
+}
+
+/// Stack a part scanned before parts were recognized: store its part number, and make it (with
+/// any parts of its own) part of the stack of a sibling. Returns the stack's primary.
+pub async fn stack_existing(pool: &SqlitePool, id: i64, library_id: i64, path: &Path, part: &PartInfo) -> Result<Option<i64>, AppError> {
+    sqlx::query("UPDATE media SET part_number = COALESCE(part_number, ?) WHERE id = ?")
+        .bind(part.part_number)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    if !media_groups::is_ungrouped(pool, id).await? {
+        return Ok(None);
+    }
+    let Some(primary_id) = find_stack(pool, library_id, path, &part.key).await? else { return Ok(None) };
+    media_groups::join(pool, Grouping::Parts, primary_id, id).await?;
+    Ok(Some(primary_id))
+}
+
+/// Take a file that no longer counts as a part out of its stack, along with any parts stacked
+/// onto it; they are unstacked or restacked when scanned. Returns whether it was a part.
+pub async fn unstack(pool: &SqlitePool, id: i64) -> Result<bool, AppError> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("UPDATE media SET part_of = NULL WHERE part_of = ?").bind(id).execute(&mut *tx).await?;
+    let updated = sqlx::query("UPDATE media SET part_of = NULL, part_number = NULL WHERE id = ? AND part_number IS NOT NULL")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(updated.rows_affected() > 0)
+}
+
+/// Record a file's duration, e.g. from FFprobe or a player reporting it.
+pub async fn set_duration(pool: &SqlitePool, id: i64, duration: f64) -> Result<(), AppError> {
+    sqlx::query("UPDATE media SET duration = ? WHERE id = ?")
+        .bind(duration)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[derive(FromRow)]
+struct PartRow {
+    id: i64,
+    file_path: String,
+    part_number: i32,
+    duration: Option<f64>,
+}
+
+/// The parts of `id`'s stack in playback order, each with its offset on the combined timeline.
+/// Empty for media that isn't stacked. Offsets after a part of unknown duration are unknown too.
+pub async fn list_parts(pool: &SqlitePool, id: i64) -> Result<Vec<MediaPartDto>, AppError> {
+    let rows: Vec<PartRow> = sqlx::query_as(
+        "SELECT id, file_path, part_number, duration FROM media
+         WHERE part_number IS NOT NULL AND COALESCE(part_of, id) = (SELECT COALESCE(part_of, id) FROM media WHERE id = ?)
+         ORDER BY part_number, id"
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut offset = Some(0.0);
+    Ok(rows.into_iter().map(|row| {
+        let part = MediaPartDto {
+            id: row.id,
+            file_path: row.file_path,
+            part_number: row.part_number,
+            duration: row.duration,
+            offset,
+        };
+        offset = offset.zip(row.duration).map(|(o, d)| o + d);
+        part
+    }).collect())
+}
+
+/// Combined duration of a stack, if every part's duration is known.
+pub fn total_duration(parts: &[MediaPartDto]) -> Option<f64> {
+    parts.iter().map(|p| p.duration).sum()
+}
+
+/// The part playing at `position` seconds into the combined timeline, and the position within it.
+pub fn locate(parts: &[MediaPartDto], position: f64) -> Option<(&MediaPartDto, f64)> {
+    let mut found = None;
+    for part in parts {
+        let Some(offset) = part.offset else { break };
+        if position >= offset {
+            found = Some((part, position - offset));
+        }
+    }
+    found.or_else(|| parts.first().map(|p| (p, position)))
+}
+
+/// Before a stack's primary row is deleted, hand its role (and the progress) to the next part.
+pub async fn promote_next_part(pool: &SqlitePool, primary_id: i64) -> Result<(), AppError> {
+    media_groups::promote_next(pool, Grouping::Parts, primary_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(offset: Option<f64>, duration: Option<f64>) -> MediaPartDto {
+        MediaPartDto { id: 0, file_path: String::new(), part_number: 1, duration, offset }
+    }
+
+    #[test]
+    fn part_info_reads_the_marker_and_keeps_the_title() {
+        let info = part_info("Heat (1995) - cd2").unwrap();
+        assert_eq!((info.title.as_str(), info.key.as_str(), info.part_number, info.ambiguous), ("Heat (1995)", "heat 1995", 2, false));
+        let info = part_info("Heat.1995.[Disc 1]").unwrap();
+        assert_eq!((info.key.as_str(), info.part_number), ("heat 1995", 1));
+        let info = part_info("Deathly Hallows Part 1").unwrap();
+        assert_eq!((info.title.as_str(), info.part_number, info.ambiguous), ("Deathly Hallows", 1, true));
+
+        // A bare marker, part zero or a number that doesn't end the stem don't stack
+        assert_eq!(part_info("CD1"), None);
+        assert_eq!(part_info("Heat cd0"), None);
+        assert_eq!(part_info("Heat cd1 extended"), None);
+        assert_eq!(part_info("Heat (1995)"), None);
+
+        // "Part" only stacks inside a movie folder
+        let loose = Path::new("/movies/Deathly Hallows Part 1.mkv");
+        assert_eq!(detect(loose, "Deathly Hallows Part 1", "/movies"), None);
+        let foldered = Path::new("/movies/Deathly Hallows/Deathly Hallows Part 1.mkv");
+        assert!(detect(foldered, "Deathly Hallows Part 1", "/movies").is_some());
+    }
+
+    #[test]
+    fn locate_maps_a_combined_position_onto_a_part() {
+        let parts = [part(Some(0.0), Some(100.0)), part(Some(100.0), Some(50.0)), part(Some(150.0), None)];
+        let at = |position| locate(&parts, position).map(|(p, within)| (p.offset, within));
+        assert_eq!(at(0.0), Some((Some(0.0), 0.0)));
+        assert_eq!(at(99.5), Some((Some(0.0), 99.5)));
+        assert_eq!(at(100.0), Some((Some(100.0), 0.0)));
+        assert_eq!(at(400.0), Some((Some(150.0), 250.0)));
+
+        // Past a part of unknown duration the later parts can't be told apart
+        let unknown = [part(Some(0.0), None), part(None, Some(50.0))];
+        assert_eq!(locate(&unknown, 500.0).map(|(p, within)| (p.offset, within)), Some((Some(0.0), 500.0)));
+        assert!(locate(&[], 10.0).is_none());
+    }
+
+    #[tokio::test]
+    async fn list_parts_offsets_follow_the_part_order_and_survive_promotion() {
+        let dir = std::env::temp_dir().join(format!("vortex-media-parts-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let pool = crate::db::connect(&format!("sqlite:{}", dir.join("test.db").display())).await;
+        sqlx::query("INSERT INTO libraries (name, path, library_type) VALUES ('Movies', '/movies', 'movies')").execute(&pool).await.unwrap();
+        // Inserted out of order: cd2 was scanned first and is the primary
+        for (path, part_of, part_number, duration) in [
+            ("/movies/Heat/Heat cd2.mkv", None, Some(2), Some(50.0)),
+            ("/movies/Heat/Heat cd1.mkv", Some(1), Some(1), Some(100.0)),
+            ("/movies/Heat/Heat cd3.mkv", None, None, None),
+        ] {
+            sqlx::query("INSERT INTO media (file_path, title, library_id, media_type, part_of, part_number, duration) VALUES (?, 'Heat', 1, 'movie', ?, ?, ?)")
+                .bind(path).bind(part_of).bind(part_number).bind(duration).execute(&pool).await.unwrap();
+        }
+
+        // cd3 was scanned before parts were recognized
+        let cd3 = Path::new("/movies/Heat/Heat cd3.mkv");
+        let stacked = stack_existing(&pool, 3, 1, cd3, &part_info("Heat cd3").unwrap()).await.unwrap();
+        assert_eq!(stacked, Some(1));
+
+        let parts = list_parts(&pool, 3).await.unwrap();
+        let layout: Vec<(i64, i32, Option<f64>)> = parts.iter().map(|p| (p.id, p.part_number, p.offset)).collect();
+        assert_eq!(layout, [(2, 1, Some(0.0)), (1, 2, Some(100.0)), (3, 3, Some(150.0))]);
+        assert_eq!(total_duration(&parts), None);
+
+        set_duration(&pool, 3, 25.0).await.unwrap();
+        assert_eq!(total_duration(&list_parts(&pool, 1).await.unwrap()), Some(175.0));
+
+        // The lowest remaining part takes over from a deleted primary
+        promote_next_part(&pool, 1).await.unwrap();
+        sqlx::query("DELETE FROM media WHERE id = 1").execute(&pool).await.unwrap();
+        let parts = list_parts(&pool, 3).await.unwrap();
+        let layout: Vec<(i64, Option<f64>)> = parts.iter().map(|p| (p.id, p.offset)).collect();
+        assert_eq!(layout, [(2, Some(0.0)), (3, Some(100.0))]);
+        assert_eq!(find_stack(&pool, 1, Path::new("/movies/Heat/Heat cd4.mkv"), "heat").await.unwrap(), Some(2));
+
+        pool.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
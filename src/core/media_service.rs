@@ -4,6 +4,11 @@
 use sqlx::SqlitePool;
 use crate::error::AppError;
 use crate::models::metadata::NormalizedMetadata;
+use crate::providers::nfo::{self, ArtworkKind};
+use crate::core::episode_numbering::{self, EpisodeNumbering};
+use crate::core::scanner::parse_episode_key;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
 
 
 /// Update a single media item (movie or episode) with fetched metadata.
@@ -20,9 +25,10 @@ pub async fn update_media_metadata(
         .unwrap_or(0);
 
     sqlx::query(
-        "UPDATE media SET title = ?, year = ?, poster_url = ?, backdrop_url = ?, plot = ?, media_type = ?, runtime = ?, genres = ?, provider_ids = ? WHERE id = ?"
+        "UPDATE media SET title = ?, original_title = ?, year = ?, poster_url = ?, backdrop_url = ?, plot = ?, media_type = ?, runtime = ?, genres = ?, provider_ids = ? WHERE id = ?"
     )
     .bind(&meta.title)
+    .bind(&meta.original_title)
     .bind(year)
     .bind(&meta.poster_url)
     .bind(&meta.backdrop_url)
@@ -51,8 +57,9 @@ pub async fn update_series_metadata(
         .unwrap_or(0);
     
     sqlx::query(
-        "UPDATE media SET poster_url = ?, backdrop_url = ?, plot = ?, year = ?, genres = ?, provider_ids = ? WHERE series_name = ? AND media_type != 'book'"
+        "UPDATE media SET original_title = ?, poster_url = ?, backdrop_url = ?, plot = ?, year = ?, genres = ?, provider_ids = ? WHERE series_name = ? AND media_type != 'book'"
     )
+    .bind(&meta.original_title)
     .bind(&meta.poster_url)
     .bind(&meta.backdrop_url)
     .bind(&meta.plot)
@@ -91,6 +98,123 @@ pub async fn update_episode_details(
     Ok(())
 }
 
+/// Get the library a series belongs to (used to pick its metadata provider chain).
+pub async fn get_series_library_id(pool: &SqlitePool, series_name: &str) -> Result<Option<i64>, AppError> {
+    let library_id: Option<i64> = sqlx::query_scalar(
+        "SELECT library_id FROM media WHERE series_name = ? AND media_type != 'book' LIMIT 1"
+    )
+    .bind(series_name)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(library_id)
+}
+
+/// Get a media item's file path, its library's root path and its series name.
+pub async fn get_media_location(pool: &SqlitePool, id: i64) -> Result<Option<(String, String, Option<String>)>, AppError> {
+    let location = sqlx::query_as(
+        "SELECT m.file_path, l.path, m.series_name FROM media m JOIN libraries l ON m.library_id = l.id WHERE m.id = ?"
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(location)
+}
+
+/// Get the folder holding a series' `tvshow.nfo` and artwork, derived from one of its episodes.
+pub async fn get_series_dir(pool: &SqlitePool, series_name: &str) -> Result<Option<PathBuf>, AppError> {
+    let row: Option<(String, String)> = sqlx::query_as(
+        "SELECT m.file_path, l.path FROM media m JOIN libraries l ON m.library_id = l.id WHERE m.series_name = ? AND l.library_type = 'tv_shows' LIMIT 1"
+    )
+    .bind(series_name)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.and_then(|(file_path, library_path)| nfo::series_dir(Path::new(&file_path), &library_path)))
+}
+
+/// Point every episode of a series at the series' local poster/backdrop, where one exists.
+pub async fn apply_local_series_artwork(pool: &SqlitePool, series_name: &str, series_dir: &Path) -> Result<(), AppError> {
+    for kind in [ArtworkKind::Poster, ArtworkKind::Backdrop] {
+        if nfo::find_series_artwork(series_dir, kind).is_none() {
+            continue;
+        }
+        let column = if kind == ArtworkKind::Poster { "poster_url" } else { "backdrop_url" };
+        sqlx::query(&format!(
+            "UPDATE media SET {} = '/api/v1/media/' || id || '/artwork/{}' WHERE series_name = ? AND media_type != 'book'",
+            column, kind.as_str()
+        ))
+        .bind(series_name)
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Re-apply episode NFOs and local thumbnails so they take priority over provider episode data.
+pub async fn apply_episode_nfos(pool: &SqlitePool, series_name: &str) -> Result<(), AppError> {
+    let episodes: Vec<(i64, String, Option<i32>, i64)> = sqlx::query_as(
+        "SELECT id, file_path, episode_number, (SELECT COUNT(*) FROM media m2 WHERE m2.file_path = media.file_path) FROM media WHERE series_name = ? AND media_type != 'book' AND extra_type IS NULL"
+    )
+    .bind(series_name)
+    .fetch_all(pool)
+    .await?;
+
+    for (id, file_path, episode_number, rows_for_file) in episodes {
+        let video = Path::new(&file_path);
+        let episode = nfo::select_episode(nfo::episode_nfos(video), episode_number, rows_for_file > 1).unwrap_or_default();
+        let local_still = nfo::find_still(video)
+            .map(|_| nfo::artwork_url(id, ArtworkKind::Still));
+        let still_url = local_still.or(episode.still_url);
+
+        if episode.title.is_none() && episode.plot.is_none() && still_url.is_none() {
+            continue;
+        }
+        sqlx::query(
+            "UPDATE media SET title = COALESCE(?, title), plot = COALESCE(?, plot), still_url = COALESCE(?, still_url) WHERE id = ?"
+        )
+        .bind(episode.title)
+        .bind(episode.plot)
+        .bind(still_url)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Map a series' absolute-numbered or dated episode files onto the provider's seasons, for
+/// libraries with `episode_numbering` set. Files whose episode NFO gives a season keep it.
+pub async fn resolve_episode_numbers(pool: &SqlitePool, series_name: &str, provider_ids: &Value) -> Result<(), AppError> {
+    let episodes: Vec<(i64, String, i64, Option<String>)> = sqlx::query_as(
+        "SELECT m.id, m.file_path, l.id, l.episode_numbering FROM media m JOIN libraries l ON m.library_id = l.id
+         WHERE m.series_name = ? AND m.extra_type IS NULL AND l.library_type = 'tv_shows' AND l.episode_numbering IN ('absolute', 'date')"
+    )
+    .bind(series_name)
+    .fetch_all(pool)
+    .await?;
+
+    for (id, file_path, library_id, numbering) in episodes {
+        let video = Path::new(&file_path);
+        let Some(stem) = video.file_stem().map(|s| s.to_string_lossy().to_string()) else { continue };
+        let Some(key) = parse_episode_key(&stem, EpisodeNumbering::parse(numbering.as_deref())) else { continue };
+        if nfo::episode_nfos(video).iter().any(|e| e.season_number.is_some()) {
+            continue;
+        }
+
+        if let Some((season, episode)) = episode_numbering::resolve(&key, provider_ids, Some(library_id), pool).await? {
+            sqlx::query("UPDATE media SET season_number = ?, episode_number = ? WHERE id = ?")
+                .bind(season)
+                .bind(episode)
+                .bind(id)
+                .execute(pool)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
 /// Get all distinct season numbers for a series.
 pub async fn get_series_seasons(pool: &SqlitePool, series_name: &str) -> Result<Vec<i32>, AppError> {
     let seasons: Vec<i32> = sqlx::query_scalar(
@@ -0,0 +1,106 @@
+//! Media Streams - technical metadata from FFprobe: the video, audio and subtitle streams of
+//! each file, stored in `media_streams`.
+//!
+//! The scanner probes every new file (and any file not probed yet). Probing also fills in
+//! `media.duration` and `media.bitrate`, and `media.resolution` when the filename didn't
+//! carry one. Playback decisions and quality filters read the stored streams.
+
+use crate::core::probe::{self, MediaInfo, ProbeResult};
+use crate::models::media::MediaStreamDto;
+use sqlx::SqlitePool;
+use std::path::Path;
+
+/// "1080p" for a 1920x800 scope picture as much as for 1920x1080.
+fn resolution_label(width: i64, height: i64) -> &'static str {
+    match (width, height) {
+        (w, h) if w >= 3200 || h >= 1600 => "2160p",
+        (w, h) if w >= 2200 || h >= 1300 => "1440p",
+        (w, h) if w >= 1700 || h >= 900 => "1080p",
+        (w, h) if w >= 1100 || h >= 650 => "720p",
+        (_, h) if h >= 540 => "576p",
+        _ => "480p",
+    }
+}
+
+/// Replace the stored streams of a media item with a probe's.
+pub async fn store(pool: &SqlitePool, media_id: i64, result: &ProbeResult) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM media_streams WHERE media_id = ?").bind(media_id).execute(&mut *tx).await?;
+    for s in &result.streams {
+        sqlx::query(
+            "INSERT INTO media_streams (media_id, stream_index, stream_type, codec, profile, language, title, channels, channel_layout, width, height, bitrate, frame_rate, hdr, is_default, is_forced, is_hearing_impaired)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(media_id).bind(s.index).bind(&s.stream_type).bind(&s.codec).bind(&s.profile).bind(&s.language).bind(&s.title)
+        .bind(s.channels).bind(&s.channel_layout).bind(s.width).bind(s.height).bind(s.bitrate).bind(s.frame_rate)
+        .bind(&s.hdr).bind(s.is_default).bind(s.is_forced).bind(s.is_hearing_impaired)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let resolution = result.streams.iter()
+        .find(|s| s.stream_type == "video")
+        .and_then(|s| Some(resolution_label(s.width?, s.height?)));
+    sqlx::query("UPDATE media SET duration = COALESCE(duration, ?), bitrate = ?, resolution = COALESCE(resolution, ?) WHERE id = ?")
+        .bind(result.duration).bind(result.bitrate).bind(resolution).bind(media_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
+/// Probe a file and store its streams for every media row of it (a multi-episode file has
+/// several). Returns whether the probe succeeded.
+pub async fn probe_and_store(pool: &SqlitePool, media_ids: &[i64], path: &Path) -> bool {
+    let Some(result) = probe::probe(path).await else { return false };
+    for &id in media_ids {
+        if let Err(e) = store(pool, id, &result).await {
+            tracing::warn!("Failed to store streams of media {}: {}", id, e);
+        }
+    }
+    true
+}
+
+/// Streams of a media item in file order.
+pub async fn list(pool: &SqlitePool, media_id: i64) -> Result<Vec<MediaStreamDto>, sqlx::Error> {
+    sqlx::query_as::<_, MediaStreamDto>(
+        "SELECT stream_index, stream_type, codec, profile, language, title, channels, channel_layout, width, height, bitrate, frame_rate, hdr, is_default, is_forced, is_hearing_impaired
+         FROM media_streams WHERE media_id = ? ORDER BY stream_index"
+    )
+    .bind(media_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// What playback decisions need, from the stored streams. `None` if the item wasn't probed.
+pub async fn media_info(pool: &SqlitePool, media_id: i64) -> Result<Option<MediaInfo>, sqlx::Error> {
+    let streams = list(pool, media_id).await?;
+    if streams.is_empty() {
+        return Ok(None);
+    }
+    let bitrate: Option<i64> = sqlx::query_scalar("SELECT bitrate FROM media WHERE id = ?")
+        .bind(media_id)
+        .fetch_optional(pool)
+        .await?
+        .flatten();
+
+    let first = |kind: &str| streams.iter().find(|s| s.stream_type == kind);
+    let video = first("video");
+    Ok(Some(MediaInfo {
+        video_codec: video.and_then(|s| s.codec.clone()),
+        // The first audio stream is the one remux and transcode pick
+        audio_codec: first("audio").and_then(|s| s.codec.clone()),
+        bitrate: bitrate.map(|b| b as u64),
+        height: video.and_then(|s| s.height).map(|h| h as u32),
+    }))
+}
+
+/// Stored playback info, probing the file first if it hasn't been.
+pub async fn media_info_or_probe(pool: &SqlitePool, media_id: i64, path: &Path) -> Result<Option<MediaInfo>, sqlx::Error> {
+    if let Some(info) = media_info(pool, media_id).await? {
+        return Ok(Some(info));
+    }
+    if probe_and_store(pool, &[media_id], path).await {
+        return media_info(pool, media_id).await;
+    }
+    Ok(None)
+}
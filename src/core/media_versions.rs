@@ -0,0 +1,199 @@
+//! Media Versions - groups several files of the same movie (2160p remux, 1080p encode,
+//! director's cut, ...) under one logical item.
+//!
+//! The first file scanned is the primary row and carries the metadata; every other version
+//! points at it through `media.version_of` and is hidden from listings. Playback progress
+//! is stored against the primary so it is shared by all versions.
+
+use crate::core::media_groups::{self, Grouping};
+use crate::error::AppError;
+use crate::models::media::MediaVersionDto;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use sqlx::{FromRow, SqlitePool};
+use std::path::Path;
+
+// Plex-style "Movie (2019) - 1080p" / "Movie (2019) - Director's Cut" version label. Only
+// taken as a label after a year, so "Star Wars - A New Hope" keeps its full title.
+const LABEL_SEPARATOR: &str = " - ";
+static RE_YEAR_END: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:19|20)\d{2}\)?\s*$").unwrap());
+
+static RE_RESOLUTION: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)(?:^|[\s._\-\[(])(2160p|4k|uhd|1440p|1080[pi]|720p|576p|480p)(?:[\s._\-\])]|$)").unwrap());
+static RE_PLEX_EDITION: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\{edition-([^}]+)\}").unwrap());
+static RE_EDITION: Lazy<Regex> = Lazy::new(|| Regex::new(
+    r"(?i)(?:^|[\s._\-\[(])(director'?s[\s._]cut|extended(?:[\s._](?:cut|edition))?|theatrical(?:[\s._]cut)?|unrated|uncut|remastered|final[\s._]cut|imax|special[\s._]edition|ultimate[\s._](?:cut|edition)|criterion)(?:[\s._\-\])]|$)"
+).unwrap());
+// Release tags that start the "technical" part of a filename; the title ends before them
+static RE_RELEASE_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(
+    r"(?i)(?:^|[\s._\-\[(])(?:2160p|4k|uhd|1440p|1080[pi]|720p|576p|480p|blu-?ray|bdrip|brrip|web-?dl|webrip|hdtv|dvdrip|remux|hdr10|hdr|x264|x265|h\.?264|h\.?265|hevc)(?:[\s._\-\])]|$)"
+).unwrap());
+static RE_NON_ALNUM: Lazy<Regex> = Lazy::new(|| Regex::new(r"[^\p{L}\p{N}]+").unwrap());
+
+/// What a filename says about which version of a movie it is.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VersionInfo {
+    /// Normalized title (and year) shared by every version of the movie
+    pub key: String,
+    pub resolution: Option<String>,
+    pub edition: Option<String>,
+}
+
+/// Read the grouping key, resolution and edition from a file stem.
+pub fn version_info(stem: &str) -> VersionInfo {
+    let resolution = RE_RESOLUTION.captures(stem).map(|c| match c[1].to_lowercase().as_str() {
+        "4k" | "uhd" => "2160p".to_string(),
+        other => other.to_string(),
+    });
+    let edition = RE_PLEX_EDITION.captures(stem)
+        .or_else(|| RE_EDITION.captures(stem))
+        .map(|c| title_case(&c[1]));
+
+    let mut title = RE_PLEX_EDITION.replace_all(stem, "").to_string();
+    if let Some(pos) = title.find(LABEL_SEPARATOR).filter(|pos| RE_YEAR_END.is_match(&title[..*pos])) {
+        title.truncate(pos);
+    }
+    for re in [&*RE_RELEASE_TAG, &*RE_EDITION] {
+        if let Some(m) = re.find(&title) {
+            title.truncate(m.start());
+        }
+    }
+    let key = RE_NON_ALNUM.replace_all(&title.to_lowercase(), " ").trim().to_string();
+
+    VersionInfo { key, resolution, edition }
+}
+
+fn title_case(value: &str) -> String {
+    value.split(|c: char| c.is_whitespace() || c == '.' || c == '_')
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            let mut chars = w.chars();
+            chars.next().map(|f| f.to_uppercase().chain(chars.flat_map(|c| c.to_lowercase())).collect::<String>()).unwrap_or_default()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Find the primary row of the movie `path` is another version of: the oldest movie in the same
+/// library and folder whose filename gives the same key.
+pub async fn find_primary(pool: &SqlitePool, library_id: i64, path: &Path, key: &str) -> Result<Option<i64>, AppError> {
+    if key.is_empty() {
+        return Ok(None);
+    }
+    media_groups::find_primary(pool, Grouping::Versions, library_id, path, |stem| version_info(stem).key == key).await
+}
+
+/// Group a movie row scanned before versions were recognized: fill in its resolution and
+/// edition, and make it (with any versions of its own) a version of an older row of the same
+/// movie. Returns the primary it joined.
+pub async fn group_existing(pool: &SqlitePool, id: i64, library_id: i64, path: &Path, stem: &str) -> Result<Option<i64>, AppError> {
+    let info = version_info(stem);
+    sqlx::query("UPDATE media SET resolution = COALESCE(resolution, ?), edition = COALESCE(edition, ?) WHERE id = ?")
+        .bind(&info.resolution)
+        .bind(&info.edition)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    if !media_groups::is_ungrouped(pool, id).await? {
+        return Ok(None);
+    }
+    let Some(primary_id) = find_primary(pool, library_id, path, &info.key).await?.filter(|p| *p < id) else { return Ok(None) };
+    media_groups::join(pool, Grouping::Versions, primary_id, id).await?;
+    Ok(Some(primary_id))
+}
+
+/// Copy the primary's metadata onto a newly added version.
+pub async fn copy_metadata(pool: &SqlitePool, primary_id: i64, version_id: i64) -> Result<(), AppError> {
+    sqlx::query(
+        "UPDATE media SET (title, year, poster_url, plot, media_type, backdrop_url, provider_ids, runtime, genres, original_title) =
+            (SELECT title, year, poster_url, plot, media_type, backdrop_url, provider_ids, runtime, genres, original_title FROM media WHERE id = ?)
+         WHERE id = ?"
+    )
+    .bind(primary_id)
+    .bind(version_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// The id that holds `id`'s shared state such as playback progress: the primary of its version
+/// group or part stack (see `core::media_parts`), or itself.
+pub async fn group_id(pool: &SqlitePool, id: i64) -> Result<i64, AppError> {
+    let group: Option<i64> = sqlx::query_scalar("SELECT COALESCE(version_of, part_of, id) FROM media WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(group.unwrap_or(id))
+}
+
+#[derive(FromRow)]
+struct VersionRow {
+    id: i64,
+    file_path: String,
+    resolution: Option<String>,
+    edition: Option<String>,
+    version_of: Option<i64>,
+}
+
+/// Every file of `id`'s version group, primary first.
+pub async fn list_versions(pool: &SqlitePool, id: i64) -> Result<Vec<MediaVersionDto>, AppError> {
+    let rows: Vec<VersionRow> = sqlx::query_as(
+        "SELECT id, file_path, resolution, edition, version_of FROM media
+         WHERE COALESCE(version_of, id) = (SELECT COALESCE(version_of, id) FROM media WHERE id = ?)
+         ORDER BY version_of IS NOT NULL, id"
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| {
+        let label = [row.edition.as_deref(), row.resolution.as_deref()].into_iter().flatten().collect::<Vec<_>>().join(" ");
+        let label = if label.is_empty() {
+            Path::new(&row.file_path).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default()
+        } else {
+            label
+        };
+        MediaVersionDto {
+            id: row.id,
+            file_path: row.file_path,
+            resolution: row.resolution,
+            edition: row.edition,
+            label,
+            is_primary: row.version_of.is_none(),
+        }
+    }).collect())
+}
+
+/// Before a primary row is deleted, hand its role (and the shared progress) to its oldest
+/// remaining version so the movie doesn't vanish from listings.
+pub async fn promote_next_version(pool: &SqlitePool, primary_id: i64) -> Result<(), AppError> {
+    media_groups::promote_next(pool, Grouping::Versions, primary_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(key: &str, resolution: Option<&str>, edition: Option<&str>) -> VersionInfo {
+        VersionInfo { key: key.to_string(), resolution: resolution.map(str::to_string), edition: edition.map(str::to_string) }
+    }
+
+    #[test]
+    fn version_info_reads_the_key_resolution_and_edition() {
+        let cases = [
+            ("Movie (2019) - 1080p", info("movie 2019", Some("1080p"), None)),
+            ("Movie (2019) - 4K", info("movie 2019", Some("2160p"), None)),
+            ("Movie.2019.UHD.BluRay.x265", info("movie 2019", Some("2160p"), None)),
+            ("Movie (2019) - Director's Cut", info("movie 2019", None, Some("Director's Cut"))),
+            ("Movie.2019.Extended.Edition.720p", info("movie 2019", Some("720p"), Some("Extended Edition"))),
+            ("Movie (2019) {edition-Final Cut} - 1080i", info("movie 2019", Some("1080i"), Some("Final Cut"))),
+            // " - " only starts a label after a year
+            ("Star Wars - A New Hope", info("star wars a new hope", None, None)),
+            ("Star Wars - A New Hope (1977) - 720p", info("star wars a new hope 1977", Some("720p"), None)),
+            ("Movie", info("movie", None, None)),
+        ];
+        for (stem, expected) in cases {
+            assert_eq!(version_info(stem), expected, "{}", stem);
+        }
+    }
+}
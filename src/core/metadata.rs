@@ -1,12 +1,20 @@
-use crate::models::metadata::{NormalizedMetadata, EpisodeMetadata};
+use crate::models::metadata::{NormalizedMetadata, EpisodeMetadata, MetadataLocale};
 use crate::providers::traits::MetadataProvider;
 use crate::providers::tmdb::TmdbProvider;
+use crate::providers::tvdb::TvdbProvider;
+use crate::providers::offline::OfflineProvider;
+use crate::providers::nfo;
+use crate::core::metadata_merge::{self, MergePolicy};
 use sqlx::SqlitePool;
+use serde_json::Value;
 use crate::error::AppError;
 
 /// Default provider if not configured in settings
 const DEFAULT_PROVIDER: &str = "tmdb";
 
+/// Provider names accepted by `get_provider`
+pub const KNOWN_PROVIDERS: &[&str] = &["tmdb", "tvdb", "offline"];
+
 /// Get the configured default provider from settings, or use DEFAULT_PROVIDER
 pub async fn get_default_provider(pool: &SqlitePool) -> String {
     let result: Option<(String,)> = sqlx::query_as("SELECT value FROM settings WHERE key = 'metadata_provider'")
@@ -16,38 +24,209 @@ pub async fn get_default_provider(pool: &SqlitePool) -> String {
     result.map(|r| r.0).unwrap_or_else(|| DEFAULT_PROVIDER.to_string())
 }
 
-/// Get a provider instance by name
-pub async fn get_provider(pool: &SqlitePool, provider: &str) -> Result<Box<dyn MetadataProvider>, AppError> {
+/// Read a single setting value, treating blank values as unset
+pub(crate) async fn get_setting(pool: &SqlitePool, key: &str) -> Option<String> {
+    let result: Option<(String,)> = sqlx::query_as("SELECT value FROM settings WHERE key = ?")
+        .bind(key)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None);
+    result.map(|r| r.0.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// Get the configured metadata language/region from settings
+/// (`metadata_language`, `metadata_region`, `metadata_fallback_language`).
+pub async fn get_metadata_locale(pool: &SqlitePool) -> MetadataLocale {
+    let defaults = MetadataLocale::default();
+    MetadataLocale {
+        language: get_setting(pool, "metadata_language").await.unwrap_or(defaults.language),
+        region: get_setting(pool, "metadata_region").await.or(defaults.region),
+        fallback_language: get_setting(pool, "metadata_fallback_language").await.or(defaults.fallback_language),
+    }
+}
+
+/// Get the episode ordering for a library (`libraries.episode_order`),
+/// falling back to the `tvdb_episode_order` setting, then aired order.
+pub async fn get_episode_order(pool: &SqlitePool, library_id: Option<i64>) -> String {
+    let library_order: Option<Option<String>> = match library_id {
+        Some(id) => sqlx::query_scalar("SELECT episode_order FROM libraries WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .unwrap_or(None),
+        None => None,
+    };
+
+    match library_order.flatten().filter(|o| !o.trim().is_empty()) {
+        Some(order) => order,
+        None => get_setting(pool, "tvdb_episode_order").await.unwrap_or_else(|| "aired".to_string()),
+    }
+}
+
+/// Get a provider instance by name. `library_id` selects library-specific options such as episode order.
+pub async fn get_provider(pool: &SqlitePool, provider: &str, library_id: Option<i64>) -> Result<Box<dyn MetadataProvider>, AppError> {
     match provider {
         "tmdb" => {
             let api_key = TmdbProvider::fetch_api_key(pool).await?;
             Ok(Box::new(TmdbProvider::new(api_key)))
         },
-        // Future: "tvdb" => { ... },
+        "tvdb" => {
+            let (api_key, pin) = TvdbProvider::fetch_credentials(pool).await?;
+            let episode_order = get_episode_order(pool, library_id).await;
+            Ok(Box::new(TvdbProvider::new(api_key, pin, episode_order)))
+        },
+        "offline" => {
+            let path = OfflineProvider::fetch_catalog_path(pool).await?;
+            Ok(Box::new(OfflineProvider::new(&path)?))
+        },
         _ => Err(AppError::BadRequest(format!("Unknown provider: {}", provider)))
     }
 }
 
-/// Fetch metadata using the default configured provider
+/// Get the ordered provider chain for a library (`libraries.metadata_providers`, comma separated),
+/// or just the default provider when the library has no chain configured.
+pub async fn get_provider_chain(pool: &SqlitePool, library_id: Option<i64>) -> Vec<String> {
+    let configured: Option<Option<String>> = match library_id {
+        Some(id) => sqlx::query_scalar("SELECT metadata_providers FROM libraries WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .unwrap_or(None),
+        None => None,
+    };
+
+    let chain: Vec<String> = configured.flatten().unwrap_or_default()
+        .split(',')
+        .map(|p| p.trim().to_lowercase())
+        .filter(|p| !p.is_empty())
+        .collect();
+
+    if chain.is_empty() {
+        vec![get_default_provider(pool).await]
+    } else {
+        chain
+    }
+}
+
+/// Get the field merge policy for a library, falling back to the `metadata_merge_policy` setting.
+pub async fn get_merge_policy(pool: &SqlitePool, library_id: Option<i64>) -> MergePolicy {
+    let library_policy: Option<Option<String>> = match library_id {
+        Some(id) => sqlx::query_scalar("SELECT metadata_merge_policy FROM libraries WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .unwrap_or(None),
+        None => None,
+    };
+
+    let json = match library_policy.flatten().filter(|p| !p.trim().is_empty()) {
+        Some(p) => Some(p),
+        None => get_setting(pool, "metadata_merge_policy").await,
+    };
+
+    json.and_then(|j| MergePolicy::from_json(&j)
+        .map_err(|e| tracing::warn!("Ignoring merge policy: {}", e))
+        .ok())
+        .unwrap_or_default()
+}
+
+/// Read one provider's ID out of a `provider_ids` object, whether stored as a number or a string.
+pub fn provider_id_str(provider_ids: &Value, provider: &str) -> Option<String> {
+    let v = provider_ids.get(provider)?;
+    if let Some(s) = v.as_str() {
+        Some(s.to_string()).filter(|s| !s.is_empty())
+    } else {
+        v.as_i64().map(|i| i.to_string())
+    }
+}
+
+/// Whether a search result can be the item looked up: of the hinted type ("movie" or "series")
+/// and, when an earlier match knows the year, released within a year of it (providers
+/// disagree on festival and release dates).
+fn plausible_match(result: &NormalizedMetadata, media_type_hint: Option<&str>, year: Option<i32>) -> bool {
+    let type_ok = match (media_type_hint, result.media_type.as_deref()) {
+        (Some(hint), Some(media_type)) => hint == media_type,
+        _ => true,
+    };
+    let result_year = result.year.as_deref().and_then(|y| y.parse::<i32>().ok());
+    let year_ok = match (year, result_year) {
+        (Some(year), Some(result_year)) => (year - result_year).abs() <= 1,
+        _ => true,
+    };
+    type_ok && year_ok
+}
+
+/// Look up one provider in the chain. If an earlier provider already matched and knows this
+/// provider's ID, fetch details by that ID instead of searching by name. Otherwise the first
+/// search result of the hinted type and the year an earlier match found is taken.
+async fn fetch_from_provider(
+    pool: &SqlitePool,
+    provider_name: &str,
+    query: &str,
+    media_type_hint: Option<&str>,
+    library_id: Option<i64>,
+    matched: &[(String, NormalizedMetadata)],
+    locale: &MetadataLocale,
+) -> Result<Option<NormalizedMetadata>, AppError> {
+    let provider = get_provider(pool, provider_name, library_id).await?;
+
+    let known_id = matched.iter()
+        .find_map(|(_, m)| m.provider_ids.as_ref().and_then(|ids| provider_id_str(ids, provider_name)));
+    if let Some(id) = known_id {
+        let media_type = matched.first().and_then(|(_, m)| m.media_type.as_deref()).or(media_type_hint);
+        return provider.get_details(&id, media_type, locale).await.map(Some);
+    }
+
+    let year = matched.iter().find_map(|(_, m)| m.year.as_deref().and_then(|y| y.parse::<i32>().ok()));
+    let results = provider.search(query, locale).await?;
+    let Some(first) = results.iter().find(|r| plausible_match(r, media_type_hint, year)) else {
+        return Ok(None);
+    };
+
+    if let Some(id) = first.provider_ids.as_ref().and_then(|ids| provider_id_str(ids, provider_name)) {
+        return provider.get_details(&id, first.media_type.as_deref(), locale).await.map(Some);
+    }
+    Ok(Some(first.clone()))
+}
+
+/// Fetch metadata through the library's provider chain and merge the results.
+/// `local` (metadata read from an NFO beside the file) is placed ahead of the chain: its values
+/// win under the default `fill` policy, and its IDs let remote providers fetch by ID.
+/// Providers that fail are skipped; the first error is returned only if nothing matched.
 pub async fn fetch_metadata(
     query: &str,
-    _media_type_hint: Option<&str>,
+    media_type_hint: Option<&str>,
+    library_id: Option<i64>,
+    local: Option<NormalizedMetadata>,
     pool: &SqlitePool
 ) -> Result<NormalizedMetadata, AppError> {
-    let provider_name = get_default_provider(pool).await;
-    let provider = get_provider(pool, &provider_name).await?;
-    let results = provider.search(query).await?;
-    
-    if let Some(first) = results.first() {
-        if let Some(ids) = &first.provider_ids {
-            if let Some(id) = ids.get(&provider_name).and_then(|v| v.as_i64()) {
-                return Ok(provider.get_details(&id.to_string(), first.media_type.as_deref()).await?);
+    let chain = get_provider_chain(pool, library_id).await;
+    let locale = get_metadata_locale(pool).await;
+
+    let mut results: Vec<(String, NormalizedMetadata)> = Vec::new();
+    if let Some(local) = local {
+        results.push((nfo::NFO_PROVIDER.to_string(), local));
+    }
+    let mut first_error = None;
+
+    for provider_name in &chain {
+        match fetch_from_provider(pool, provider_name, query, media_type_hint, library_id, &results, &locale).await {
+            Ok(Some(meta)) => results.push((provider_name.clone(), meta)),
+            Ok(None) => {}
+            Err(e) => {
+                tracing::warn!("Provider '{}' failed for '{}': {}", provider_name, query, e);
+                first_error.get_or_insert(e);
             }
         }
-        Ok(first.clone())
-    } else {
-        Err(AppError::NotFound("No results found".into()))
     }
+
+    // A link-only NFO contributes IDs but no title; on its own it is not a match
+    if results.iter().all(|(_, m)| m.title.trim().is_empty()) {
+        return Err(first_error.unwrap_or_else(|| AppError::NotFound("No results found".into())));
+    }
+
+    let policy = get_merge_policy(pool, library_id).await;
+    metadata_merge::merge(&results, &policy).ok_or_else(|| AppError::NotFound("No results found".into()))
 }
 
 /// Search using the default configured provider
@@ -57,8 +236,9 @@ pub async fn search(
     pool: &SqlitePool
 ) -> Result<Vec<NormalizedMetadata>, AppError> {
     let provider_name = get_default_provider(pool).await;
-    let provider = get_provider(pool, &provider_name).await?;
-    Ok(provider.search(query).await?)
+    let provider = get_provider(pool, &provider_name, None).await?;
+    let locale = get_metadata_locale(pool).await;
+    provider.search(query, &locale).await
 }
 
 /// Fetch by ID using the default configured provider
@@ -68,17 +248,76 @@ pub async fn fetch_by_id(
     pool: &SqlitePool
 ) -> Result<NormalizedMetadata, AppError> {
     let provider_name = get_default_provider(pool).await;
-    let provider = get_provider(pool, &provider_name).await?;
-    Ok(provider.get_details(provider_id, _media_type).await?)
+    let provider = get_provider(pool, &provider_name, None).await?;
+    let locale = get_metadata_locale(pool).await;
+    provider.get_details(provider_id, _media_type, &locale).await
 }
 
-/// Fetch episodes using the default configured provider
+/// Fetch a season's episodes from every provider in the chain that has an ID in `provider_ids`,
+/// followed by any other known provider with an ID, and merge them.
 pub async fn fetch_episodes(
-    series_provider_id: &str,
+    provider_ids: &Value,
     season_number: i32,
+    library_id: Option<i64>,
     pool: &SqlitePool
 ) -> Result<Vec<EpisodeMetadata>, AppError> {
-    let provider_name = get_default_provider(pool).await;
-    let provider = get_provider(pool, &provider_name).await?;
-    Ok(provider.get_season_episodes(series_provider_id, season_number).await?)
+    let mut providers = get_provider_chain(pool, library_id).await;
+    for name in KNOWN_PROVIDERS {
+        if !providers.iter().any(|p| p == name) {
+            providers.push(name.to_string());
+        }
+    }
+
+    let locale = get_metadata_locale(pool).await;
+    let mut lists = Vec::new();
+    let mut first_error = None;
+
+    for provider_name in &providers {
+        let Some(id) = provider_id_str(provider_ids, provider_name) else { continue };
+        let episodes = match get_provider(pool, provider_name, library_id).await {
+            Ok(provider) => provider.get_season_episodes(&id, season_number, &locale).await,
+            Err(e) => Err(e),
+        };
+        match episodes {
+            Ok(list) => lists.push(list),
+            Err(e) => { first_error.get_or_insert(e); }
+        }
+    }
+
+    match (lists.is_empty(), first_error) {
+        (true, Some(e)) => Err(e),
+        _ => Ok(metadata_merge::merge_episodes(lists)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(media_type: &str, year: &str) -> NormalizedMetadata {
+        NormalizedMetadata {
+            title: "The Office".to_string(),
+            original_title: None,
+            year: Some(year.to_string()),
+            plot: None,
+            poster_url: None,
+            backdrop_url: None,
+            media_type: Some(media_type.to_string()),
+            provider_ids: None,
+            genres: None,
+            runtime: None,
+            rating: None,
+        }
+    }
+
+    #[test]
+    fn plausible_match_checks_the_type_hint_and_known_year() {
+        assert!(plausible_match(&result("series", "2005"), Some("series"), None));
+        assert!(!plausible_match(&result("movie", "2005"), Some("series"), None));
+        assert!(plausible_match(&result("movie", "2005"), None, None));
+
+        assert!(plausible_match(&result("series", "2005"), Some("series"), Some(2006)));
+        assert!(!plausible_match(&result("series", "2001"), Some("series"), Some(2005)));
+        assert!(plausible_match(&NormalizedMetadata { year: None, ..result("series", "") }, Some("series"), Some(2005)));
+    }
 }
@@ -0,0 +1,168 @@
+//! Metadata Merge - combines results from an ordered chain of providers into one record.
+//!
+//! Each field has a rule (see `MergeRule`). The policy is stored as a JSON object keyed by
+//! field name, e.g. `{"genres": "union", "poster_url": "tvdb", "title": "primary"}`.
+//! Fields without a rule use `fill`. A rule naming a provider must name one this server has.
+
+use std::collections::HashMap;
+use serde_json::{Map, Value};
+use crate::core::metadata::KNOWN_PROVIDERS;
+use crate::error::AppError;
+use crate::providers::nfo::NFO_PROVIDER;
+use crate::models::metadata::{NormalizedMetadata, EpisodeMetadata};
+
+/// Field names accepted in a merge policy.
+pub const MERGE_FIELDS: &[&str] = &[
+    "title", "original_title", "year", "plot", "poster_url", "backdrop_url", "genres", "runtime", "rating",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeRule {
+    /// First provider in chain order with a non-empty value (default)
+    Fill,
+    /// Only the first provider that matched; never filled from later providers
+    Primary,
+    /// Combine values from every provider (list fields); behaves like `Fill` elsewhere
+    Union,
+    /// Prefer the named provider's (or `nfo`'s) value, falling back to `Fill`
+    Prefer(String),
+}
+
+impl MergeRule {
+    fn parse(value: &str) -> Result<MergeRule, AppError> {
+        Ok(match value.trim().to_lowercase().as_str() {
+            "" | "fill" => MergeRule::Fill,
+            "primary" => MergeRule::Primary,
+            "union" => MergeRule::Union,
+            other if KNOWN_PROVIDERS.contains(&other) || other == NFO_PROVIDER => MergeRule::Prefer(other.to_string()),
+            other => return Err(AppError::BadRequest(format!("Unknown merge rule: {}", other))),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MergePolicy {
+    rules: HashMap<String, MergeRule>,
+}
+
+impl MergePolicy {
+    /// Parse a policy from its JSON representation. Unknown field names and rules are rejected.
+    pub fn from_json(json: &str) -> Result<MergePolicy, AppError> {
+        let map: HashMap<String, String> = serde_json::from_str(json)
+            .map_err(|e| AppError::BadRequest(format!("Invalid merge policy: {}", e)))?;
+
+        let mut rules = HashMap::new();
+        for (field, rule) in map {
+            if !MERGE_FIELDS.contains(&field.as_str()) {
+                return Err(AppError::BadRequest(format!("Unknown merge policy field: {}", field)));
+            }
+            rules.insert(field, MergeRule::parse(&rule)?);
+        }
+        Ok(MergePolicy { rules })
+    }
+
+    pub fn rule(&self, field: &str) -> &MergeRule {
+        self.rules.get(field).unwrap_or(&MergeRule::Fill)
+    }
+}
+
+fn non_empty(s: &Option<String>) -> Option<String> {
+    s.as_ref().filter(|v| !v.trim().is_empty()).cloned()
+}
+
+/// Pick a value for one field according to its rule. `results` is in chain order.
+fn pick<T>(
+    results: &[(String, NormalizedMetadata)],
+    rule: &MergeRule,
+    get: impl Fn(&NormalizedMetadata) -> Option<T>,
+) -> Option<T> {
+    match rule {
+        MergeRule::Primary => results.first().and_then(|(_, m)| get(m)),
+        MergeRule::Prefer(provider) => results.iter()
+            .find(|(name, _)| name == provider)
+            .and_then(|(_, m)| get(m))
+            .or_else(|| results.iter().find_map(|(_, m)| get(m))),
+        MergeRule::Fill | MergeRule::Union => results.iter().find_map(|(_, m)| get(m)),
+    }
+}
+
+/// Merge provider results (in chain order) into a single record.
+/// `provider_ids` always collects the IDs of every provider that matched.
+pub fn merge(results: &[(String, NormalizedMetadata)], policy: &MergePolicy) -> Option<NormalizedMetadata> {
+    let (_, primary) = results.first()?;
+
+    let genres = match policy.rule("genres") {
+        MergeRule::Union => {
+            let mut all: Vec<String> = Vec::new();
+            for (_, m) in results {
+                for g in m.genres.iter().flatten() {
+                    if !all.iter().any(|existing| existing.eq_ignore_ascii_case(g)) {
+                        all.push(g.clone());
+                    }
+                }
+            }
+            Some(all).filter(|g| !g.is_empty())
+        }
+        rule => pick(results, rule, |m| m.genres.clone().filter(|g| !g.is_empty())),
+    };
+
+    let mut provider_ids = Map::new();
+    for (_, m) in results {
+        if let Some(Value::Object(ids)) = &m.provider_ids {
+            for (k, v) in ids {
+                provider_ids.entry(k.clone()).or_insert_with(|| v.clone());
+            }
+        }
+    }
+
+    Some(NormalizedMetadata {
+        title: pick(results, policy.rule("title"), |m| non_empty(&Some(m.title.clone())))
+            .unwrap_or_else(|| primary.title.clone()),
+        original_title: pick(results, policy.rule("original_title"), |m| non_empty(&m.original_title)),
+        year: pick(results, policy.rule("year"), |m| non_empty(&m.year)),
+        plot: pick(results, policy.rule("plot"), |m| non_empty(&m.plot)),
+        poster_url: pick(results, policy.rule("poster_url"), |m| non_empty(&m.poster_url)),
+        backdrop_url: pick(results, policy.rule("backdrop_url"), |m| non_empty(&m.backdrop_url)),
+        media_type: primary.media_type.clone().or_else(|| results.iter().find_map(|(_, m)| m.media_type.clone())),
+        provider_ids: Some(Value::Object(provider_ids)),
+        genres,
+        runtime: pick(results, policy.rule("runtime"), |m| m.runtime.filter(|r| *r > 0)),
+        rating: pick(results, policy.rule("rating"), |m| m.rating),
+    })
+}
+
+/// Merge episode lists from several providers. The first non-empty list defines the episodes;
+/// later lists only fill a missing name, overview, still or air date for the same episode number.
+pub fn merge_episodes(lists: Vec<Vec<EpisodeMetadata>>) -> Vec<EpisodeMetadata> {
+    let mut lists = lists.into_iter().filter(|l| !l.is_empty());
+    let mut merged = lists.next().unwrap_or_default();
+
+    for list in lists {
+        for ep in merged.iter_mut() {
+            if let Some(other) = list.iter().find(|o| o.episode_number == ep.episode_number) {
+                if ep.name.is_empty() { ep.name = other.name.clone(); }
+                if ep.overview.is_empty() { ep.overview = other.overview.clone(); }
+                if ep.still_path.is_none() { ep.still_path = other.still_path.clone(); }
+                if ep.air_date.is_none() { ep.air_date = other.air_date.clone(); }
+            }
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_json_accepts_rules_and_known_providers_only() {
+        let policy = MergePolicy::from_json(r#"{"genres": "union", "plot": "TVDB", "title": "nfo"}"#).unwrap();
+        assert_eq!(policy.rule("genres"), &MergeRule::Union);
+        assert_eq!(policy.rule("plot"), &MergeRule::Prefer("tvdb".to_string()));
+        assert_eq!(policy.rule("title"), &MergeRule::Prefer("nfo".to_string()));
+        assert_eq!(policy.rule("year"), &MergeRule::Fill);
+
+        assert!(matches!(MergePolicy::from_json(r#"{"plot": "tmbd"}"#), Err(AppError::BadRequest(_))));
+        assert!(matches!(MergePolicy::from_json(r#"{"plots": "fill"}"#), Err(AppError::BadRequest(_))));
+    }
+}
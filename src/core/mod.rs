@@ -1,4 +1,23 @@
+pub mod device_profile;
+pub mod disc;
+pub mod episode_numbering;
+pub mod extras;
+pub mod hls;
+pub mod language;
+pub mod media_groups;
+pub mod media_parts;
 pub mod media_service;
+pub mod media_streams;
+pub mod media_versions;
 pub mod metadata;
+pub mod metadata_merge;
+pub mod nfo_export;
+pub mod parsing_rules;
+pub mod probe;
+pub mod remux;
+pub mod retry_queue;
 pub mod scanner;
+pub mod subtitle_format;
+pub mod subtitles;
+pub mod trickplay;
 pub mod util;
@@ -0,0 +1,418 @@
+//! NFO Export - writes Kodi-compatible NFO files and sidecar artwork next to a library's media
+//! files, so curated metadata survives a database loss and is readable by other players
+//! (and by `providers::nfo` on the next scan).
+//!
+//! Files written use the names `providers::nfo` reads:
+//! - movies: `<name>.nfo`, `<name>-poster.jpg`, `<name>-fanart.jpg`; for a disc rip folder
+//!   `movie.nfo`, `poster.jpg`, `fanart.jpg` inside it
+//! - series: `tvshow.nfo`, `poster.jpg`, `fanart.jpg` in the series folder
+//! - episodes: `<name>.nfo` (one `<episodedetails>` per episode), `<name>-thumb.jpg`
+
+use crate::core::metadata::provider_id_str;
+use crate::dtos::nfo::{NfoDocument, NfoFanart, NfoThumb, NfoUniqueId};
+use crate::error::AppError;
+use crate::models::db::library::{Library, LibraryType};
+use crate::models::db::media::Media;
+use crate::providers::nfo::{self, ArtworkKind};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp"];
+
+/// What to do when a file the export would write already exists.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OverwritePolicy {
+    /// Leave existing NFOs and artwork untouched (default)
+    #[default]
+    Never,
+    /// Rewrite NFOs; only download artwork that is missing
+    NfoOnly,
+    /// Rewrite NFOs and re-download all artwork
+    Always,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportOutcome {
+    Written,
+    /// Dry run: the file would have been written
+    WouldWrite,
+    SkippedExisting,
+    Failed,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ExportAction {
+    pub path: String,
+    /// "nfo", "poster", "backdrop" or "still"
+    pub kind: &'static str,
+    pub outcome: ExportOutcome,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ExportReport {
+    pub library_id: i64,
+    pub dry_run: bool,
+    pub written: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub actions: Vec<ExportAction>,
+}
+
+impl ExportReport {
+    fn record(&mut self, path: &Path, kind: &'static str, outcome: ExportOutcome, error: Option<String>) {
+        match outcome {
+            ExportOutcome::Written | ExportOutcome::WouldWrite => self.written += 1,
+            ExportOutcome::SkippedExisting => self.skipped += 1,
+            ExportOutcome::Failed => self.failed += 1,
+        }
+        self.actions.push(ExportAction { path: path.display().to_string(), kind, outcome, error });
+    }
+}
+
+/// State of the most recent export of a library
+#[derive(Serialize, Debug, Clone)]
+pub struct ExportJob {
+    pub running: bool,
+    pub overwrite: OverwritePolicy,
+    pub started_at: chrono::NaiveDateTime,
+    pub finished_at: Option<chrono::NaiveDateTime>,
+    pub report: Option<ExportReport>,
+    pub error: Option<String>,
+}
+
+// One job per library, kept in memory until the next export of that library replaces it
+static EXPORT_JOBS: Lazy<Mutex<HashMap<i64, ExportJob>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Status of the latest export job for a library, if one has been started since startup.
+pub fn job_status(library_id: i64) -> Option<ExportJob> {
+    EXPORT_JOBS.lock().ok()?.get(&library_id).cloned()
+}
+
+/// Start a background export of a library. Fails if one is already running for it.
+pub fn start_job(pool: SqlitePool, library: Library, overwrite: OverwritePolicy) -> Result<ExportJob, AppError> {
+    let job = {
+        let mut jobs = EXPORT_JOBS.lock().map_err(|_| AppError::Internal("Export job state poisoned".into()))?;
+        if jobs.get(&library.id).is_some_and(|j| j.running) {
+            return Err(AppError::BadRequest(format!("An export is already running for library {}", library.id)));
+        }
+        let job = ExportJob {
+            running: true,
+            overwrite,
+            started_at: chrono::Utc::now().naive_utc(),
+            finished_at: None,
+            report: None,
+            error: None,
+        };
+        jobs.insert(library.id, job.clone());
+        job
+    };
+
+    tokio::spawn(async move {
+        let result = export_library(&pool, &library, false, overwrite).await;
+        match &result {
+            Ok(report) => println!("Exported library {}: {} written, {} skipped, {} failed", library.name, report.written, report.skipped, report.failed),
+            Err(e) => println!("Export of library {} failed: {}", library.name, e),
+        }
+        if let Ok(mut jobs) = EXPORT_JOBS.lock() {
+            if let Some(job) = jobs.get_mut(&library.id) {
+                job.running = false;
+                job.finished_at = Some(chrono::Utc::now().naive_utc());
+                match result {
+                    Ok(report) => job.report = Some(report),
+                    Err(e) => job.error = Some(e.to_string()),
+                }
+            }
+        }
+    });
+
+    Ok(job)
+}
+
+/// Write NFOs and artwork for every video in a library that has metadata.
+/// With `dry_run` nothing is written or downloaded; the report lists what would be.
+pub async fn export_library(
+    pool: &SqlitePool,
+    library: &Library,
+    dry_run: bool,
+    overwrite: OverwritePolicy,
+) -> Result<ExportReport, AppError> {
+    if library.library_type == LibraryType::Books {
+        return Err(AppError::BadRequest("NFO export is only supported for video libraries".into()));
+    }
+
+    let items = sqlx::query_as::<_, Media>("SELECT m.*, l.library_type FROM media m JOIN libraries l ON m.library_id = l.id WHERE m.library_id = ? AND m.extra_type IS NULL ORDER BY m.file_path, m.episode_number")
+        .bind(library.id)
+        .fetch_all(pool)
+        .await?;
+
+    let mut exporter = Exporter {
+        client: reqwest::Client::new(),
+        report: ExportReport { library_id: library.id, dry_run, ..Default::default() },
+        dry_run,
+        overwrite,
+    };
+    let mut exported_series: HashSet<PathBuf> = HashSet::new();
+    // Episode rows grouped by file; a multi-episode file gets one NFO with a block per episode
+    let mut episode_files: Vec<(&Media, Vec<NfoDocument>)> = Vec::new();
+
+    for media in &items {
+        // Rows that were never matched only carry the file name; an NFO would just pin that
+        if media.provider_ids.is_none() && media.plot.is_none() {
+            continue;
+        }
+        let video = Path::new(&media.file_path);
+        let Some(stem) = video.file_stem().map(|s| s.to_string_lossy().to_string()) else { continue };
+        let Some(dir) = video.parent() else { continue };
+
+        let series_dir = match (&library.library_type, &media.series_name) {
+            (LibraryType::TvShows, Some(_)) => nfo::series_dir(video, &library.path),
+            _ => None,
+        };
+
+        match series_dir {
+            Some(series_dir) => {
+                if exported_series.insert(series_dir.clone()) {
+                    exporter.write_nfo(&series_dir.join("tvshow.nfo"), "tvshow", &[series_document(media)]).await;
+                    let existing = nfo::find_series_artwork(&series_dir, ArtworkKind::Poster);
+                    exporter.artwork(existing, media.poster_url.as_deref(), &series_dir, "poster", ArtworkKind::Poster).await;
+                    let existing = nfo::find_series_artwork(&series_dir, ArtworkKind::Backdrop);
+                    exporter.artwork(existing, media.backdrop_url.as_deref(), &series_dir, "fanart", ArtworkKind::Backdrop).await;
+                }
+
+                match episode_files.last_mut() {
+                    Some((first, docs)) if first.file_path == media.file_path => docs.push(episode_document(media, &stem)),
+                    _ => episode_files.push((media, vec![episode_document(media, &stem)])),
+                }
+            }
+            None => {
+                // A disc rip folder is the movie's own; beside it is the library or a collection
+                let (dir, nfo_name, poster, fanart) = if media.disc_type.is_some() && video.is_dir() {
+                    (video, "movie.nfo".to_string(), "poster".to_string(), "fanart".to_string())
+                } else {
+                    (dir, format!("{}.nfo", stem), format!("{}-poster", stem), format!("{}-fanart", stem))
+                };
+                exporter.write_nfo(&dir.join(nfo_name), "movie", &[movie_document(media, &stem)]).await;
+                let existing = nfo::find_artwork(video, &library.path, None, ArtworkKind::Poster);
+                exporter.artwork(existing, media.poster_url.as_deref(), dir, &poster, ArtworkKind::Poster).await;
+                let existing = nfo::find_artwork(video, &library.path, None, ArtworkKind::Backdrop);
+                exporter.artwork(existing, media.backdrop_url.as_deref(), dir, &fanart, ArtworkKind::Backdrop).await;
+            }
+        }
+    }
+
+    for (media, docs) in episode_files {
+        let video = Path::new(&media.file_path);
+        let (Some(stem), Some(dir)) = (video.file_stem().map(|s| s.to_string_lossy().to_string()), video.parent()) else { continue };
+        exporter.write_nfo(&dir.join(format!("{}.nfo", stem)), "episodedetails", &docs).await;
+        let existing = nfo::find_still(video);
+        exporter.artwork(existing, media.still_url.as_deref(), dir, &format!("{}-thumb", stem), ArtworkKind::Still).await;
+    }
+
+    Ok(exporter.report)
+}
+
+struct Exporter {
+    client: reqwest::Client,
+    report: ExportReport,
+    dry_run: bool,
+    overwrite: OverwritePolicy,
+}
+
+impl Exporter {
+    async fn write_nfo(&mut self, path: &Path, root: &str, docs: &[NfoDocument]) {
+        if path.exists() && self.overwrite == OverwritePolicy::Never {
+            self.report.record(path, "nfo", ExportOutcome::SkippedExisting, None);
+            return;
+        }
+        if self.dry_run {
+            self.report.record(path, "nfo", ExportOutcome::WouldWrite, None);
+            return;
+        }
+
+        let result = match render_nfo(root, docs) {
+            Ok(xml) => tokio::fs::write(path, xml).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => self.report.record(path, "nfo", ExportOutcome::Written, None),
+            Err(e) => self.report.record(path, "nfo", ExportOutcome::Failed, Some(e)),
+        }
+    }
+
+    /// Download a remote image to `<dir>/<name>.<ext>` unless `existing` artwork is kept by
+    /// the overwrite policy. Local artwork URLs and items without an image are ignored.
+    async fn artwork(&mut self, existing: Option<PathBuf>, url: Option<&str>, dir: &Path, name: &str, kind: ArtworkKind) {
+        let Some(url) = url.filter(|u| u.starts_with("http://") || u.starts_with("https://")) else { return };
+        if let Some(existing) = existing.filter(|_| self.overwrite != OverwritePolicy::Always) {
+            self.report.record(&existing, kind.as_str(), ExportOutcome::SkippedExisting, None);
+            return;
+        }
+        let path = dir.join(format!("{}.{}", name, image_extension(url)));
+
+        if self.dry_run {
+            self.report.record(&path, kind.as_str(), ExportOutcome::WouldWrite, None);
+            return;
+        }
+
+        let result = async {
+            let resp = self.client.get(url).send().await.map_err(|e| e.to_string())?;
+            if !resp.status().is_success() {
+                return Err(format!("{} returned {}", url, resp.status()));
+            }
+            let bytes = resp.bytes().await.map_err(|e| e.to_string())?;
+            tokio::fs::write(&path, &bytes).await.map_err(|e| e.to_string())
+        }.await;
+
+        match result {
+            Ok(()) => self.report.record(&path, kind.as_str(), ExportOutcome::Written, None),
+            Err(e) => self.report.record(&path, kind.as_str(), ExportOutcome::Failed, Some(e)),
+        }
+    }
+}
+
+/// Render one or more documents (one per episode for multi-episode files) under a single XML declaration
+fn render_nfo(root: &str, docs: &[NfoDocument]) -> Result<String, String> {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n");
+    for doc in docs {
+        let mut serializer = quick_xml::se::Serializer::with_root(&mut xml, Some(root)).map_err(|e| e.to_string())?;
+        serializer.indent(' ', 2);
+        doc.serialize(serializer).map_err(|e| e.to_string())?;
+        xml.push('\n');
+    }
+    Ok(xml)
+}
+
+/// Image file extension from a URL, defaulting to jpg
+fn image_extension(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.rsplit('.').next()
+        .map(|e| e.to_lowercase())
+        .filter(|e| IMAGE_EXTENSIONS.contains(&e.as_str()))
+        .unwrap_or_else(|| "jpg".to_string())
+}
+
+fn non_empty(s: Option<&str>) -> Option<String> {
+    s.map(str::trim).filter(|s| !s.is_empty()).map(str::to_string)
+}
+
+fn remote_thumb(url: Option<&str>, aspect: Option<&str>) -> Option<NfoThumb> {
+    url.filter(|u| u.starts_with("http://") || u.starts_with("https://"))
+        .map(|u| NfoThumb { aspect: aspect.map(str::to_string), url: u.to_string() })
+}
+
+/// `<uniqueid>` tags for every provider ID, marking `preferred` (or the first ID) as the default
+fn unique_ids(provider_ids: Option<&str>, preferred: &str) -> Vec<NfoUniqueId> {
+    let Some(ids) = provider_ids.and_then(|s| serde_json::from_str::<Value>(s).ok()) else { return Vec::new() };
+    let Some(map) = ids.as_object() else { return Vec::new() };
+
+    let default = if map.contains_key(preferred) { preferred.to_string() } else { map.keys().next().cloned().unwrap_or_default() };
+    map.keys()
+        .filter_map(|provider| provider_id_str(&ids, provider).map(|value| NfoUniqueId {
+            kind: Some(provider.clone()),
+            default: (*provider == default).then(|| "true".to_string()),
+            value,
+        }))
+        .collect()
+}
+
+fn genres(media: &Media) -> Vec<String> {
+    media.genres.as_deref().unwrap_or("")
+        .split(',')
+        .map(|g| g.trim().to_string())
+        .filter(|g| !g.is_empty())
+        .collect()
+}
+
+fn year(media: &Media) -> Option<String> {
+    media.year.filter(|y| *y > 0).map(|y| y.to_string())
+}
+
+fn movie_document(media: &Media, stem: &str) -> NfoDocument {
+    NfoDocument {
+        // A title still equal to the file name was never matched; don't pin it
+        title: non_empty(media.title.as_deref()).filter(|t| t != stem),
+        originaltitle: non_empty(media.original_title.as_deref()),
+        year: year(media),
+        plot: non_empty(media.plot.as_deref()),
+        runtime: media.runtime.filter(|r| *r > 0).map(|r| r.to_string()),
+        genre: genres(media),
+        uniqueid: unique_ids(media.provider_ids.as_deref(), "tmdb"),
+        thumb: remote_thumb(media.poster_url.as_deref(), Some("poster")).into_iter().collect(),
+        fanart: remote_thumb(media.backdrop_url.as_deref(), None).map(|t| NfoFanart { thumb: vec![t] }),
+        ..Default::default()
+    }
+}
+
+fn series_document(media: &Media) -> NfoDocument {
+    NfoDocument {
+        title: non_empty(media.series_name.as_deref()),
+        originaltitle: non_empty(media.original_title.as_deref()),
+        year: year(media),
+        genre: genres(media),
+        uniqueid: unique_ids(media.provider_ids.as_deref(), "tvdb"),
+        thumb: remote_thumb(media.poster_url.as_deref(), Some("poster")).into_iter().collect(),
+        fanart: remote_thumb(media.backdrop_url.as_deref(), None).map(|t| NfoFanart { thumb: vec![t] }),
+        ..Default::default()
+    }
+}
+
+fn episode_document(media: &Media, stem: &str) -> NfoDocument {
+    NfoDocument {
+        title: non_empty(media.title.as_deref()).filter(|t| t != stem),
+        showtitle: non_empty(media.series_name.as_deref()),
+        season: media.season_number.map(|s| s.to_string()),
+        episode: media.episode_number.map(|e| e.to_string()),
+        plot: non_empty(media.plot.as_deref()),
+        thumb: remote_thumb(media.still_url.as_deref(), None).into_iter().collect(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn export_writes_into_a_disc_rip_folder_and_beside_a_file() {
+        let dir = std::env::temp_dir().join(format!("vortex-nfo-export-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let disc = dir.join("movies/Ran (1985)");
+        std::fs::create_dir_all(disc.join("VIDEO_TS")).unwrap();
+        let file = dir.join("movies/Heat (1995).mkv");
+        std::fs::write(&file, b"").unwrap();
+
+        let pool = crate::db::connect(&format!("sqlite:{}", dir.join("test.db").display())).await;
+        sqlx::query("INSERT INTO libraries (name, path, library_type) VALUES ('Movies', ?, 'movies')")
+            .bind(dir.join("movies").to_string_lossy()).execute(&pool).await.unwrap();
+        for (path, disc_type) in [(&disc, Some("dvd")), (&file, None)] {
+            sqlx::query("INSERT INTO media (file_path, title, library_id, media_type, plot, poster_url, backdrop_url, disc_type) VALUES (?, 'A film', 1, 'movie', 'Plot', 'https://images.example.com/p.jpg', 'https://images.example.com/b.png', ?)")
+                .bind(path.to_string_lossy()).bind(disc_type).execute(&pool).await.unwrap();
+        }
+        let library = sqlx::query_as::<_, Library>("SELECT * FROM libraries").fetch_one(&pool).await.unwrap();
+
+        let report = export_library(&pool, &library, true, OverwritePolicy::Never).await.unwrap();
+        let mut paths: Vec<&str> = report.actions.iter().map(|a| a.path.as_str()).collect();
+        paths.sort();
+        let expected = [
+            dir.join("movies/Heat (1995)-fanart.png"),
+            dir.join("movies/Heat (1995)-poster.jpg"),
+            dir.join("movies/Heat (1995).nfo"),
+            disc.join("fanart.png"),
+            disc.join("movie.nfo"),
+            disc.join("poster.jpg"),
+        ];
+        let expected: Vec<String> = expected.iter().map(|p| p.to_string_lossy().to_string()).collect();
+        assert_eq!(paths, expected);
+
+        pool.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,168 @@
+//! Parsing Rules - user-defined filename patterns tried before the scanner's built-in ones.
+//!
+//! A library's rules are stored in `libraries.parsing_rules` as a JSON array of regexes, e.g.
+//! `["^(?P<series>[^/]+)/Ep(?P<episode>\\d+)"]`. Each is matched case-insensitively against the
+//! file's path relative to the library root ("/" separated, extension included). The first rule
+//! that matches supplies whichever groups from `RULE_GROUPS` it captures; the built-in parsing
+//! fills in the rest.
+
+use crate::error::AppError;
+use crate::models::db::library::Library;
+use once_cell::sync::Lazy;
+use regex::{Regex, RegexBuilder};
+
+/// Named capture groups a rule may use.
+pub const RULE_GROUPS: &[&str] = &["series", "season", "episode", "chapter", "volume", "year", "title"];
+
+static RE_DIGITS: Lazy<Regex> = Lazy::new(|| Regex::new(r"\d+").unwrap());
+
+#[derive(Debug, Clone, Default)]
+pub struct ParsingRules {
+    rules: Vec<Regex>,
+}
+
+/// Values captured by the first matching rule. `rule` is its index in the library's list.
+#[derive(Debug, Clone, Default)]
+pub struct RuleMatch {
+    pub rule: usize,
+    pub series: Option<String>,
+    pub season: Option<i32>,
+    pub episode: Option<i32>,
+    pub chapter: Option<i32>,
+    pub volume: Option<i32>,
+    pub year: Option<i32>,
+    pub title: Option<String>,
+}
+
+impl ParsingRules {
+    /// Parse and compile rules from their JSON representation. Patterns that don't compile,
+    /// use unknown group names or capture none of `RULE_GROUPS` are rejected.
+    pub fn from_json(json: &str) -> Result<ParsingRules, AppError> {
+        let patterns: Vec<String> = serde_json::from_str(json)
+            .map_err(|e| AppError::BadRequest(format!("Invalid parsing rules: {}", e)))?;
+
+        let mut rules = Vec::new();
+        for pattern in patterns {
+            let regex = RegexBuilder::new(&pattern)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| AppError::BadRequest(format!("Invalid parsing rule '{}': {}", pattern, e)))?;
+
+            let names: Vec<&str> = regex.capture_names().flatten().collect();
+            if let Some(unknown) = names.iter().find(|n| !RULE_GROUPS.contains(n)) {
+                return Err(AppError::BadRequest(format!("Unknown capture group '{}' in parsing rule '{}'", unknown, pattern)));
+            }
+            if names.is_empty() {
+                return Err(AppError::BadRequest(format!("Parsing rule '{}' has no named capture groups", pattern)));
+            }
+            rules.push(regex);
+        }
+        Ok(ParsingRules { rules })
+    }
+
+    /// A library's rules. Stored rules that no longer parse are logged and ignored.
+    pub fn for_library(library: &Library) -> ParsingRules {
+        library.parsing_rules.as_deref()
+            .filter(|r| !r.trim().is_empty())
+            .and_then(|r| ParsingRules::from_json(r)
+                .map_err(|e| tracing::warn!("Ignoring parsing rules for library {}: {}", library.name, e))
+                .ok())
+            .unwrap_or_default()
+    }
+
+    /// Match `relative_path` against each rule in order and return the first match.
+    pub fn apply(&self, relative_path: &str) -> Option<RuleMatch> {
+        self.rules.iter().enumerate().find_map(|(index, regex)| {
+            let caps = regex.captures(relative_path)?;
+            let text = |name: &str| caps.name(name)
+                .map(|m| m.as_str().replace(['.', '_'], " ").trim().to_string())
+                .filter(|s| !s.is_empty());
+            let number = |name: &str| caps.name(name)
+                .and_then(|m| RE_DIGITS.find(m.as_str()))
+                .and_then(|m| m.as_str().parse().ok());
+
+            Some(RuleMatch {
+                rule: index,
+                series: text("series"),
+                season: number("season"),
+                episode: number("episode"),
+                chapter: number("chapter"),
+                volume: number("volume"),
+                year: number("year"),
+                title: text("title"),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::scanner::parse_path;
+    use crate::models::db::library::LibraryType;
+    use std::path::Path;
+
+    fn rules(patterns: &[&str]) -> Result<ParsingRules, AppError> {
+        ParsingRules::from_json(&serde_json::to_string(patterns).unwrap())
+    }
+
+    #[test]
+    fn from_json_validates_the_patterns() {
+        assert!(rules(&[r"^(?P<series>[^/]+)/Ep(?P<episode>\d+)"]).is_ok());
+        assert!(rules(&[]).is_ok());
+
+        let message = |result: Result<ParsingRules, AppError>| match result {
+            Err(AppError::BadRequest(message)) => message,
+            other => panic!("expected a bad request, got {:?}", other.map(|r| r.rules.len())),
+        };
+        assert!(message(rules(&[r"^(?P<series>[^/]+"])).starts_with("Invalid parsing rule"));
+        assert!(message(rules(&[r"^(?P<show>[^/]+)/(?P<episode>\d+)"])).contains("Unknown capture group 'show'"));
+        assert!(message(rules(&[r"^([^/]+)/(\d+)"])).contains("no named capture groups"));
+        assert!(message(ParsingRules::from_json(r#"{"rule": "x"}"#)).starts_with("Invalid parsing rules"));
+    }
+
+    #[test]
+    fn apply_uses_the_first_matching_rule() {
+        let rules = rules(&[
+            r"^(?P<series>[^/]+)/Ep(?P<episode>\d+)",
+            r"^(?P<series>[^/]+)/(?P<title>[^/]+?)\.(?P<year>\d{4})",
+        ]).unwrap();
+
+        let first = rules.apply("My_Show/EP012.mkv").unwrap();
+        assert_eq!((first.rule, first.series.as_deref(), first.episode), (0, Some("My Show"), Some(12)));
+
+        let second = rules.apply("My.Show/Pilot.2019.mkv").unwrap();
+        assert_eq!((second.rule, second.series.as_deref(), second.title.as_deref(), second.year), (1, Some("My Show"), Some("Pilot"), Some(2019)));
+        assert_eq!(second.episode, None);
+
+        assert!(rules.apply("loose.mkv").is_none());
+    }
+
+    #[test]
+    fn rules_win_over_the_built_in_parsing_for_what_they_capture() {
+        let library = Library {
+            id: 1,
+            name: "Anime".to_string(),
+            path: "/tv".to_string(),
+            library_type: LibraryType::TvShows,
+            metadata_providers: None,
+            metadata_merge_policy: None,
+            episode_order: None,
+            episode_numbering: None,
+            parsing_rules: None,
+        };
+        let rules = rules(&[r"/\[(?P<series>[^\]]+)\] Ep(?P<episode>\d+)"]).unwrap();
+
+        // The rule's series and episode replace the folder's and filename's; the season is built-in
+        let parsed = parse_path(Path::new("/tv/Folder/Season 2/[Real Name] Ep07 S01E03.mkv"), &library, &rules);
+        assert_eq!(parsed.matched_rule, Some(0));
+        assert_eq!(parsed.series_name.as_deref(), Some("Real Name"));
+        assert_eq!(parsed.episode_numbers, [7]);
+        assert_eq!(parsed.season_number, Some(1));
+
+        let parsed = parse_path(Path::new("/tv/Folder/Season 2/Folder.S02E03.mkv"), &library, &rules);
+        assert_eq!(parsed.matched_rule, None);
+        assert_eq!((parsed.series_name.as_deref(), parsed.season_number), (Some("Folder"), Some(2)));
+        assert_eq!(parsed.episode_numbers, [3]);
+    }
+}
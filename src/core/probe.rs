@@ -0,0 +1,188 @@
+//! Probe - reads technical information from media files with FFprobe.
+//!
+//! FFprobe is optional: when it isn't installed every probe returns `None` and callers fall
+//! back to what they know without it.
+
+use crate::core::disc;
+use std::path::Path;
+
+/// Find FFprobe - the same locations `remux::ffmpeg_command` checks for FFmpeg
+pub fn ffprobe_command() -> &'static str {
+    let ffprobe_paths = [
+        "C:\\ffmpeg\\bin\\ffprobe.exe",  // Common Windows install
+        "./ffmpeg/ffprobe.exe",           // Bundled with server (Windows)
+        "./ffmpeg/ffprobe",               // Bundled with server (Linux/Mac)
+    ];
+    ffprobe_paths.into_iter()
+        .find(|p| Path::new(p).exists())
+        .unwrap_or("ffprobe")                // System PATH
+}
+
+/// Run FFprobe on `path` and return its trimmed output. Disc images are read the way FFmpeg
+/// plays them (see `disc::input_args`), so they report their main title.
+async fn run(args: &[&str], path: &Path) -> Option<String> {
+    let input = match disc::is_iso(path) {
+        true => disc::input_args(path, disc::detect(path)?)?,
+        false => vec![path.to_string_lossy().to_string()],
+    };
+    let output = tokio::process::Command::new(ffprobe_command())
+        .args(args)
+        .args(&input)
+        .output()
+        .await
+        .map_err(|e| tracing::debug!("FFprobe unavailable: {}", e))
+        .ok()?;
+    if !output.status.success() {
+        tracing::warn!("FFprobe failed for {}: {}", path.display(), String::from_utf8_lossy(&output.stderr).trim());
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Duration of a media file in seconds.
+pub async fn duration(path: &Path) -> Option<f64> {
+    run(&["-v", "error", "-show_entries", "format=duration", "-of", "default=noprint_wrappers=1:nokey=1"], path).await?
+        .parse::<f64>().ok().filter(|d| *d > 0.0)
+}
+
+/// What playback decisions need to know about a file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediaInfo {
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    /// Overall bitrate in bits per second
+    pub bitrate: Option<u64>,
+    pub height: Option<u32>,
+}
+
+/// One stream of a file as FFprobe reports it.
+#[derive(Debug, Clone, Default)]
+pub struct ProbedStream {
+    pub index: i64,
+    /// video, audio or subtitle
+    pub stream_type: String,
+    pub codec: Option<String>,
+    pub profile: Option<String>,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub channels: Option<i64>,
+    pub channel_layout: Option<String>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub bitrate: Option<i64>,
+    pub frame_rate: Option<f64>,
+    /// HDR10, HLG or Dolby Vision; `None` for SDR
+    pub hdr: Option<String>,
+    pub is_default: bool,
+    pub is_forced: bool,
+    /// SDH/closed captions
+    pub is_hearing_impaired: bool,
+}
+
+/// Everything FFprobe tells about a file.
+#[derive(Debug, Clone, Default)]
+pub struct ProbeResult {
+    pub duration: Option<f64>,
+    pub bitrate: Option<i64>,
+    pub streams: Vec<ProbedStream>,
+}
+
+#[derive(serde::Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(serde::Deserialize)]
+struct ProbeStream {
+    index: i64,
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    profile: Option<String>,
+    channels: Option<i64>,
+    channel_layout: Option<String>,
+    width: Option<i64>,
+    height: Option<i64>,
+    bit_rate: Option<String>,
+    r_frame_rate: Option<String>,
+    color_transfer: Option<String>,
+    #[serde(default)]
+    side_data_list: Vec<ProbeSideData>,
+    #[serde(default)]
+    disposition: std::collections::HashMap<String, i64>,
+    #[serde(default)]
+    tags: std::collections::HashMap<String, String>,
+}
+
+#[derive(serde::Deserialize)]
+struct ProbeSideData {
+    side_data_type: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+    bit_rate: Option<String>,
+}
+
+/// "24000/1001" -> 23.976
+fn parse_rate(rate: &str) -> Option<f64> {
+    let (num, den) = rate.split_once('/')?;
+    let (num, den): (f64, f64) = (num.parse().ok()?, den.parse().ok()?);
+    (num > 0.0 && den > 0.0).then(|| num / den)
+}
+
+fn hdr_format(stream: &ProbeStream) -> Option<String> {
+    if stream.side_data_list.iter().any(|d| d.side_data_type.as_deref().is_some_and(|t| t.starts_with("DOVI"))) {
+        return Some("Dolby Vision".to_string());
+    }
+    match stream.color_transfer.as_deref() {
+        Some("smpte2084") => Some("HDR10".to_string()),
+        Some("arib-std-b67") => Some("HLG".to_string()),
+        _ => None,
+    }
+}
+
+/// Probe a file's format and every video, audio and subtitle stream.
+pub async fn probe(path: &Path) -> Option<ProbeResult> {
+    let json = run(&["-v", "error", "-show_format", "-show_streams", "-of", "json"], path).await?;
+    let output: ProbeOutput = serde_json::from_str(&json)
+        .map_err(|e| tracing::warn!("Unreadable FFprobe output for {}: {}", path.display(), e))
+        .ok()?;
+
+    let streams = output.streams.iter()
+        .filter_map(|s| {
+            let stream_type = s.codec_type.as_deref().filter(|t| matches!(*t, "video" | "audio" | "subtitle"))?;
+            // Cover art is stored as a single-frame video stream
+            if s.disposition.get("attached_pic") == Some(&1) {
+                return None;
+            }
+            Some(ProbedStream {
+                index: s.index,
+                stream_type: stream_type.to_string(),
+                codec: s.codec_name.clone(),
+                profile: s.profile.clone(),
+                language: s.tags.get("language").filter(|l| l.as_str() != "und").cloned(),
+                title: s.tags.get("title").cloned(),
+                channels: s.channels,
+                channel_layout: s.channel_layout.clone(),
+                width: s.width,
+                height: s.height,
+                bitrate: s.bit_rate.as_deref().and_then(|b| b.parse().ok()),
+                frame_rate: s.r_frame_rate.as_deref().and_then(parse_rate).filter(|_| stream_type == "video"),
+                hdr: hdr_format(s),
+                is_default: s.disposition.get("default") == Some(&1),
+                is_forced: s.disposition.get("forced") == Some(&1),
+                is_hearing_impaired: s.disposition.get("hearing_impaired") == Some(&1),
+            })
+        })
+        .collect();
+
+    let format = output.format;
+    Some(ProbeResult {
+        duration: format.as_ref().and_then(|f| f.duration.as_deref()?.parse().ok()).filter(|d: &f64| *d > 0.0),
+        bitrate: format.as_ref().and_then(|f| f.bit_rate.as_deref()?.parse().ok()),
+        streams,
+    })
+}
@@ -0,0 +1,87 @@
+//! Remux - repackages a video into a streamable container with FFmpeg, copying the streams
+//! without re-encoding. Used for sources a player can't open directly, such as disc rips, and
+//! for direct streaming: MKVs whose codecs a player decodes but whose container it can't play
+//! are sent as fragmented MP4, with only audio MP4 can't carry converted to AAC.
+
+use crate::error::AppError;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::process::ChildStdout;
+
+/// Find FFmpeg - check common locations first
+pub fn ffmpeg_command() -> &'static str {
+    let ffmpeg_paths = [
+        "C:\\ffmpeg\\bin\\ffmpeg.exe",  // Common Windows install
+        "./ffmpeg/ffmpeg.exe",           // Bundled with server (Windows)
+        "./ffmpeg/ffmpeg",               // Bundled with server (Linux/Mac)
+    ];
+    ffmpeg_paths.into_iter()
+        .find(|p| Path::new(p).exists())
+        .unwrap_or("ffmpeg")             // System PATH
+}
+
+/// Audio codecs that can be copied into MP4 as they are
+const MP4_AUDIO_CODECS: &[&str] = &["aac", "mp3", "ac3", "eac3", "opus", "flac", "alac"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Container {
+    /// Every video and audio stream, copied
+    Matroska,
+    /// Fragmented MP4 (playable while it's written) with the first video and audio stream;
+    /// audio is converted to AAC when `transcode_audio`
+    Mp4 { transcode_audio: bool },
+}
+
+impl Container {
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Container::Matroska => "video/x-matroska",
+            Container::Mp4 { .. } => "video/mp4",
+        }
+    }
+}
+
+/// Whether audio in `codec` (as FFprobe names it) has to be converted for MP4. Unknown audio
+/// is converted to be safe.
+pub fn mp4_needs_audio_transcode(codec: Option<&str>) -> bool {
+    codec.is_none_or(|c| !MP4_AUDIO_CODECS.contains(&c))
+}
+
+/// Start FFmpeg remuxing `input_args` to `container`, from `start` seconds in, and return its
+/// output. The process exits on its own once the reader goes away (broken pipe).
+pub fn spawn(input_args: &[String], start: Option<f64>, container: Container) -> Result<ChildStdout, AppError> {
+    let mut command = tokio::process::Command::new(ffmpeg_command());
+    command.args(["-hide_banner", "-loglevel", "error"]);
+    if let Some(start) = start.filter(|s| *s > 0.0) {
+        command.arg("-ss").arg(format!("{:.3}", start));
+    }
+    command.args(input_args);
+    match container {
+        Container::Matroska => {
+            command.args(["-map", "0:v:0", "-map", "0:a?", "-c", "copy", "-f", "matroska"]);
+        }
+        Container::Mp4 { transcode_audio } => {
+            command.args(["-map", "0:v:0", "-map", "0:a:0?", "-c:v", "copy"]);
+            if transcode_audio {
+                command.args(["-c:a", "aac", "-ac", "2", "-b:a", "192k"]);
+            } else {
+                command.args(["-c:a", "copy"]);
+            }
+            command.args(["-f", "mp4", "-movflags", "frag_keyframe+empty_moov+default_base_moof"]);
+        }
+    }
+    command.arg("pipe:1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+
+    let mut child = command.spawn()
+        .map_err(|e| AppError::Internal(format!("Failed to start FFmpeg: {}", e)))?;
+    let stdout = child.stdout.take()
+        .ok_or_else(|| AppError::Internal("FFmpeg has no output".to_string()))?;
+    // Reap the process when it finishes
+    tokio::spawn(async move {
+        let _ = child.wait().await;
+    });
+    Ok(stdout)
+}
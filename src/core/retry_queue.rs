@@ -0,0 +1,45 @@
+//! Metadata Retry Queue - remembers media whose metadata fetch failed with a transient
+//! provider error so the next scan can try again instead of leaving them unmatched.
+
+use sqlx::SqlitePool;
+use crate::error::AppError;
+
+/// Give up on an item after this many failed scan runs.
+pub const MAX_ATTEMPTS: i64 = 5;
+
+/// Add a media item to the queue, or bump its attempt count if it is already queued.
+pub async fn enqueue(pool: &SqlitePool, media_id: i64, error: &str) -> Result<(), AppError> {
+    sqlx::query(
+        "INSERT INTO metadata_retry_queue (media_id, attempts, last_error, updated_at)
+         VALUES (?, 1, ?, CURRENT_TIMESTAMP)
+         ON CONFLICT(media_id) DO UPDATE SET attempts = attempts + 1, last_error = ?, updated_at = CURRENT_TIMESTAMP"
+    )
+    .bind(media_id)
+    .bind(error)
+    .bind(error)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Remove a media item from the queue (metadata succeeded, or retrying is pointless).
+pub async fn remove(pool: &SqlitePool, media_id: i64) -> Result<(), AppError> {
+    sqlx::query("DELETE FROM metadata_retry_queue WHERE media_id = ?")
+        .bind(media_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Get all queued media IDs with their attempt counts, oldest first.
+pub async fn pending(pool: &SqlitePool) -> Result<Vec<(i64, i64)>, AppError> {
+    let rows: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT media_id, attempts FROM metadata_retry_queue ORDER BY updated_at ASC"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
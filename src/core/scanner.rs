@@ -4,18 +4,52 @@ use std::path::Path;
 use regex::Regex;
 use once_cell::sync::Lazy;
 use crate::models::db::library::{Library, LibraryType};
-use crate::core::metadata::{fetch_metadata, fetch_episodes, get_default_provider};
+use crate::core::metadata::{fetch_metadata, fetch_episodes};
+use crate::core::retry_queue;
+use crate::core::{media_parts, media_streams, media_versions, probe};
+use crate::core::disc::{self, DiscType};
+use crate::core::extras::{self, DetectedExtra};
+use crate::core::episode_numbering::{self, EpisodeKey, EpisodeNumbering};
+use crate::core::parsing_rules::ParsingRules;
+use chrono::NaiveDate;
+use serde::Serialize;
+use crate::error::AppError;
+use crate::models::db::media::Media;
+use crate::providers::nfo::{self, ArtworkKind};
+
+/// Extensions of the video files a scan picks up (disc images aside)
+pub const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "avi", "mov", "webm", "wmv", "m4v", "mpg", "mpeg", "flv", "ts"];
 
 // Cached regex patterns - compiled once at first use, reused for all subsequent calls
 // Episode number patterns (most common first for faster matching)
-static RE_SEASON_EPISODE: Lazy<Regex> = Lazy::new(|| Regex::new(r"s\d+e(\d+)").unwrap());
-static RE_EPISODE_X: Lazy<Regex> = Lazy::new(|| Regex::new(r"\d+x(\d+)").unwrap());
+static RE_SEASON_EPISODE: Lazy<Regex> = Lazy::new(|| Regex::new(r"s(\d+)[\s._]*e(\d+)").unwrap());
+static RE_EPISODE_X: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|\D)(\d{1,2})x(\d{1,3})(?:\D|$)").unwrap());
 static RE_EPISODE_WORD: Lazy<Regex> = Lazy::new(|| Regex::new(r"ep(?:isode)?\s*(\d+)").unwrap());
 static RE_EPISODE_DASH: Lazy<Regex> = Lazy::new(|| Regex::new(r"[-\s](\d{1,3})[-\s]").unwrap());
 static RE_EPISODE_E: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|\s)e(\d+)").unwrap());
 
-// Season parsing pattern
+// Further episodes of a multi-episode file, matched right after the first one:
+// "E02" / "-E03" / "-S01E02", "-1x02", and a bare "-02"
+static RE_NEXT_EPISODE_E: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[\s._]*(-?)[\s._]*(?:s\d+)?e(\d+)").unwrap());
+static RE_NEXT_EPISODE_X: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[\s._]*(-?)[\s._]*\d{1,2}x(\d{1,3})").unwrap());
+static RE_NEXT_EPISODE_DASH: Lazy<Regex> = Lazy::new(|| Regex::new(r"^-(\d{1,3})(?:\D|$)").unwrap());
+
+/// Upper bound on how many episodes one file can span, to reject things like "S01E01-720p"
+const MAX_EPISODES_PER_FILE: i32 = 20;
+
+// Absolute (anime) numbering, matched after bracketed tags like "[Group]" or "(1080p)" are removed:
+// "Show - 137", "Show - 137v2", "Show Ep 137", or a number ending the name
+static RE_BRACKETED: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[[^\]]*\]|\([^)]*\)|\{[^}]*\}").unwrap());
+static RE_ABSOLUTE_DASH: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s-\s*(\d{1,4})(?:v\d)?(?:[\s._-]|$)").unwrap());
+static RE_ABSOLUTE_EPISODE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|[\s._-])ep?(?:isode)?[\s._]*(\d{1,4})(?:v\d)?(?:[\s._-]|$)").unwrap());
+static RE_ABSOLUTE_TRAILING: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|[\s._-])(\d{1,4})(?:v\d)?$").unwrap());
+
+// Air dates for daily shows: "2024.03.15", "2024-03-15", "2024 03 15"
+static RE_AIR_DATE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|\D)(\d{4})[.\-_ ](\d{2})[.\-_ ](\d{2})(?:\D|$)").unwrap());
+
+// Season parsing patterns
 static RE_SEASON: Lazy<Regex> = Lazy::new(|| Regex::new(r"season\s*(\d+)").unwrap());
+static RE_SPECIALS: Lazy<Regex> = Lazy::new(|| Regex::new(r"^specials?$").unwrap());
 
 // Chapter number patterns for comics/books
 static RE_CHAPTER: Lazy<Regex> = Lazy::new(|| Regex::new(r"chapter[_\-\s]*(\d+)").unwrap());
@@ -26,6 +60,9 @@ pub async fn scan_media(pool: &SqlitePool) {
     // Clean up stale paths first (renamed/deleted files)
     cleanup_missing_files(pool).await;
 
+    // Revisit items whose metadata fetch failed during a previous run
+    process_retry_queue(pool).await;
+
     let libraries = sqlx::query_as::<_, Library>("SELECT * FROM libraries")
         .fetch_all(pool)
         .await
@@ -33,19 +70,38 @@ pub async fn scan_media(pool: &SqlitePool) {
 
     for library in libraries {
         println!("Scanning library: {} (type: {:?})", library.name, library.library_type);
-        for entry in WalkDir::new(&library.path).into_iter().filter_map(|e| e.ok()) {
+        let rules = ParsingRules::for_library(&library);
+        let scans_discs = matches!(library.library_type, LibraryType::Movies | LibraryType::Other);
+        let mut entries = WalkDir::new(&library.path).into_iter();
+        while let Some(entry) = entries.next() {
+            let Ok(entry) = entry else { continue };
             let path = entry.path();
+
+            // A VIDEO_TS/BDMV folder is one disc: its parent is scanned as a movie, never the fragments inside
+            if entry.file_type().is_dir() && DiscType::from_folder(&entry.file_name().to_string_lossy()).is_some() {
+                entries.skip_current_dir();
+                if let (true, Some(root)) = (scans_discs, path.parent()) {
+                    process_video(pool, root, &library, &rules).await;
+                }
+                continue;
+            }
+
             if path.is_file() {
                 if let Some(ext) = path.extension() {
                     let ext_str = ext.to_string_lossy().to_lowercase();
-                    if ["mp4", "mkv", "avi", "mov", "webm", "wmv", "m4v", "mpg", "mpeg", "flv", "ts"].contains(&ext_str.as_str()) {
-                        process_video(pool, path, &library).await;
+                    if VIDEO_EXTENSIONS.contains(&ext_str.as_str())
+                        || (scans_discs && ext_str == "iso") {
+                        process_video(pool, path, &library, &rules).await;
                     } else if ["pdf", "epub", "cbz", "zip", "cbx"].contains(&ext_str.as_str()) {
-                        process_book(pool, path, &library).await;
+                        process_book(pool, path, &library, &rules).await;
                     }
                 }
             }
         }
+        // Extras may be walked before their movie, so they are attached once the whole library is in
+        if let Err(e) = extras::link_extras(pool, library.id, &library.path).await {
+            println!("Failed to link extras for library {}: {}", library.name, e);
+        }
     }
 }
 
@@ -60,122 +116,578 @@ async fn cleanup_missing_files(pool: &SqlitePool) {
         let path = Path::new(&path_str);
         if !path.exists() {
             println!("Removing missing file from DB: {}", path_str);
-            let _ = sqlx::query("DELETE FROM media WHERE id = ?").bind(id).execute(pool).await;
-            let _ = sqlx::query("DELETE FROM playback_progress WHERE media_id = ?").bind(id).execute(pool).await;
+            let _ = media_versions::promote_next_version(pool, id).await;
+            let _ = media_parts::promote_next_part(pool, id).await;
+            let _ = extras::unlink_extras(pool, id).await;
+            if let Err(e) = delete_media(pool, id).await {
+                println!("Failed to remove {} from DB: {}", path_str, e);
+            }
         }
     }
 }
 
-fn parse_tv_show_info(path: &Path, library_path: &str, library_name: &str) -> Option<(String, i32, i32)> {
+/// Delete a media row together with the rows referencing it, which must go first while
+/// foreign keys are enforced.
+async fn delete_media(pool: &SqlitePool, id: i64) -> Result<(), AppError> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM media_streams WHERE media_id = ?").bind(id).execute(&mut *tx).await?;
+    sqlx::query("DELETE FROM metadata_retry_queue WHERE media_id = ?").bind(id).execute(&mut *tx).await?;
+    sqlx::query("DELETE FROM playback_progress WHERE media_id = ?").bind(id).execute(&mut *tx).await?;
+    sqlx::query("DELETE FROM media WHERE id = ?").bind(id).execute(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// What the scanner reads from a file's path. Only the fields relevant to the library type are set.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ParsedPath {
+    /// Index of the library's custom parsing rule that matched, if any
+    pub matched_rule: Option<usize>,
+    pub series_name: Option<String>,
+    pub season_number: Option<i32>,
+    /// Every episode a TV file contains
+    pub episode_numbers: Vec<i32>,
+    pub chapter_number: Option<i32>,
+    /// Book volume, stored in `season_number` like chapters are in `episode_number`
+    pub volume_number: Option<i32>,
+    pub year: Option<i32>,
+    /// Title to show until metadata is found; also the search term for movies
+    pub title: Option<String>,
+}
+
+/// Parse a file's path the way a scan would: the library's custom rules first, with the
+/// built-in patterns filling in whatever the matching rule doesn't capture.
+pub fn parse_path(path: &Path, library: &Library, rules: &ParsingRules) -> ParsedPath {
+    let relative = path.strip_prefix(&library.path).ok()
+        .map(|r| r.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/"));
+    let mut parsed = ParsedPath::default();
+
+    match library.library_type {
+        LibraryType::TvShows => {
+            let numbering = EpisodeNumbering::parse(library.episode_numbering.as_deref());
+            if let Some((series, season, episodes)) = parse_tv_show_info(path, &library.path, &library.name, numbering) {
+                parsed.series_name = Some(series);
+                parsed.season_number = Some(season);
+                parsed.episode_numbers = episodes;
+            }
+        }
+        LibraryType::Books => {
+            let (series, chapter) = parse_comic_info(path, &library.path);
+            parsed.series_name = series;
+            parsed.chapter_number = chapter;
+        }
+        _ => {}
+    }
+
+    let Some(rule) = relative.and_then(|r| rules.apply(&r)) else { return parsed };
+    parsed.matched_rule = Some(rule.rule);
+    parsed.year = rule.year;
+    parsed.title = rule.title;
+    match library.library_type {
+        LibraryType::TvShows => {
+            parsed.series_name = rule.series.or(parsed.series_name);
+            parsed.season_number = rule.season.or(parsed.season_number);
+            if let Some(episode) = rule.episode {
+                parsed.episode_numbers = vec![episode];
+            }
+        }
+        LibraryType::Books => {
+            parsed.series_name = rule.series.or(parsed.series_name);
+            parsed.chapter_number = rule.chapter.or(parsed.chapter_number);
+            parsed.volume_number = rule.volume;
+        }
+        _ => {}
+    }
+    parsed
+}
+
+/// Series name, season and episode numbers for a TV file. A season in the filename ("S00E03",
+/// "2x05") wins over the folder; "Specials" folders are season 0. Multi-episode files return
+/// every episode they contain. Absolute-numbered and dated files get provisional numbers
+/// (see `EpisodeKey::provisional`) until metadata maps them onto the provider's seasons.
+fn parse_tv_show_info(path: &Path, library_path: &str, library_name: &str, numbering: EpisodeNumbering) -> Option<(String, i32, Vec<i32>)> {
     let relative = path.strip_prefix(library_path).ok()?;
     let components: Vec<_> = relative.components().collect();
     
     if components.is_empty() { return None; }
 
     let filename = path.file_stem()?.to_string_lossy().to_string();
-    let episode_number = parse_episode_number(&filename).unwrap_or(1);
+    let (mut file_season, mut episodes) = parse_episode_numbers(&filename);
+    if let Some(key) = parse_episode_key(&filename, numbering) {
+        let (season, episode) = key.provisional();
+        file_season = season;
+        episodes = vec![episode];
+    }
+    if episodes.is_empty() {
+        episodes.push(1);
+    }
 
     if components.len() >= 3 {
         let series_name = components[0].as_os_str().to_string_lossy().to_string();
         let season_folder = components[1].as_os_str().to_string_lossy().to_lowercase();
-        let season_number = RE_SEASON.captures(&season_folder)
-            .and_then(|c| c.get(1).and_then(|m| m.as_str().parse().ok())).unwrap_or(1);
-        return Some((series_name, season_number, episode_number));
+        let folder_season = if RE_SPECIALS.is_match(season_folder.trim()) {
+            Some(0)
+        } else {
+            RE_SEASON.captures(&season_folder).and_then(|c| c.get(1).and_then(|m| m.as_str().parse().ok()))
+        };
+        return Some((series_name, file_season.or(folder_season).unwrap_or(1), episodes));
     }
     
     if components.len() == 2 {
         let series_name = components[0].as_os_str().to_string_lossy().to_string();
-        return Some((series_name, 1, episode_number));
+        return Some((series_name, file_season.unwrap_or(1), episodes));
     }
     
     if components.len() == 1 {
-        return Some((library_name.to_string(), 1, episode_number));
+        return Some((library_name.to_string(), file_season.unwrap_or(1), episodes));
     }
     
     None
 }
 
-fn parse_episode_number(filename: &str) -> Option<i32> {
+/// Season (when the name carries one) and episode numbers from a filename.
+/// "S01E01-E03" is a range (1, 2, 3); "S01E01E02" lists episodes.
+fn parse_episode_numbers(filename: &str) -> (Option<i32>, Vec<i32>) {
     let lower = filename.to_lowercase();
+
     // Use cached regex patterns - no recompilation on each call
-    if let Some(caps) = RE_SEASON_EPISODE.captures(&lower) { return caps.get(1)?.as_str().parse().ok(); }
-    if let Some(caps) = RE_EPISODE_X.captures(&lower) { return caps.get(1)?.as_str().parse().ok(); }
-    if let Some(caps) = RE_EPISODE_WORD.captures(&lower) { return caps.get(1)?.as_str().parse().ok(); }
-    if let Some(caps) = RE_EPISODE_DASH.captures(&lower) { return caps.get(1)?.as_str().parse().ok(); }
-    if let Some(caps) = RE_EPISODE_E.captures(&lower) { return caps.get(1)?.as_str().parse().ok(); }
-    None
+    let (season, first, mut rest) = if let Some(caps) = RE_SEASON_EPISODE.captures(&lower) {
+        (caps[1].parse().ok(), caps[2].parse().ok(), &lower[caps.get(0).map_or(0, |m| m.end())..])
+    } else if let Some(caps) = RE_EPISODE_X.captures(&lower) {
+        (caps[1].parse().ok(), caps[2].parse().ok(), &lower[caps.get(2).map_or(0, |m| m.end())..])
+    } else {
+        let episode = [&*RE_EPISODE_WORD, &*RE_EPISODE_DASH, &*RE_EPISODE_E].iter()
+            .find_map(|re| re.captures(&lower).and_then(|c| c[1].parse().ok()));
+        return (None, episode.into_iter().collect());
+    };
+
+    let Some(first) = first else { return (season, Vec::new()) };
+    let mut episodes = vec![first];
+
+    loop {
+        let (is_range, number, consumed) = if let Some(caps) = RE_NEXT_EPISODE_E.captures(rest).or_else(|| RE_NEXT_EPISODE_X.captures(rest)) {
+            (!caps[1].is_empty(), caps[2].parse::<i32>().ok(), caps.get(0).map_or(0, |m| m.end()))
+        } else if let Some(caps) = RE_NEXT_EPISODE_DASH.captures(rest) {
+            (true, caps[1].parse::<i32>().ok(), caps.get(1).map_or(0, |m| m.end()))
+        } else {
+            break;
+        };
+
+        let last = *episodes.last().unwrap_or(&first);
+        let Some(number) = number.filter(|n| *n > last && *n - first < MAX_EPISODES_PER_FILE) else { break };
+        if is_range {
+            episodes.extend(last + 1..=number);
+        } else {
+            episodes.push(number);
+        }
+        rest = &rest[consumed..];
+    }
+
+    (season, episodes)
 }
 
-async fn process_video(pool: &SqlitePool, path: &Path, library: &Library) {
+/// Absolute episode number or air date, for libraries whose filenames use them.
+/// A filename that does carry a season ("S02E05", "2x05") is left to the standard parser.
+pub(crate) fn parse_episode_key(filename: &str, numbering: EpisodeNumbering) -> Option<EpisodeKey> {
+    if numbering == EpisodeNumbering::Standard || parse_episode_numbers(filename).0.is_some() {
+        return None;
+    }
+    let lower = filename.to_lowercase();
+
+    match numbering {
+        EpisodeNumbering::Absolute => {
+            let stripped = RE_BRACKETED.replace_all(&lower, " ");
+            let stripped = stripped.trim_end_matches([' ', '.', '_', '-']);
+            [&*RE_ABSOLUTE_DASH, &*RE_ABSOLUTE_EPISODE].iter()
+                .find_map(|re| re.captures(stripped).and_then(|c| c[1].parse::<i32>().ok()))
+                // A bare trailing number could just as well be a year
+                .or_else(|| RE_ABSOLUTE_TRAILING.captures(stripped)
+                    .and_then(|c| c[1].parse::<i32>().ok())
+                    .filter(|n| !(1900..=2099).contains(n)))
+                .filter(|n| *n > 0)
+                .map(EpisodeKey::Absolute)
+        }
+        EpisodeNumbering::Date => RE_AIR_DATE.captures(&lower)
+            .and_then(|c| NaiveDate::from_ymd_opt(c[1].parse().ok()?, c[2].parse().ok()?, c[3].parse().ok()?))
+            .map(EpisodeKey::AirDate),
+        EpisodeNumbering::Standard => None,
+    }
+}
+
+/// Scan a video file, or a disc rip given as its root folder or `.iso` image.
+async fn process_video(pool: &SqlitePool, path: &Path, library: &Library, rules: &ParsingRules) {
     let path_str = path.to_string_lossy().to_string();
-    let file_stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "Unknown".to_string());
-    
-    let (series_name, season_number, episode_number) = if library.library_type == LibraryType::TvShows {
-        parse_tv_show_info(path, &library.path, &library.name)
-            .map(|(s, sn, en)| (Some(s), Some(sn), Some(en)))
-            .unwrap_or((None, None, None))
+    let disc = disc::detect(path);
+    // A disc folder's whole name is its title ("Heat.1995" has no extension to strip)
+    let file_stem = if path.is_dir() { path.file_name() } else { path.file_stem() }
+        .map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "Unknown".to_string());
+    let mut parsed = parse_path(path, library, rules);
+
+    let is_tv = library.library_type == LibraryType::TvShows;
+    if is_tv || library.library_type == LibraryType::Movies {
+        if let Some(extra) = extras::detect(path, &library.path) {
+            process_extra(pool, path, library, &parsed, &extra).await;
+            return;
+        }
+    }
+    reclaim_from_extras(pool, path, library, &parsed).await;
+
+    // "Movie.cd2.avi" is one part of a movie split across files; the part marker isn't part of its title
+    let part = if library.library_type == LibraryType::Movies { media_parts::detect(path, &file_stem, &library.path) } else { None };
+    if let (Some(part), None) = (&part, &parsed.title) {
+        parsed.title = Some(part.title.clone());
+    }
+
+    let title = parsed.title.clone().unwrap_or_else(|| file_stem.clone());
+    let (series_name, season_number) = (&parsed.series_name, parsed.season_number);
+
+    // One entry per episode the file contains; a single `None` for movies
+    let episode_numbers: Vec<Option<i32>> = if parsed.episode_numbers.is_empty() {
+        vec![None]
     } else {
-        (None, None, None)
+        parsed.episode_numbers.iter().copied().map(Some).collect()
     };
 
-    let existing: Option<(i64,)> = sqlx::query_as("SELECT id FROM media WHERE file_path = ?")
-        .bind(&path_str).fetch_optional(pool).await.unwrap_or(None);
+    let existing: Vec<(i64, Option<i32>)> = sqlx::query_as("SELECT id, episode_number FROM media WHERE file_path = ?")
+        .bind(&path_str).fetch_all(pool).await.unwrap_or_default();
 
-    if let Some((_id,)) = existing {
+    if !existing.is_empty() {
         let _ = sqlx::query("UPDATE media SET library_id = ? WHERE file_path = ?").bind(library.id).bind(&path_str).execute(pool).await;
-        if library.library_type == LibraryType::TvShows && series_name.is_some() {
+        if library.library_type == LibraryType::TvShows && series_name.is_some() && episode_numbers.len() == 1 {
             let _ = sqlx::query("UPDATE media SET series_name = ?, season_number = ?, episode_number = ? WHERE file_path = ? AND series_name IS NULL")
-                .bind(&series_name).bind(season_number).bind(episode_number).bind(&path_str).execute(pool).await;
+                .bind(series_name).bind(season_number).bind(episode_numbers[0]).bind(&path_str).execute(pool).await;
+        }
+    }
+
+    // Parts and duplicates scanned before they were stacked or grouped join their movie now
+    if let ([(id, _)], LibraryType::Movies) = (existing.as_slice(), &library.library_type) {
+        regroup_movie(pool, *id, path, &file_stem, library, &parsed, part.as_ref()).await;
+    }
+
+    // A known single-episode file keeps its row even if its episode was renumbered since;
+    // a multi-episode file gains rows for episodes it doesn't have yet
+    let missing: Vec<Option<i32>> = if existing.is_empty() {
+        episode_numbers
+    } else if episode_numbers.len() > 1 {
+        episode_numbers.into_iter().filter(|ep| !existing.iter().any(|(_, e)| e == ep)).collect()
+    } else {
+        Vec::new()
+    };
+
+    // A new file of a movie that's already in the library becomes one of its versions or parts
+    let is_new_movie = library.library_type == LibraryType::Movies && existing.is_empty();
+    let (version, version_of, part_of) = match &part {
+        _ if !is_new_movie => (Default::default(), None, None),
+        Some(part) => (Default::default(), None, media_parts::find_stack(pool, library.id, path, &part.key).await.unwrap_or(None)),
+        None => {
+            let info = media_versions::version_info(&file_stem);
+            let primary = media_versions::find_primary(pool, library.id, path, &info.key).await.unwrap_or(None);
+            (info, primary, None)
+        }
+    };
+
+    for episode_number in missing {
+        let inserted = sqlx::query("INSERT INTO media (file_path, title, year, library_id, series_name, season_number, episode_number, version_of, resolution, edition, part_of, part_number, disc_type) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(&path_str).bind(&title).bind(parsed.year).bind(library.id).bind(series_name).bind(season_number).bind(episode_number)
+            .bind(version_of).bind(&version.resolution).bind(&version.edition)
+            .bind(part_of).bind(part.as_ref().map(|p| p.part_number)).bind(disc.map(|d| d.as_str())).execute(pool).await;
+        let Ok(res) = inserted else { continue };
+        let media_id = res.last_insert_rowid();
+
+        // A disc's length is its main title's; files get theirs from the stream probe below
+        if let Some(disc) = disc {
+            if let Some(duration) = disc::duration(path, disc).await {
+                let _ = media_parts::set_duration(pool, media_id, duration).await;
+            }
+        }
+
+        if let Some(primary_id) = version_of {
+            let _ = media_versions::copy_metadata(pool, primary_id, media_id).await;
+            println!("Added {} as another version of media {}", file_stem, primary_id);
+            continue;
         }
+        if let Some(primary_id) = part_of {
+            let _ = media_versions::copy_metadata(pool, primary_id, media_id).await;
+            println!("Added {} as part {} of media {}", file_stem, part.as_ref().map_or(0, |p| p.part_number), primary_id);
+            continue;
+        }
+
+        if library.library_type == LibraryType::Other {
+            let _ = sqlx::query("UPDATE media SET media_type = 'movie' WHERE id = ?").bind(media_id).execute(pool).await;
+            continue;
+        }
+
+        match apply_video_metadata(pool, media_id, &path_str, library, &parsed, episode_number).await {
+            Ok(()) => println!("Updated metadata for: {}", file_stem),
+            Err(AppError::External(e)) => {
+                // Transient provider failure (rate limit, 5xx, network) - try again on the next scan
+                println!("Metadata fetch failed for {}, queued for retry: {}", file_stem, e);
+                let _ = retry_queue::enqueue(pool, media_id, &e).await;
+            }
+            Err(_) => {}
+        }
+    }
+
+    probe_streams(pool, path, disc).await;
+}
+
+/// Probe the streams of a file for its rows that don't have them yet (new files, and files
+/// scanned before probing existed). A disc folder is probed through the first file of its main
+/// title, an image through FFmpeg's disc input.
+async fn probe_streams(pool: &SqlitePool, path: &Path, disc: Option<DiscType>) {
+    let ids: Vec<i64> = sqlx::query_scalar(
+        "SELECT id FROM media WHERE file_path = ? AND NOT EXISTS (SELECT 1 FROM media_streams s WHERE s.media_id = media.id)"
+    )
+    .bind(path.to_string_lossy().to_string())
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+    if ids.is_empty() {
         return;
     }
 
-    let _ = sqlx::query("INSERT INTO media (file_path, title, library_id, series_name, season_number, episode_number) VALUES (?, ?, ?, ?, ?, ?)")
-        .bind(&path_str).bind(&file_stem).bind(library.id).bind(&series_name).bind(season_number).bind(episode_number).execute(pool).await;
+    let is_folder = disc.is_some() && !disc::is_iso(path);
+    let probe_path = match disc {
+        Some(disc) if is_folder => match disc::main_title(path, disc).and_then(|t| t.files.into_iter().next()) {
+            Some(file) => file,
+            None => return,
+        },
+        _ => path.to_path_buf(),
+    };
+    let Some(mut result) = probe::probe(&probe_path).await else { return };
+    if is_folder {
+        // One file of the title isn't the length of the disc
+        result.duration = None;
+    }
+    for id in ids {
+        let _ = media_streams::store(pool, id, &result).await;
+    }
+}
+
+/// Stack or group a known movie file the way a new one would be, and take a file an earlier
+/// scan stacked as a part (before "part"/"pt" needed a movie folder) back out of its stack.
+async fn regroup_movie(pool: &SqlitePool, id: i64, path: &Path, file_stem: &str, library: &Library, parsed: &ParsedPath, part: Option<&media_parts::PartInfo>) {
+    if let Some(part) = part {
+        if let Ok(Some(primary_id)) = media_parts::stack_existing(pool, id, library.id, path, part).await {
+            println!("Stacked {} as part {} of media {}", file_stem, part.part_number, primary_id);
+        }
+        return;
+    }
+
+    if let Ok(true) = media_parts::unstack(pool, id).await {
+        // Its metadata was the stack's, found by a title without the marker
+        let path_str = path.to_string_lossy().to_string();
+        let _ = sqlx::query("UPDATE media SET title = ? WHERE id = ?").bind(file_stem).bind(id).execute(pool).await;
+        println!("Unstacked {}", path_str);
+        if let Err(AppError::External(e)) = apply_video_metadata(pool, id, &path_str, library, parsed, None).await {
+            println!("Metadata fetch failed for {}, queued for retry: {}", path_str, e);
+            let _ = retry_queue::enqueue(pool, id, &e).await;
+        }
+    }
+    if let Ok(Some(primary_id)) = media_versions::group_existing(pool, id, library.id, path, file_stem).await {
+        println!("Grouped {} as another version of media {}", file_stem, primary_id);
+    }
+}
+
+/// Turn a movie an earlier scan took for an extra (it sat in a top-level "Shorts" or "Bonus"
+/// folder) back into a movie with its own metadata.
+async fn reclaim_from_extras(pool: &SqlitePool, path: &Path, library: &Library, parsed: &ParsedPath) {
+    if library.library_type != LibraryType::Movies {
+        return;
+    }
+    let path_str = path.to_string_lossy().to_string();
+    let id: Option<i64> = sqlx::query_scalar("SELECT id FROM media WHERE file_path = ? AND extra_type IS NOT NULL ORDER BY id LIMIT 1")
+        .bind(&path_str).fetch_optional(pool).await.unwrap_or(None);
+    let Some(id) = id else { return };
 
-    if library.library_type == LibraryType::Other {
-        let _ = sqlx::query("UPDATE media SET media_type = 'movie' WHERE file_path = ?").bind(&path_str).execute(pool).await;
+    let title = parsed.title.clone()
+        .or_else(|| path.file_stem().map(|s| s.to_string_lossy().to_string()))
+        .unwrap_or_else(|| "Unknown".to_string());
+    let updated = sqlx::query("UPDATE media SET title = ?, media_type = NULL, extra_type = NULL, extra_of = NULL WHERE id = ?")
+        .bind(&title).bind(id).execute(pool).await;
+    if updated.is_err() {
         return;
     }
+    println!("Reclassified {} as a movie", path_str);
+    if let Err(AppError::External(e)) = apply_video_metadata(pool, id, &path_str, library, parsed, None).await {
+        println!("Metadata fetch failed for {}, queued for retry: {}", path_str, e);
+        let _ = retry_queue::enqueue(pool, id, &e).await;
+    }
+}
+
+/// Store a trailer, featurette or other extra. Extras get no metadata lookup of their own;
+/// movie extras are attached to their movie by `extras::link_extras` after the scan.
+async fn process_extra(pool: &SqlitePool, path: &Path, library: &Library, parsed: &ParsedPath, extra: &DetectedExtra) {
+    let path_str = path.to_string_lossy().to_string();
+    let title = extras::extra_title(path, extra);
+    let series_name = if library.library_type == LibraryType::TvShows { parsed.series_name.as_deref() } else { None };
+
+    let existing: Option<(i64, Option<String>)> = sqlx::query_as("SELECT id, extra_type FROM media WHERE file_path = ? ORDER BY id LIMIT 1")
+        .bind(&path_str).fetch_optional(pool).await.unwrap_or(None);
+
+    match existing {
+        Some((id, Some(_))) => {
+            // Earlier scans titled suffix extras by their type alone
+            let _ = sqlx::query("UPDATE media SET title = ? WHERE id = ? AND title = ?")
+                .bind(&title).bind(id).bind(extra.extra_type.label()).execute(pool).await;
+        }
+        Some((id, None)) => {
+            // Scanned as a movie or episode before extras were recognized
+            let _ = media_versions::promote_next_version(pool, id).await;
+            // The other episodes of a multi-episode file
+            let siblings: Vec<i64> = sqlx::query_scalar("SELECT id FROM media WHERE file_path = ? AND id != ?")
+                .bind(&path_str).bind(id).fetch_all(pool).await.unwrap_or_default();
+            for sibling in siblings {
+                if let Err(e) = delete_media(pool, sibling).await {
+                    println!("Failed to remove episode {} of {} from DB: {}", sibling, path_str, e);
+                }
+            }
+            let _ = retry_queue::remove(pool, id).await;
+            let _ = sqlx::query(
+                "UPDATE media SET title = ?, media_type = 'extra', extra_type = ?, extra_of = NULL, version_of = NULL, series_name = ?, season_number = NULL, episode_number = NULL, library_id = ? WHERE id = ?"
+            )
+            .bind(&title).bind(extra.extra_type.as_str()).bind(series_name).bind(library.id).bind(id).execute(pool).await;
+            println!("Reclassified {} as {}", path_str, extra.extra_type.label());
+        }
+        None => {
+            let inserted = sqlx::query("INSERT INTO media (file_path, title, library_id, media_type, series_name, extra_type) VALUES (?, ?, ?, 'extra', ?, ?)")
+                .bind(&path_str).bind(&title).bind(library.id).bind(series_name).bind(extra.extra_type.as_str()).execute(pool).await;
+            if inserted.is_ok() {
+                println!("Added {}: {}", extra.extra_type.label(), path_str);
+            }
+        }
+    }
+}
+
+/// Fetch metadata for a video file and write it to its `media` row.
+/// Local NFOs and sidecar artwork take priority over the library's remote providers.
+async fn apply_video_metadata(
+    pool: &SqlitePool,
+    media_id: i64,
+    path_str: &str,
+    library: &Library,
+    parsed: &ParsedPath,
+    episode_number: Option<i32>,
+) -> Result<(), AppError> {
+    let video = Path::new(path_str);
+    let file_stem = video.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "Unknown".to_string());
+    let search_term = parsed.series_name.as_deref().or(parsed.title.as_deref()).unwrap_or(&file_stem);
+    let is_tv = library.library_type == LibraryType::TvShows;
+    let media_type_hint = if is_tv { Some("series") } else { Some("movie") };
 
-    let search_term = series_name.as_ref().unwrap_or(&file_stem);
-    let media_type_hint = if library.library_type == LibraryType::TvShows { Some("series") } else { Some("movie") };
+    let series_dir = if is_tv { nfo::series_dir(video, &library.path) } else { None };
+    let local = match &series_dir {
+        Some(dir) => nfo::series_nfo(dir),
+        None => nfo::movie_nfo(video, &library.path),
+    };
+    let episode_nfo = if is_tv {
+        let shared_file: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM media WHERE file_path = ?")
+            .bind(path_str).fetch_one(pool).await.unwrap_or(1);
+        nfo::select_episode(nfo::episode_nfos(video), episode_number, shared_file > 1)
+    } else {
+        None
+    };
+
+    let mut meta = fetch_metadata(search_term, media_type_hint, Some(library.id), local, pool).await?;
+    nfo::apply_local_artwork(&mut meta, media_id, video, &library.path, series_dir.as_deref());
+
+    // Absolute numbers and air dates only become a season/episode once the series is known
+    let numbering = EpisodeNumbering::parse(library.episode_numbering.as_deref());
+    let mut resolved = None;
+    if let (true, Some(key), Some(provider_ids)) = (is_tv, parse_episode_key(&file_stem, numbering), meta.provider_ids.as_ref()) {
+        resolved = episode_numbering::resolve(&key, provider_ids, Some(library.id), pool).await?;
+    }
+    let season_number = episode_nfo.as_ref().and_then(|e| e.season_number).or(resolved.map(|r| r.0)).or(parsed.season_number);
+    let episode_number = episode_nfo.as_ref().and_then(|e| e.episode_number).or(resolved.map(|r| r.1)).or(episode_number);
 
-    if let Ok(meta) = fetch_metadata(search_term, media_type_hint, pool).await {
-        let mut final_plot = meta.plot.clone();
-        let mut final_title = None;
-        let mut final_still = None;
+    let mut final_plot = meta.plot.clone();
+    let mut final_title = None;
+    let mut final_still = None;
+    // A transient failure here still stores the series metadata, then queues the episode for retry
+    let mut episode_error = None;
 
-        // Get the provider name dynamically to look up ID
-        let provider_name = get_default_provider(pool).await;
-        if let Some(provider_id) = meta.provider_ids.as_ref().and_then(|ids| ids.get(&provider_name)).and_then(|v| v.as_i64()) {
-            if let (Some(sn), Some(en)) = (season_number, episode_number) {
-                let id_str = provider_id.to_string();
-                if let Ok(episodes) = fetch_episodes(&id_str, sn, pool).await {
+    if let Some(provider_ids) = meta.provider_ids.as_ref() {
+        if let (Some(sn), Some(en)) = (season_number, episode_number) {
+            match fetch_episodes(provider_ids, sn, Some(library.id), pool).await {
+                Ok(episodes) => {
                     if let Some(ep) = episodes.iter().find(|e| e.episode_number == en) {
                         final_title = Some(ep.name.clone());
                         if !ep.overview.is_empty() { final_plot = Some(ep.overview.clone()); }
                         final_still = ep.still_path.clone();
                     }
                 }
+                Err(AppError::External(e)) => episode_error = Some(e),
+                Err(_) => {}
             }
         }
+    }
 
-        let genres_str = meta.genres.as_ref().map(|g| g.join(", "));
-        let year_int = meta.year.as_ref().and_then(|y| y.parse::<i64>().ok()).unwrap_or(0);
+    if let Some(ep) = episode_nfo {
+        final_title = ep.title.or(final_title);
+        final_plot = ep.plot.or(final_plot);
+        final_still = ep.still_url.or(final_still);
+    }
+    if is_tv && nfo::find_still(video).is_some() {
+        final_still = Some(nfo::artwork_url(media_id, ArtworkKind::Still));
+    }
 
-        if library.library_type == LibraryType::TvShows {
-            let _ = sqlx::query("UPDATE media SET year = ?, poster_url = ?, plot = ?, media_type = ?, backdrop_url = ?, series_name = ?, provider_ids = ?, title = COALESCE(?, title), still_url = ?, runtime = ?, genres = ? WHERE file_path = ?")
-                .bind(year_int).bind(&meta.poster_url).bind(final_plot).bind(&meta.media_type).bind(&meta.backdrop_url).bind(&meta.title)
-                .bind(meta.provider_ids.as_ref().map(|v| v.to_string())).bind(final_title).bind(final_still).bind(meta.runtime).bind(genres_str).bind(&path_str)
-                .execute(pool).await;
-        } else {
-            let _ = sqlx::query("UPDATE media SET title = ?, year = ?, poster_url = ?, plot = ?, media_type = ?, backdrop_url = ?, provider_ids = ?, runtime = ?, genres = ? WHERE file_path = ?")
-                .bind(&meta.title).bind(year_int).bind(&meta.poster_url).bind(&meta.plot).bind(&meta.media_type).bind(&meta.backdrop_url)
-                .bind(meta.provider_ids.as_ref().map(|v| v.to_string())).bind(meta.runtime).bind(genres_str).bind(&path_str)
-                .execute(pool).await;
+    let genres_str = meta.genres.as_ref().map(|g| g.join(", "));
+    let year_int = meta.year.as_ref().and_then(|y| y.parse::<i64>().ok()).or(parsed.year.map(i64::from)).unwrap_or(0);
+
+    if library.library_type == LibraryType::TvShows {
+        sqlx::query("UPDATE media SET year = ?, poster_url = ?, plot = ?, media_type = ?, backdrop_url = ?, series_name = ?, original_title = ?, provider_ids = ?, title = COALESCE(?, title), still_url = ?, runtime = ?, genres = ?, season_number = ?, episode_number = ? WHERE id = ?")
+            .bind(year_int).bind(&meta.poster_url).bind(final_plot).bind(&meta.media_type).bind(&meta.backdrop_url).bind(&meta.title).bind(&meta.original_title)
+            .bind(meta.provider_ids.as_ref().map(|v| v.to_string())).bind(final_title).bind(final_still).bind(meta.runtime).bind(genres_str)
+            .bind(season_number).bind(episode_number).bind(media_id)
+            .execute(pool).await?;
+    } else {
+        sqlx::query("UPDATE media SET title = ?, original_title = ?, year = ?, poster_url = ?, plot = ?, media_type = ?, backdrop_url = ?, provider_ids = ?, runtime = ?, genres = ? WHERE id = ?")
+            .bind(&meta.title).bind(&meta.original_title).bind(year_int).bind(&meta.poster_url).bind(&meta.plot).bind(&meta.media_type).bind(&meta.backdrop_url)
+            .bind(meta.provider_ids.as_ref().map(|v| v.to_string())).bind(meta.runtime).bind(genres_str).bind(media_id)
+            .execute(pool).await?;
+    }
+    match episode_error {
+        Some(e) => Err(AppError::External(format!("Episode metadata: {}", e))),
+        None => Ok(()),
+    }
+}
+
+/// Retry metadata for every queued item. Items are dropped from the queue once they
+/// succeed, fail with a non-transient error, or exceed `retry_queue::MAX_ATTEMPTS`.
+async fn process_retry_queue(pool: &SqlitePool) {
+    let pending = retry_queue::pending(pool).await.unwrap_or_default();
+    if pending.is_empty() { return; }
+    println!("Retrying metadata for {} queued item(s)...", pending.len());
+
+    for (media_id, attempts) in pending {
+        let row = sqlx::query_as::<_, Media>("SELECT m.*, l.library_type FROM media m JOIN libraries l ON m.library_id = l.id WHERE m.id = ?")
+            .bind(media_id).fetch_optional(pool).await.unwrap_or(None);
+        let library = sqlx::query_as::<_, Library>("SELECT l.* FROM libraries l JOIN media m ON m.library_id = l.id WHERE m.id = ?")
+            .bind(media_id).fetch_optional(pool).await.unwrap_or(None);
+
+        let (Some(media), Some(library)) = (row, library) else {
+            let _ = retry_queue::remove(pool, media_id).await;
+            continue;
+        };
+
+        // The row may have been renamed or renumbered since the path was first parsed
+        let mut parsed = parse_path(Path::new(&media.file_path), &library, &ParsingRules::for_library(&library));
+        parsed.series_name = media.series_name.clone().or(parsed.series_name);
+        parsed.season_number = media.season_number.or(parsed.season_number);
+
+        match apply_video_metadata(pool, media.id, &media.file_path, &library, &parsed, media.episode_number).await {
+            Ok(()) => {
+                println!("Retry succeeded for: {}", media.file_path);
+                let _ = retry_queue::remove(pool, media_id).await;
+            }
+            Err(AppError::External(e)) if attempts + 1 < retry_queue::MAX_ATTEMPTS => {
+                let _ = retry_queue::enqueue(pool, media_id, &e).await;
+            }
+            Err(e) => {
+                println!("Giving up on metadata for {}: {}", media.file_path, e);
+                let _ = retry_queue::remove(pool, media_id).await;
+            }
         }
-        println!("Updated metadata for: {}", file_stem);
     }
 }
 
@@ -267,12 +779,12 @@ fn extract_cbz_cover(cbz_path: &Path, media_id: i64) -> bool {
     std::fs::write(&thumb_path, &buffer).is_ok()
 }
 
-async fn process_book(pool: &SqlitePool, path: &Path, library: &Library) {
+async fn process_book(pool: &SqlitePool, path: &Path, library: &Library, rules: &ParsingRules) {
     let path_str = path.to_string_lossy().to_string();
     let file_stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "Unknown".to_string());
     
-    // Parse comic series info from folder structure
-    let (series_name, chapter_number) = parse_comic_info(path, &library.path);
+    // Parse comic series info from custom rules and folder structure
+    let ParsedPath { series_name, chapter_number, volume_number, title, .. } = parse_path(path, library, rules);
     
     // Check if already exists
     let existing: Option<(i64,)> = sqlx::query_as("SELECT id FROM media WHERE file_path = ?")
@@ -280,25 +792,183 @@ async fn process_book(pool: &SqlitePool, path: &Path, library: &Library) {
 
     if let Some((_id,)) = existing {
         // Update library_id and series info
-        let _ = sqlx::query("UPDATE media SET library_id = ?, series_name = COALESCE(series_name, ?), episode_number = COALESCE(episode_number, ?) WHERE file_path = ?")
-            .bind(library.id).bind(&series_name).bind(chapter_number).bind(&path_str).execute(pool).await;
+        let _ = sqlx::query("UPDATE media SET library_id = ?, series_name = COALESCE(series_name, ?), episode_number = COALESCE(episode_number, ?), season_number = COALESCE(season_number, ?) WHERE file_path = ?")
+            .bind(library.id).bind(&series_name).bind(chapter_number).bind(volume_number).bind(&path_str).execute(pool).await;
         return;
     }
 
-    // Insert new book with series info (reusing episode_number for chapter_number)
-    let result = sqlx::query("INSERT INTO media (file_path, title, library_id, media_type, series_name, episode_number) VALUES (?, ?, ?, 'book', ?, ?)")
-        .bind(&path_str).bind(&file_stem).bind(library.id).bind(&series_name).bind(chapter_number).execute(pool).await;
+    // Insert new book with series info (reusing episode_number for chapter_number, season_number for volume)
+    let result = sqlx::query("INSERT INTO media (file_path, title, library_id, media_type, series_name, episode_number, season_number) VALUES (?, ?, ?, 'book', ?, ?, ?)")
+        .bind(&path_str).bind(title.as_deref().unwrap_or(&file_stem)).bind(library.id).bind(&series_name).bind(chapter_number).bind(volume_number).execute(pool).await;
     
     // Extract cover from CBZ
     if let Ok(res) = result {
         let media_id = res.last_insert_rowid();
         let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
-        if ext == "cbz" || ext == "zip" {
-            if extract_cbz_cover(path, media_id) {
-                println!("Extracted cover for book: {}", file_stem);
-            }
+        if (ext == "cbz" || ext == "zip") && extract_cbz_cover(path, media_id) {
+            println!("Extracted cover for book: {}", file_stem);
         }
     }
         
     println!("Added book: {} (series: {:?}, chapter: {:?})", file_stem, series_name, chapter_number);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// An empty scratch directory for one test
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vortex-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn touch(path: PathBuf) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, b"").unwrap();
+    }
+
+    #[test]
+    fn parse_episode_numbers_reads_seasons_ranges_and_lists() {
+        let cases: &[(&str, Option<i32>, &[i32])] = &[
+            ("Show.S01E02.1080p", Some(1), &[2]),
+            ("Show.S00E03", Some(0), &[3]),
+            ("Show 2x05", Some(2), &[5]),
+            ("Show.S01E01-E03", Some(1), &[1, 2, 3]),
+            ("Show.S01E01-02", Some(1), &[1, 2]),
+            ("Show.S01E01E02E03", Some(1), &[1, 2, 3]),
+            ("Show.S01E01.S01E02", Some(1), &[1, 2]),
+            ("Show.2x01.2x02", Some(2), &[1, 2]),
+            // Backwards or implausibly long ranges stop at the first episode
+            ("Show.S01E05-E03", Some(1), &[5]),
+            ("Show.S01E01-E40", Some(1), &[1]),
+            ("Show.S01E01-E20", Some(1), &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20]),
+            ("Show - 05 - Title", None, &[5]),
+            ("Show Episode 7", None, &[7]),
+            ("Show", None, &[]),
+        ];
+        for (filename, season, episodes) in cases {
+            assert_eq!(parse_episode_numbers(filename), (*season, episodes.to_vec()), "{}", filename);
+        }
+    }
+
+    #[test]
+    fn parse_tv_show_info_takes_the_season_from_the_file_then_the_folder() {
+        let cases: &[(&str, &str, i32, &[i32])] = &[
+            ("/tv/Show/Season 2/Show.S02E04.mkv", "Show", 2, &[4]),
+            // The filename's season wins over the folder's
+            ("/tv/Show/Season 2/Show.S03E04.mkv", "Show", 3, &[4]),
+            ("/tv/Show/Season 3/Show - 04 - Title.mkv", "Show", 3, &[4]),
+            ("/tv/Show/Specials/Show - 03 - Title.mkv", "Show", 0, &[3]),
+            ("/tv/Show/Special/Show - 03 - Title.mkv", "Show", 0, &[3]),
+            ("/tv/Show/Season 0/Show - 03 - Title.mkv", "Show", 0, &[3]),
+            ("/tv/Show/Season 1/Show.S00E02.mkv", "Show", 0, &[2]),
+            ("/tv/Show/Show.S01E01E02.mkv", "Show", 1, &[1, 2]),
+            ("/tv/Show/Season 4/Untitled.mkv", "Show", 4, &[1]),
+            // Loose in the library: the library's name is the series
+            ("/tv/Show.S02E01.mkv", "TV", 2, &[1]),
+        ];
+        for (path, series, season, episodes) in cases {
+            let parsed = parse_tv_show_info(Path::new(path), "/tv", "TV", EpisodeNumbering::Standard);
+            assert_eq!(parsed, Some((series.to_string(), *season, episodes.to_vec())), "{}", path);
+        }
+        assert_eq!(parse_tv_show_info(Path::new("/elsewhere/Show.S01E01.mkv"), "/tv", "TV", EpisodeNumbering::Standard), None);
+    }
+
+    #[test]
+    fn parse_episode_key_reads_absolute_numbers_and_air_dates() {
+        use EpisodeNumbering::{Absolute, Date, Standard};
+        let date = |y, m, d| Some(EpisodeKey::AirDate(NaiveDate::from_ymd_opt(y, m, d).unwrap()));
+        let cases: &[(&str, EpisodeNumbering, Option<EpisodeKey>)] = &[
+            ("[Group] Show - 137 [1080p]", Absolute, Some(EpisodeKey::Absolute(137))),
+            ("[Group] Show - 05v2 [ABCD1234]", Absolute, Some(EpisodeKey::Absolute(5))),
+            ("Show Episode 12", Absolute, Some(EpisodeKey::Absolute(12))),
+            ("Show.E1024", Absolute, Some(EpisodeKey::Absolute(1024))),
+            ("Show 42", Absolute, Some(EpisodeKey::Absolute(42))),
+            // A trailing year isn't an episode, and a season leaves it to the standard parser
+            ("Show 2019", Absolute, None),
+            ("Show.S02E05", Absolute, None),
+            ("Show.2024.03.15.Guest", Date, date(2024, 3, 15)),
+            ("Show - 2024-03-15", Date, date(2024, 3, 15)),
+            ("Show.2024.02.30", Date, None),
+            ("Show - 137", Standard, None),
+        ];
+        for (filename, numbering, key) in cases {
+            assert_eq!(&parse_episode_key(filename, *numbering), key, "{}", filename);
+        }
+        assert_eq!(date(2024, 3, 15).unwrap().provisional(), (Some(2024), 315));
+        assert_eq!(EpisodeKey::Absolute(137).provisional(), (None, 137));
+    }
+
+    #[tokio::test]
+    async fn scan_matches_movies_and_episodes_against_the_offline_catalog() {
+        let dir = scratch_dir("scan-offline");
+        touch(dir.join("movies/The Matrix (1999)/The.Matrix.1999.1080p.BluRay.mkv"));
+        touch(dir.join("tv/Breaking Bad/Season 1/Breaking.Bad.S01E02.mkv"));
+
+        let pool = crate::db::connect(&format!("sqlite:{}", dir.join("test.db").display())).await;
+        let catalog = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/offline_catalog");
+        sqlx::query("INSERT INTO settings (key, value) VALUES ('metadata_provider', 'offline'), ('offline_catalog_path', ?)")
+            .bind(catalog.to_string_lossy()).execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO libraries (name, path, library_type) VALUES ('Movies', ?, 'movies'), ('Shows', ?, 'tv_shows')")
+            .bind(dir.join("movies").to_string_lossy()).bind(dir.join("tv").to_string_lossy())
+            .execute(&pool).await.unwrap();
+
+        scan_media(&pool).await;
+
+        let media = |pattern: &'static str| {
+            sqlx::query_as::<_, Media>("SELECT m.*, l.library_type FROM media m JOIN libraries l ON m.library_id = l.id WHERE m.file_path LIKE ?")
+                .bind(pattern).fetch_one(&pool)
+        };
+
+        let movie = media("%The.Matrix%").await.unwrap();
+        assert_eq!(movie.title.as_deref(), Some("The Matrix"));
+        assert_eq!((movie.year, movie.runtime), (Some(1999), Some(136)));
+        assert!(movie.provider_ids.unwrap().contains("\"offline\":\"the-matrix\""));
+
+        let episode = media("%S01E02%").await.unwrap();
+        assert_eq!(episode.series_name.as_deref(), Some("Breaking Bad"));
+        assert_eq!(episode.title.as_deref(), Some("Cat's in the Bag..."));
+        assert_eq!((episode.season_number, episode.episode_number), (Some(1), Some(2)));
+        assert_eq!(episode.plot.as_deref(), Some("Walt and Jesse clean up."));
+
+        let queued: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM metadata_retry_queue").fetch_one(&pool).await.unwrap();
+        assert_eq!(queued, 0);
+
+        pool.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn process_extra_reclassifies_a_multi_episode_file_and_drops_its_other_episodes() {
+        let dir = scratch_dir("scan-extra-siblings");
+        let file = dir.join("tv/Breaking Bad/Featurettes/Breaking.Bad.S01E01-E02.mkv");
+        touch(file.clone());
+
+        let pool = crate::db::connect(&format!("sqlite:{}", dir.join("test.db").display())).await;
+        sqlx::query("INSERT INTO libraries (name, path, library_type) VALUES ('Shows', ?, 'tv_shows')")
+            .bind(dir.join("tv").to_string_lossy()).execute(&pool).await.unwrap();
+        // Both episodes as an earlier scan stored them, each queued for a metadata retry
+        for episode in [1, 2] {
+            let id = sqlx::query("INSERT INTO media (file_path, title, library_id, media_type, series_name, season_number, episode_number) VALUES (?, 'Episode', 1, 'episode', 'Breaking Bad', 1, ?)")
+                .bind(file.to_string_lossy()).bind(episode).execute(&pool).await.unwrap().last_insert_rowid();
+            sqlx::query("INSERT INTO metadata_retry_queue (media_id) VALUES (?)").bind(id).execute(&pool).await.unwrap();
+        }
+
+        // Called directly: a scan would first work through (and empty) the retry queue
+        let library = sqlx::query_as::<_, Library>("SELECT * FROM libraries").fetch_one(&pool).await.unwrap();
+        let extra = extras::detect(&file, &library.path).unwrap();
+        process_extra(&pool, &file, &library, &ParsedPath::default(), &extra).await;
+
+        let rows: Vec<(i64, Option<String>)> = sqlx::query_as("SELECT id, extra_type FROM media").fetch_all(&pool).await.unwrap();
+        assert_eq!(rows, vec![(1, Some("featurette".to_string()))]);
+        let queued: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM metadata_retry_queue").fetch_one(&pool).await.unwrap();
+        assert_eq!(queued, 0);
+
+        pool.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,275 @@
+//! Subtitle Format - decodes subtitle files in any charset and converts them to WebVTT, the
+//! format browsers and ExoPlayer both load.
+//!
+//! SubRip (`.srt`), ASS/SSA and MicroDVD (`.sub` text) are converted; WebVTT is passed through.
+//! Every format can be shifted by an offset in seconds for files that are out of sync.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+// "00:01:02,500", "1:02.500" or "00:01:02.50" - SRT, WebVTT and ASS timestamps
+static RE_TIMESTAMP: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:(\d+):)?(\d{1,2}):(\d{1,2})[,.](\d{1,3})").unwrap());
+// ASS override blocks: {\i1}, {\pos(10,20)}, ...
+static RE_ASS_OVERRIDE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{[^}]*\}").unwrap());
+// <font color="..."> and other tags WebVTT doesn't know; <i>, <b> and <u> are kept
+static RE_UNSUPPORTED_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)</?(?:font|span|div|p)\b[^>]*>").unwrap());
+// MicroDVD: {start frame}{end frame}text
+static RE_MICRODVD: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\{(\d+)\}\{(\d*)\}(.*)$").unwrap());
+
+/// MicroDVD frame rate when the file doesn't state one
+const MICRODVD_DEFAULT_FPS: f64 = 23.976;
+
+/// Formats a subtitle file can be converted from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubtitleFormat {
+    Srt,
+    WebVtt,
+    Ass,
+    MicroDvd,
+}
+
+impl SubtitleFormat {
+    /// Format by file extension. `.sub` is MicroDVD here; VobSub `.sub` files (with an `.idx`)
+    /// are pictures and have to be ruled out by the caller.
+    pub fn from_extension(ext: &str) -> Option<SubtitleFormat> {
+        match ext.to_lowercase().as_str() {
+            "srt" => Some(SubtitleFormat::Srt),
+            "vtt" => Some(SubtitleFormat::WebVtt),
+            "ass" | "ssa" => Some(SubtitleFormat::Ass),
+            "sub" => Some(SubtitleFormat::MicroDvd),
+            _ => None,
+        }
+    }
+}
+
+struct Cue {
+    start: f64,
+    end: f64,
+    text: String,
+}
+
+/// Text of a subtitle file: UTF-8/UTF-16 by BOM, valid UTF-8 as is, anything else by guess
+/// (Windows-1252, ISO-8859-x, ...).
+pub fn decode(bytes: &[u8]) -> String {
+    if let Some((encoding, bom_length)) = encoding_rs::Encoding::for_bom(bytes) {
+        return encoding.decode_without_bom_handling(&bytes[bom_length..]).0.into_owned();
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return text.to_string();
+    }
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    let encoding = detector.guess(None, true);
+    tracing::debug!("Decoding subtitle as {}", encoding.name());
+    encoding.decode_without_bom_handling(bytes).0.into_owned()
+}
+
+/// Convert decoded subtitle text to WebVTT, shifted by `offset` seconds (negative is earlier).
+pub fn to_webvtt(text: &str, format: SubtitleFormat, offset: f64) -> String {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let cues = match format {
+        SubtitleFormat::WebVtt => return shift_webvtt(&text, offset),
+        SubtitleFormat::Srt => parse_srt(&text),
+        SubtitleFormat::Ass => parse_ass(&text),
+        SubtitleFormat::MicroDvd => parse_microdvd(&text),
+    };
+    render(cues, offset)
+}
+
+fn parse_timestamp(value: &str) -> Option<f64> {
+    let caps = RE_TIMESTAMP.captures(value.trim())?;
+    let hours: f64 = caps.get(1).map_or(Ok(0.0), |h| h.as_str().parse()).ok()?;
+    let minutes: f64 = caps[2].parse().ok()?;
+    let seconds: f64 = caps[3].parse().ok()?;
+    // ".5" is half a second whether written as 5, 50 or 500
+    let fraction: f64 = format!("0.{}", &caps[4]).parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds + fraction)
+}
+
+fn format_timestamp(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!("{:02}:{:02}:{:02}.{:03}", millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000)
+}
+
+fn clean_text(text: &str) -> String {
+    RE_UNSUPPORTED_TAG.replace_all(&RE_ASS_OVERRIDE.replace_all(text, ""), "").trim().to_string()
+}
+
+fn render(mut cues: Vec<Cue>, offset: f64) -> String {
+    cues.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap_or(std::cmp::Ordering::Equal));
+    let mut vtt = String::from("WEBVTT\n");
+    for cue in cues {
+        let (start, end) = (cue.start + offset, cue.end + offset);
+        // Shifted out of the timeline, or empty once tags are gone
+        if end <= 0.0 || cue.text.is_empty() {
+            continue;
+        }
+        vtt.push_str(&format!("\n{} --> {}\n{}\n", format_timestamp(start), format_timestamp(end), cue.text));
+    }
+    vtt
+}
+
+/// SubRip: numbered blocks of a "start --> end" line and the text.
+fn parse_srt(text: &str) -> Vec<Cue> {
+    text.split("\n\n")
+        .filter_map(|block| {
+            let mut lines = block.lines().skip_while(|l| !l.contains("-->"));
+            let (start, end) = lines.next()?.split_once("-->")?;
+            let text = clean_text(&lines.collect::<Vec<_>>().join("\n"));
+            Some(Cue { start: parse_timestamp(start)?, end: parse_timestamp(end)?, text })
+        })
+        .collect()
+}
+
+/// ASS/SSA: "Dialogue:" lines of the [Events] section, laid out by its "Format:" line.
+fn parse_ass(text: &str) -> Vec<Cue> {
+    let mut fields: Vec<String> = Vec::new();
+    let mut in_events = false;
+    let mut cues = Vec::new();
+
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_events = line.eq_ignore_ascii_case("[events]");
+            continue;
+        }
+        if !in_events {
+            continue;
+        }
+        if let Some(format) = line.strip_prefix("Format:") {
+            fields = format.split(',').map(|f| f.trim().to_lowercase()).collect();
+        } else if let Some(dialogue) = line.strip_prefix("Dialogue:") {
+            if fields.is_empty() {
+                continue;
+            }
+            // Text is the last field and may itself contain commas
+            let values: Vec<&str> = dialogue.splitn(fields.len(), ',').collect();
+            let field = |name: &str| fields.iter().position(|f| f == name).and_then(|i| values.get(i)).copied();
+            let (Some(start), Some(end), Some(text)) = (field("start"), field("end"), field("text")) else { continue };
+            let text = text.replace("\\N", "\n").replace("\\n", "\n").replace("\\h", " ");
+            if let (Some(start), Some(end)) = (parse_timestamp(start), parse_timestamp(end)) {
+                cues.push(Cue { start, end, text: clean_text(&text) });
+            }
+        }
+    }
+    cues
+}
+
+/// MicroDVD: "{start}{end}text" in frames, "|" between lines. A first cue of "{1}{1}25.000"
+/// states the frame rate.
+fn parse_microdvd(text: &str) -> Vec<Cue> {
+    let mut fps = MICRODVD_DEFAULT_FPS;
+    let mut cues = Vec::new();
+    for (i, line) in text.lines().map(str::trim).enumerate() {
+        let Some(caps) = RE_MICRODVD.captures(line) else { continue };
+        let start: f64 = caps[1].parse().unwrap_or(0.0);
+        let body = caps[3].trim();
+        if i == 0 && start <= 1.0 {
+            if let Some(rate) = body.parse::<f64>().ok().filter(|r| *r > 0.0) {
+                fps = rate;
+                continue;
+            }
+        }
+        // An open end lasts a few seconds
+        let end = caps[2].parse::<f64>().unwrap_or(start + 3.0 * fps);
+        cues.push(Cue { start: start / fps, end: end / fps, text: clean_text(&body.replace('|', "\n")) });
+    }
+    cues
+}
+
+/// WebVTT stays as it is (styles, notes, cue settings) apart from its timings.
+fn shift_webvtt(text: &str, offset: f64) -> String {
+    if offset == 0.0 {
+        return text.to_string();
+    }
+    let blocks: Vec<String> = text.split("\n\n")
+        .filter_map(|block| {
+            let lines: Vec<&str> = block.lines().collect();
+            let Some(timing) = lines.iter().position(|l| l.contains("-->")) else { return Some(block.to_string()) };
+            let (start, rest) = lines[timing].split_once("-->")?;
+            let rest = rest.trim_start();
+            let (end, settings) = rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len()));
+            let (start, end) = (parse_timestamp(start)?, parse_timestamp(end)?);
+            // A cue that ends before the video starts is dropped with its identifier and text
+            if end + offset <= 0.0 {
+                return None;
+            }
+            let mut lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+            lines[timing] = format!("{} --> {}{}", format_timestamp(start + offset), format_timestamp(end + offset), settings);
+            Some(lines.join("\n"))
+        })
+        .collect();
+    // Splitting into lines drops the final line break
+    let mut vtt = blocks.join("\n\n").trim_end_matches('\n').to_string();
+    if text.ends_with('\n') {
+        vtt.push('\n');
+    }
+    vtt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_reads_boms_utf8_and_legacy_charsets() {
+        assert_eq!(decode("Café".as_bytes()), "Café");
+        assert_eq!(decode(b"\xEF\xBB\xBFCaf\xC3\xA9"), "Café");
+        assert_eq!(decode(b"\xFF\xFEC\0a\0f\0\xE9\0"), "Café");
+
+        // Windows-1252 / Latin-1, as most older European subtitles are
+        let latin1 = b"1\n00:00:01,000 --> 00:00:02,000\nLe gar\xE7on a command\xE9 un caf\xE9 cr\xE8me et une cr\xEApe \xE0 la fran\xE7aise, \x93tr\xE8s bien\x94 dit-il.\n";
+        let text = decode(latin1);
+        assert!(text.contains("Le garçon a commandé un café crème et une crêpe à la française, “très bien” dit-il."), "{}", text);
+    }
+
+    #[test]
+    fn srt_converts_to_webvtt() {
+        let srt = "1\r\n00:00:01,500 --> 00:00:03,000\r\n<font color=\"red\">Hello</font> <i>there</i>\r\n\r\n2\r\n00:00:04,000 --> 00:00:05,250\r\nTwo\r\nlines\r\n";
+        assert_eq!(
+            to_webvtt(srt, SubtitleFormat::Srt, 0.0),
+            "WEBVTT\n\n00:00:01.500 --> 00:00:03.000\nHello <i>there</i>\n\n00:00:04.000 --> 00:00:05.250\nTwo\nlines\n"
+        );
+    }
+
+    #[test]
+    fn ass_converts_to_webvtt() {
+        let ass = "[Script Info]\nTitle: Sample\n\n[V4+ Styles]\nFormat: Name, Fontname\nStyle: Default,Arial\n\n[Events]\n\
+                   Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                   Dialogue: 0,0:00:02.00,0:00:03.50,Default,,0,0,0,,{\\i1}Second{\\i0}, with a comma\n\
+                   Dialogue: 0,0:00:00.50,0:00:01.00,Default,,0,0,0,,First\\Nline\n";
+        assert_eq!(
+            to_webvtt(ass, SubtitleFormat::Ass, 0.0),
+            "WEBVTT\n\n00:00:00.500 --> 00:00:01.000\nFirst\nline\n\n00:00:02.000 --> 00:00:03.500\nSecond, with a comma\n"
+        );
+    }
+
+    #[test]
+    fn microdvd_converts_to_webvtt_at_its_frame_rate() {
+        let sub = "{1}{1}25.000\n{25}{50}One|two\n{100}{}Open end\n";
+        assert_eq!(
+            to_webvtt(sub, SubtitleFormat::MicroDvd, 0.0),
+            "WEBVTT\n\n00:00:01.000 --> 00:00:02.000\nOne\ntwo\n\n00:00:04.000 --> 00:00:07.000\nOpen end\n"
+        );
+    }
+
+    #[test]
+    fn offsets_shift_cues_and_drop_those_before_the_start() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\nEarly\n\n2\n00:00:03,000 --> 00:00:05,000\nStraddles\n\n3\n00:00:10,000 --> 00:00:11,000\nLate\n";
+        assert_eq!(
+            to_webvtt(srt, SubtitleFormat::Srt, 1.5),
+            "WEBVTT\n\n00:00:02.500 --> 00:00:03.500\nEarly\n\n00:00:04.500 --> 00:00:06.500\nStraddles\n\n00:00:11.500 --> 00:00:12.500\nLate\n"
+        );
+        // Cues ending before 0 are dropped, ones that straddle it start at 0
+        assert_eq!(
+            to_webvtt(srt, SubtitleFormat::Srt, -4.0),
+            "WEBVTT\n\n00:00:00.000 --> 00:00:01.000\nStraddles\n\n00:00:06.000 --> 00:00:07.000\nLate\n"
+        );
+
+        let vtt = "WEBVTT\n\nintro\n00:00:01.000 --> 00:00:02.000\nEarly\n\n00:00:03.000 --> 00:00:05.000 align:start\nStraddles\n";
+        assert_eq!(to_webvtt(vtt, SubtitleFormat::WebVtt, 0.0), vtt);
+        assert_eq!(
+            to_webvtt(vtt, SubtitleFormat::WebVtt, -2.5),
+            "WEBVTT\n\n00:00:00.500 --> 00:00:02.500 align:start\nStraddles\n"
+        );
+    }
+}
@@ -0,0 +1,426 @@
+//! Subtitles - subtitle tracks muxed into a video file.
+//!
+//! Embedded tracks are the subtitle streams the probe stored in `media_streams`. Text tracks
+//! (SubRip, ASS/SSA, WebVTT, mov_text) are extracted to WebVTT with FFmpeg on first request and
+//! cached under `subtitles/`; image tracks (PGS, VobSub, DVB) can't be converted to text and
+//! can only be burned into the video.
+//!
+//! Sidecar files carry their language and flags in the name: `Movie.eng.forced.srt`,
+//! `Movie.pt-BR.sdh.srt`, `Movie.de.cc.vtt`.
+//!
+//! Uploaded and downloaded subtitles are stored the same way next to the video, or in the
+//! managed cache (`subtitles/<media id>/`) when the media folder is read-only or asked to.
+//! Downloads come from a `SubtitleProvider`: OpenSubtitles, or a local stand-in directory.
+//!
+//! A player that selects an image track gets it burned into an HLS transcode (see `BurnIn`).
+
+use crate::core::{language, media_streams, metadata, remux};
+use crate::core::subtitle_format::SubtitleFormat;
+use crate::error::AppError;
+use crate::models::media::MediaStreamDto;
+use crate::models::metadata::SubtitleSearch;
+use crate::providers::local_subtitles::LocalSubtitleProvider;
+use crate::providers::opensubtitles::OpenSubtitlesProvider;
+use crate::providers::traits::SubtitleProvider;
+use sqlx::{FromRow, SqlitePool};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const CACHE_DIR: &str = "subtitles";
+/// Numbers the partial files of extractions running at the same time
+static EXTRACTIONS: AtomicU64 = AtomicU64::new(0);
+
+const TEXT_CODECS: &[&str] = &["subrip", "srt", "ass", "ssa", "webvtt", "mov_text", "text"];
+
+/// Whether a subtitle codec (as FFprobe names it) is text that converts to WebVTT. Anything
+/// else - hdmv_pgs_subtitle, dvd_subtitle, dvb_subtitle - is a picture.
+pub fn is_text_codec(codec: Option<&str>) -> bool {
+    codec.is_some_and(|c| TEXT_CODECS.contains(&c))
+}
+
+/// Language and flags of a subtitle track.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TrackInfo {
+    /// ISO 639-1, with a region if given ("en", "pt-BR")
+    pub language: Option<String>,
+    pub forced: bool,
+    /// Subtitles for the deaf and hard of hearing (closed captions)
+    pub sdh: bool,
+    pub default: bool,
+    /// The stream's title, or for a sidecar its label with the words of the name that aren't
+    /// a language or flag ("English - Commentary")
+    pub title: Option<String>,
+}
+
+impl TrackInfo {
+    /// What a sidecar's name says after the video's: ".eng.forced" of `Movie.eng.forced.srt`.
+    pub fn from_suffix(suffix: &str) -> TrackInfo {
+        let mut info = TrackInfo::default();
+        let mut words = Vec::new();
+        for token in suffix.split('.').map(str::trim).filter(|t| !t.is_empty()) {
+            match token.to_lowercase().as_str() {
+                "forced" | "foreign" => info.forced = true,
+                "sdh" | "cc" => info.sdh = true,
+                // "hi" after a language is hearing impaired; on its own it's Hindi
+                "hi" if info.language.is_some() => info.sdh = true,
+                "default" => info.default = true,
+                // Numbers tell apart files that are otherwise named the same (see `store`)
+                t if t.chars().all(|c| c.is_ascii_digit()) => {}
+                _ if info.language.is_none() && language_of(token).is_some() => info.language = language_of(token),
+                _ => words.push(token),
+            }
+        }
+        if !words.is_empty() {
+            info.title = Some(format!("{} - {}", info.label(), words.join(" ")));
+        }
+        info
+    }
+
+    /// An embedded stream's: its language tag, dispositions, and "SDH"/"CC" in its title.
+    pub fn from_stream(stream: &MediaStreamDto) -> TrackInfo {
+        let title_says = |word: &str| stream.title.as_deref().is_some_and(|t| {
+            t.split(|c: char| !c.is_alphanumeric()).any(|w| w.eq_ignore_ascii_case(word))
+        });
+        TrackInfo {
+            language: stream.language.as_deref().and_then(language::normalize),
+            forced: stream.is_forced || title_says("forced"),
+            sdh: stream.is_hearing_impaired || title_says("sdh") || title_says("cc"),
+            default: stream.is_default,
+            title: stream.title.clone(),
+        }
+    }
+
+    /// "English - Forced", "Portuguese (BR) - SDH", or the track's own title when it has one.
+    pub fn label(&self) -> String {
+        if let Some(title) = &self.title {
+            return title.clone();
+        }
+        let mut parts = vec![self.language.as_deref().and_then(language::display_name).unwrap_or_else(|| "Unknown".to_string())];
+        if self.forced {
+            parts.push("Forced".to_string());
+        }
+        if self.sdh {
+            parts.push("SDH".to_string());
+        }
+        parts.join(" - ")
+    }
+}
+
+/// A code ("eng", "pt-BR") or English name ("German") as a normalized code.
+fn language_of(token: &str) -> Option<String> {
+    // Short words only: "director" must not read as a code with a region
+    if token.len() > 3 && !token.contains(['-', '_']) {
+        return language::lookup_name(token).map(|l| l.code().to_string());
+    }
+    language::normalize(token)
+}
+
+/// Subtitle languages the user prefers, most wanted first: the `subtitle_language` setting
+/// ("en,fr"), else the metadata language.
+pub async fn preferred_languages(pool: &SqlitePool) -> Vec<String> {
+    let setting = match metadata::get_setting(pool, "subtitle_language").await {
+        Some(value) => value,
+        None => metadata::get_metadata_locale(pool).await.language,
+    };
+    parse_languages(&setting)
+}
+
+/// A comma-separated list of codes, normalized; unknown codes are dropped.
+pub fn parse_languages(value: &str) -> Vec<String> {
+    value.split(',').filter_map(|code| language::normalize(code.trim())).collect()
+}
+
+/// Position of a track's language in the preferred list: an exact match ("pt-BR") before the
+/// same language in another region ("pt"), and unlisted languages last.
+pub fn preference_rank(track_language: Option<&str>, preferred: &[String]) -> usize {
+    let Some(track) = track_language else { return usize::MAX };
+    let base = |code: &str| code.split('-').next().unwrap_or(code).to_string();
+    preferred.iter().position(|p| p == track)
+        .map(|i| i * 2)
+        .or_else(|| preferred.iter().position(|p| base(p) == base(track)).map(|i| i * 2 + 1))
+        .unwrap_or(usize::MAX)
+}
+
+/// A `.sub` next to an `.idx` is VobSub: pictures, not MicroDVD text.
+pub fn is_vobsub(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("sub")) && path.with_extension("idx").exists()
+}
+
+/// An image subtitle track to overlay onto the video while transcoding.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BurnIn {
+    /// Stream index within the video (PGS, DVD or DVB subtitles)
+    Stream(i64),
+    /// The `.idx` of a VobSub sidecar, which FFmpeg opens as its own input
+    VobSub(PathBuf),
+}
+
+impl BurnIn {
+    /// Short name for the transcode directory: "s5", "vobsub-Movie_en"
+    pub fn key(&self) -> String {
+        match self {
+            BurnIn::Stream(index) => format!("s{}", index),
+            BurnIn::VobSub(idx) => format!(
+                "vobsub-{}",
+                crate::core::util::sanitize_filename(&idx.file_stem().unwrap_or_default().to_string_lossy())
+            ),
+        }
+    }
+}
+
+/// What selecting subtitle `track` (a `/subtitles` track ID: "embedded-5" or a sidecar file
+/// name) means for a transcode: `Some` for image tracks, which have to be burned in, `None`
+/// for text tracks, which the player renders itself.
+pub async fn burn_in_track(pool: &SqlitePool, media_id: i64, file_path: &Path, track: &str) -> Result<Option<BurnIn>, AppError> {
+    if let Some(index) = track.strip_prefix("embedded-") {
+        let index: i64 = index.parse().map_err(|_| AppError::BadRequest(format!("Invalid subtitle track: {}", track)))?;
+        let stream = media_streams::list(pool, media_id).await?
+            .into_iter()
+            .find(|s| s.stream_type == "subtitle" && s.stream_index == index)
+            .ok_or_else(|| AppError::NotFound(format!("Media {} has no subtitle stream {}", media_id, index)))?;
+        return Ok((!is_text_codec(stream.codec.as_deref())).then_some(BurnIn::Stream(index)));
+    }
+
+    // Sidecar files are plain file names, next to the video or in the managed cache
+    if Path::new(track).file_name() != Some(std::ffi::OsStr::new(track)) {
+        return Err(AppError::BadRequest(format!("Invalid subtitle track: {}", track)));
+    }
+    let path = file_path.parent().map(|dir| dir.join(track)).into_iter()
+        .chain(std::iter::once(managed_dir(media_id).join(track)))
+        .find(|p| p.exists())
+        .ok_or_else(|| AppError::NotFound(format!("Subtitle {} not found", track)))?;
+    Ok(is_vobsub(&path).then(|| BurnIn::VobSub(path.with_extension("idx"))))
+}
+
+/// Subtitle streams of a media item's file, probing it first if it hasn't been.
+pub async fn embedded_tracks(pool: &SqlitePool, media_id: i64, file_path: &Path) -> Result<Vec<MediaStreamDto>, AppError> {
+    media_streams::media_info_or_probe(pool, media_id, file_path).await?;
+    Ok(media_streams::list(pool, media_id).await?
+        .into_iter()
+        .filter(|s| s.stream_type == "subtitle")
+        .collect())
+}
+
+fn is_fresh(cache: &Path, source: &Path) -> bool {
+    let modified = |p: &Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    match (modified(cache), modified(source)) {
+        (Some(cached), Some(source)) => cached >= source,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+/// WebVTT of text subtitle stream `stream_index` of a file, extracted once and then served
+/// from the cache until the video changes.
+pub async fn extract_webvtt(media_id: i64, file_path: &Path, stream_index: i64) -> Result<PathBuf, AppError> {
+    let cache = Path::new(CACHE_DIR).join(format!("{}-{}.vtt", media_id, stream_index));
+    if is_fresh(&cache, file_path) {
+        return Ok(cache);
+    }
+    tokio::fs::create_dir_all(CACHE_DIR).await
+        .map_err(|e| AppError::Internal(format!("Could not create {}: {}", CACHE_DIR, e)))?;
+
+    // Written next to the cache and renamed, so a concurrent request never reads half a file
+    let partial = cache.with_extension(format!("{}.part", EXTRACTIONS.fetch_add(1, Ordering::Relaxed)));
+    let output = tokio::process::Command::new(remux::ffmpeg_command())
+        .args(["-hide_banner", "-loglevel", "error", "-y", "-i"])
+        .arg(file_path)
+        .arg("-map").arg(format!("0:{}", stream_index))
+        .args(["-c:s", "webvtt", "-f", "webvtt"])
+        .arg(&partial)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to start FFmpeg: {}", e)))?;
+    if !output.status.success() {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(AppError::Internal(format!(
+            "Could not extract subtitle stream {} of {}: {}",
+            stream_index, file_path.display(), String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    tokio::fs::rename(&partial, &cache).await
+        .map_err(|e| AppError::Internal(format!("Could not cache subtitle: {}", e)))?;
+    Ok(cache)
+}
+
+const DEFAULT_PROVIDER: &str = "opensubtitles";
+
+/// A subtitle provider by name, or the `subtitle_provider` setting's.
+pub async fn get_provider(pool: &SqlitePool, name: Option<&str>) -> Result<Box<dyn SubtitleProvider>, AppError> {
+    let name = match name {
+        Some(name) => name.to_lowercase(),
+        None => metadata::get_setting(pool, "subtitle_provider").await.unwrap_or_else(|| DEFAULT_PROVIDER.to_string()),
+    };
+    match name.as_str() {
+        "opensubtitles" => Ok(Box::new(OpenSubtitlesProvider::from_settings(pool).await?)),
+        "local" => Ok(Box::new(LocalSubtitleProvider::new(&LocalSubtitleProvider::fetch_path(pool).await))),
+        _ => Err(AppError::BadRequest(format!("Unknown subtitle provider: {}", name))),
+    }
+}
+
+/// OpenSubtitles hash: the file size plus the 64-bit little-endian words of its first and last
+/// 64 KiB, wrapping. Files smaller than that have none.
+pub fn file_hash(path: &Path) -> Option<String> {
+    const CHUNK: u64 = 64 * 1024;
+    let mut file = std::fs::File::open(path).ok()?;
+    let size = file.metadata().ok()?.len();
+    if size < CHUNK {
+        return None;
+    }
+    let mut hash = size;
+    let mut buffer = vec![0u8; CHUNK as usize];
+    for start in [0, size - CHUNK] {
+        file.seek(SeekFrom::Start(start)).ok()?;
+        file.read_exact(&mut buffer).ok()?;
+        for word in buffer.chunks_exact(8) {
+            hash = hash.wrapping_add(u64::from_le_bytes(word.try_into().ok()?));
+        }
+    }
+    Some(format!("{:016x}", hash))
+}
+
+#[derive(FromRow)]
+struct SearchRow {
+    file_path: String,
+    title: Option<String>,
+    series_name: Option<String>,
+    year: Option<i64>,
+    season_number: Option<i32>,
+    episode_number: Option<i32>,
+    provider_ids: Option<String>,
+    disc_type: Option<String>,
+}
+
+/// What to ask providers for subtitles of media `id` in `languages`.
+pub async fn search_query(pool: &SqlitePool, media_id: i64, languages: Vec<String>) -> Result<SubtitleSearch, AppError> {
+    let row = sqlx::query_as::<_, SearchRow>(
+        "SELECT file_path, title, series_name, year, season_number, episode_number, provider_ids, disc_type FROM media WHERE id = ?"
+    )
+    .bind(media_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Media not found".to_string()))?;
+
+    // Disc rips aren't one file, so they have no hash
+    let hash = match row.disc_type {
+        Some(_) => None,
+        None => {
+            let path = PathBuf::from(&row.file_path);
+            tokio::task::spawn_blocking(move || file_hash(&path)).await.ok().flatten()
+        }
+    };
+    let ids: Option<serde_json::Value> = row.provider_ids.as_deref().and_then(|j| serde_json::from_str(j).ok());
+    let provider_ids = ["tmdb", "imdb", "tvdb"].iter()
+        .filter_map(|p| Some((p.to_string(), metadata::provider_id_str(ids.as_ref()?, p)?)))
+        .collect();
+    let episode = row.series_name.is_some() && row.episode_number.is_some();
+
+    Ok(SubtitleSearch {
+        file_hash: hash,
+        provider_ids,
+        title: if episode { row.series_name } else { row.title },
+        year: row.year,
+        season_number: row.season_number.filter(|_| episode),
+        episode_number: row.episode_number.filter(|_| episode),
+        languages,
+    })
+}
+
+/// Managed cache directory for subtitles of media `id` that aren't next to its video.
+pub fn managed_dir(media_id: i64) -> PathBuf {
+    Path::new(CACHE_DIR).join(media_id.to_string())
+}
+
+/// Store a subtitle file for a video as "<video>.<language>[.forced][.sdh].<format>", next to
+/// it unless `managed` (or its folder can't be written), never replacing an existing file.
+pub async fn store(media_id: i64, video: &Path, data: &[u8], format: &str, info: &TrackInfo, managed: bool) -> Result<PathBuf, AppError> {
+    let format = format.to_lowercase();
+    if SubtitleFormat::from_extension(&format).is_none() {
+        return Err(AppError::BadRequest(format!("Unsupported subtitle format: {}", format)));
+    }
+    let stem = video.file_stem().ok_or(AppError::Internal("Invalid filename".to_string()))?.to_string_lossy().to_string();
+    let mut name = stem;
+    name.extend(info.language.iter().map(|l| format!(".{}", l)));
+    if info.forced {
+        name.push_str(".forced");
+    }
+    if info.sdh {
+        name.push_str(".sdh");
+    }
+
+    // Files in both places are served by name, so the name must be free in both
+    let dirs: Vec<PathBuf> = video.parent().map(Path::to_path_buf).into_iter().chain(std::iter::once(managed_dir(media_id))).collect();
+    let file_name = (1..).map(|n| match n {
+            1 => format!("{}.{}", name, format),
+            n => format!("{}.{}.{}", name, n, format),
+        })
+        .find(|f| dirs.iter().all(|dir| !dir.join(f).exists()))
+        .expect("unbounded");
+
+    for dir in dirs.iter().skip(if managed { 1 } else { 0 }) {
+        if let Err(e) = tokio::fs::create_dir_all(dir).await {
+            tracing::warn!("Cannot store subtitle in {}: {}", dir.display(), e);
+            continue;
+        }
+        let path = dir.join(&file_name);
+        match tokio::fs::write(&path, data).await {
+            Ok(()) => return Ok(path),
+            Err(e) => tracing::warn!("Cannot store subtitle in {}: {}", dir.display(), e),
+        }
+    }
+    Err(AppError::Internal("Could not store subtitle".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_suffix_skips_the_number_store_adds() {
+        let info = TrackInfo::from_suffix(".en.forced.2");
+        assert_eq!(info.language.as_deref(), Some("en"));
+        assert!(info.forced);
+        assert_eq!(info.title, None);
+        assert_eq!(info.label(), "English - Forced");
+
+        let info = TrackInfo::from_suffix(".en.Commentary");
+        assert_eq!(info.label(), "English - Commentary");
+    }
+
+    fn track(language: Option<&str>, forced: bool, sdh: bool) -> TrackInfo {
+        TrackInfo { language: language.map(str::to_string), forced, sdh, ..Default::default() }
+    }
+
+    #[test]
+    fn from_suffix_reads_languages_and_flags() {
+        assert_eq!(TrackInfo::from_suffix(".eng.forced"), track(Some("en"), true, false));
+        assert_eq!(TrackInfo::from_suffix(".pt-BR.sdh"), track(Some("pt-BR"), false, true));
+        assert_eq!(TrackInfo::from_suffix(".de.cc"), track(Some("de"), false, true));
+        assert_eq!(TrackInfo::from_suffix(".tel"), track(Some("te"), false, false));
+        assert_eq!(TrackInfo::from_suffix(".Swahili.default").language.as_deref(), Some("sw"));
+        assert!(TrackInfo::from_suffix(".fre.default").default);
+        assert_eq!(TrackInfo::from_suffix(".pt-BR.sdh").label(), "Portuguese (BR) - SDH");
+
+        // "hi" is Hindi on its own and hearing impaired after a language
+        assert_eq!(TrackInfo::from_suffix(".hi"), track(Some("hi"), false, false));
+        assert_eq!(TrackInfo::from_suffix(".en.hi"), track(Some("en"), false, true));
+        assert_eq!(TrackInfo::from_suffix(".hi.forced"), track(Some("hi"), true, false));
+    }
+
+    #[test]
+    fn preference_rank_puts_exact_matches_before_other_regions() {
+        let preferred = parse_languages("pt-BR, en, xx, te");
+        assert_eq!(preferred, ["pt-BR", "en", "te"]);
+        assert_eq!(preference_rank(Some("pt-BR"), &preferred), 0);
+        assert_eq!(preference_rank(Some("pt"), &preferred), 1);
+        assert_eq!(preference_rank(Some("pt-PT"), &preferred), 1);
+        assert_eq!(preference_rank(Some("en"), &preferred), 2);
+        assert_eq!(preference_rank(Some("en-GB"), &preferred), 3);
+        assert_eq!(preference_rank(Some("te"), &preferred), 4);
+        assert_eq!(preference_rank(Some("de"), &preferred), usize::MAX);
+        assert_eq!(preference_rank(None, &preferred), usize::MAX);
+    }
+}
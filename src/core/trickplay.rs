@@ -0,0 +1,309 @@
+//! Trickplay - seek-preview thumbnails for scrubbing.
+//!
+//! One thumbnail every `INTERVAL_SECONDS`, `TILE_WIDTH` wide, tiled `COLUMNS` x `ROWS` into
+//! JPEG sprite sheets under `trickplay/<media id>/`, next to a `manifest.json` describing the
+//! layout. The index (WebVTT with `#xywh=` fragments, or JSON) is built from the manifest.
+//!
+//! `run_background` works through videos that have none yet, one at a time; a request for a
+//! video without sheets starts its generation right away. Both go through `check_eligible`:
+//! books, extras and unprobed videos get none, nor does anything while the
+//! `trickplay_enabled` setting is "false", and a video that failed isn't tried again until
+//! the next restart. Only keyframes are decoded, so a sheet set costs a fraction of a
+//! transcode.
+
+use crate::core::disc::{self, DiscType};
+use crate::core::{media_parts, metadata, probe, remux};
+use crate::error::AppError;
+use crate::models::media::{TrickplayDto, TrickplayTileDto};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, SqlitePool};
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const TRICKPLAY_DIR: &str = "trickplay";
+const MANIFEST: &str = "manifest.json";
+const INTERVAL_SECONDS: u32 = 10;
+const TILE_WIDTH: u32 = 320;
+const COLUMNS: u32 = 10;
+const ROWS: u32 = 10;
+/// Let the server settle before the first pass, and between passes over the library
+const BACKGROUND_DELAY: Duration = Duration::from_secs(60);
+const BACKGROUND_PERIOD: Duration = Duration::from_secs(15 * 60);
+
+/// Videos being generated, so the background task and requests don't both start one
+static IN_PROGRESS: Lazy<Mutex<HashSet<i64>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+/// Videos that failed, not tried again until the next restart
+static FAILED: Lazy<Mutex<HashSet<i64>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Layout of a video's sprite sheets, as stored in `manifest.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub interval: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub columns: u32,
+    pub rows: u32,
+    pub thumbnails: u32,
+    pub duration: f64,
+    /// File names, in order
+    pub sheets: Vec<String>,
+}
+
+#[derive(FromRow)]
+struct VideoRow {
+    file_path: String,
+    disc_type: Option<String>,
+    media_type: Option<String>,
+    has_video: bool,
+    duration: Option<f64>,
+    width: Option<i64>,
+    height: Option<i64>,
+}
+
+fn dir(media_id: i64) -> PathBuf {
+    Path::new(TRICKPLAY_DIR).join(media_id.to_string())
+}
+
+/// Path of a sprite sheet of media `id`; `None` for anything but a plain sheet name.
+pub fn sheet_path(media_id: i64, name: &str) -> Option<PathBuf> {
+    let number = name.strip_prefix("sheet_")?.strip_suffix(".jpg")?;
+    number.parse::<u32>().ok()?;
+    Some(dir(media_id).join(name))
+}
+
+/// The stored manifest, if sheets were generated since the video last changed.
+pub async fn manifest(media_id: i64, file_path: &Path) -> Option<Manifest> {
+    let path = dir(media_id).join(MANIFEST);
+    let generated = tokio::fs::metadata(&path).await.and_then(|m| m.modified()).ok()?;
+    if let Ok(source) = tokio::fs::metadata(file_path).await.and_then(|m| m.modified()) {
+        if source > generated {
+            return None;
+        }
+    }
+    serde_json::from_slice(&tokio::fs::read(&path).await.ok()?).ok()
+}
+
+async fn video(pool: &SqlitePool, media_id: i64) -> Result<VideoRow, AppError> {
+    sqlx::query_as::<_, VideoRow>(
+        "SELECT file_path, disc_type, media_type, duration,
+                EXISTS (SELECT 1 FROM media_streams WHERE media_id = media.id AND stream_type = 'video') AS has_video,
+                (SELECT MAX(width) FROM media_streams WHERE media_id = media.id AND stream_type = 'video') AS width,
+                (SELECT MAX(height) FROM media_streams WHERE media_id = media.id AND stream_type = 'video') AS height
+         FROM media WHERE id = ?"
+    )
+    .bind(media_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Media with id {} not found", media_id)))
+}
+
+/// Whether sheets may be generated for media `id`, and why not.
+async fn check_eligible(pool: &SqlitePool, media_id: i64, row: &VideoRow) -> Result<(), AppError> {
+    // Books and extras don't need scrubbing; videos without a probed stream can't be read
+    if matches!(row.media_type.as_deref(), Some("book" | "extra")) || !row.has_video {
+        return Err(AppError::NotFound(format!("Media {} has no video to generate trickplay for", media_id)));
+    }
+    if FAILED.lock().unwrap().contains(&media_id) {
+        return Err(AppError::Internal(format!("Trickplay for media {} failed; it is tried again after a restart", media_id)));
+    }
+    if metadata::get_setting(pool, "trickplay_enabled").await.is_some_and(|v| v == "false") {
+        return Err(AppError::NotFound("Trickplay is turned off".to_string()));
+    }
+    Ok(())
+}
+
+/// The manifest of media `id`, or `None` after starting its generation.
+pub async fn manifest_or_queue(pool: &SqlitePool, media_id: i64) -> Result<Option<Manifest>, AppError> {
+    let row = video(pool, media_id).await?;
+    if let Some(manifest) = manifest(media_id, Path::new(&row.file_path)).await {
+        return Ok(Some(manifest));
+    }
+    check_eligible(pool, media_id, &row).await?;
+    let pool = pool.clone();
+    tokio::spawn(async move {
+        if let Err(e) = generate(&pool, media_id).await {
+            tracing::warn!("Trickplay for media {} failed: {}", media_id, e);
+        }
+    });
+    Ok(None)
+}
+
+/// Generate the sprite sheets of media `id` (unless another task already is) and return
+/// their manifest. Callers check `check_eligible` first.
+async fn generate(pool: &SqlitePool, media_id: i64) -> Result<Option<Manifest>, AppError> {
+    if !IN_PROGRESS.lock().unwrap().insert(media_id) {
+        return Ok(None);
+    }
+    let result = generate_sheets(pool, media_id).await;
+    IN_PROGRESS.lock().unwrap().remove(&media_id);
+    match result {
+        Ok(_) => FAILED.lock().unwrap().remove(&media_id),
+        Err(_) => FAILED.lock().unwrap().insert(media_id),
+    };
+    result.map(Some)
+}
+
+async fn generate_sheets(pool: &SqlitePool, media_id: i64) -> Result<Manifest, AppError> {
+    let row = video(pool, media_id).await?;
+    let path = Path::new(&row.file_path);
+    let input = match row.disc_type.as_deref().and_then(DiscType::parse) {
+        Some(disc) => disc::input_args(path, disc).ok_or_else(|| AppError::NotFound(format!("No playable title in {}", row.file_path)))?,
+        None => vec!["-i".to_string(), row.file_path.clone()],
+    };
+    let duration = match row.duration {
+        Some(duration) => duration,
+        None => {
+            let probed = match row.disc_type.as_deref().and_then(DiscType::parse) {
+                Some(disc) => disc::duration(path, disc).await,
+                None => probe::duration(path).await,
+            }
+                .ok_or_else(|| AppError::Internal(format!("Could not determine the duration of {} (is FFprobe installed?)", row.file_path)))?;
+            media_parts::set_duration(pool, media_id, probed).await?;
+            probed
+        }
+    };
+    // Tiles keep the picture's shape, 16:9 when it wasn't probed
+    let tile_height = match (row.width, row.height) {
+        (Some(w), Some(h)) if w > 0 && h > 0 => ((TILE_WIDTH as i64 * h / w) as u32).next_multiple_of(2),
+        _ => TILE_WIDTH * 9 / 16,
+    };
+
+    // Written to a scratch directory and swapped in, so readers never see half a set
+    let target = dir(media_id);
+    let scratch = target.with_extension("partial");
+    let _ = tokio::fs::remove_dir_all(&scratch).await;
+    tokio::fs::create_dir_all(&scratch).await
+        .map_err(|e| AppError::Internal(format!("Could not create {}: {}", scratch.display(), e)))?;
+
+    tracing::info!("Generating trickplay for media {}", media_id);
+    let output = tokio::process::Command::new(remux::ffmpeg_command())
+        .args(["-hide_banner", "-loglevel", "error", "-y", "-skip_frame", "nokey"])
+        .args(&input)
+        .args(["-an", "-sn", "-map", "0:v:0"])
+        .arg("-vf").arg(format!(
+            "fps=1/{},scale={}:{},tile={}x{}",
+            INTERVAL_SECONDS, TILE_WIDTH, tile_height, COLUMNS, ROWS
+        ))
+        .args(["-q:v", "5", "-start_number", "0"])
+        .arg(scratch.join("sheet_%d.jpg"))
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to start FFmpeg: {}", e)))?;
+    if !output.status.success() {
+        let _ = tokio::fs::remove_dir_all(&scratch).await;
+        return Err(AppError::Internal(format!(
+            "Could not generate trickplay for {}: {}",
+            row.file_path, String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let thumbnails = (duration / INTERVAL_SECONDS as f64).ceil().max(1.0) as u32;
+    let sheets = (0..thumbnails.div_ceil(COLUMNS * ROWS))
+        .map(|n| format!("sheet_{}.jpg", n))
+        .take_while(|name| scratch.join(name).exists())
+        .collect::<Vec<_>>();
+    if sheets.is_empty() {
+        let _ = tokio::fs::remove_dir_all(&scratch).await;
+        return Err(AppError::Internal(format!("FFmpeg wrote no trickplay sheets for {}", row.file_path)));
+    }
+    let manifest = Manifest {
+        interval: INTERVAL_SECONDS,
+        tile_width: TILE_WIDTH,
+        tile_height,
+        columns: COLUMNS,
+        rows: ROWS,
+        // A short last sheet means fewer frames than the duration promised
+        thumbnails: thumbnails.min(sheets.len() as u32 * COLUMNS * ROWS),
+        duration,
+        sheets,
+    };
+    let json = serde_json::to_vec(&manifest).map_err(|e| AppError::Internal(e.to_string()))?;
+    tokio::fs::write(scratch.join(MANIFEST), json).await
+        .map_err(|e| AppError::Internal(format!("Could not write trickplay manifest: {}", e)))?;
+
+    let _ = tokio::fs::remove_dir_all(&target).await;
+    tokio::fs::rename(&scratch, &target).await
+        .map_err(|e| AppError::Internal(format!("Could not store trickplay: {}", e)))?;
+    tracing::info!("Generated {} trickplay sheets for media {}", manifest.sheets.len(), media_id);
+    Ok(manifest)
+}
+
+/// Each thumbnail's time range and place on its sheet.
+fn tiles(media_id: i64, manifest: &Manifest) -> Vec<TrickplayTileDto> {
+    let per_sheet = manifest.columns * manifest.rows;
+    (0..manifest.thumbnails)
+        .filter_map(|n| {
+            let sheet = manifest.sheets.get((n / per_sheet) as usize)?;
+            let position = n % per_sheet;
+            let start = (n * manifest.interval) as f64;
+            Some(TrickplayTileDto {
+                start,
+                end: (start + manifest.interval as f64).min(manifest.duration.max(start)),
+                url: format!("/api/v1/trickplay/{}/{}", media_id, sheet),
+                x: position % manifest.columns * manifest.tile_width,
+                y: position / manifest.columns * manifest.tile_height,
+            })
+        })
+        .collect()
+}
+
+pub fn index_json(media_id: i64, manifest: &Manifest) -> TrickplayDto {
+    TrickplayDto {
+        media_id,
+        interval: manifest.interval,
+        tile_width: manifest.tile_width,
+        tile_height: manifest.tile_height,
+        columns: manifest.columns,
+        rows: manifest.rows,
+        sheets: manifest.sheets.iter().map(|s| format!("/api/v1/trickplay/{}/{}", media_id, s)).collect(),
+        tiles: tiles(media_id, manifest),
+    }
+}
+
+fn vtt_timestamp(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!("{:02}:{:02}:{:02}.{:03}", millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000)
+}
+
+/// The WebVTT thumbnail track players know: one cue per thumbnail pointing at its tile.
+pub fn index_webvtt(media_id: i64, manifest: &Manifest) -> String {
+    let mut vtt = String::from("WEBVTT\n");
+    for tile in tiles(media_id, manifest) {
+        let _ = write!(
+            vtt,
+            "\n{} --> {}\n{}#xywh={},{},{},{}\n",
+            vtt_timestamp(tile.start), vtt_timestamp(tile.end), tile.url, tile.x, tile.y, manifest.tile_width, manifest.tile_height
+        );
+    }
+    vtt
+}
+
+/// Background task: generate sprite sheets for every video `check_eligible` lets through that
+/// has none, one video at a time.
+pub async fn run_background(pool: SqlitePool) {
+    tokio::time::sleep(BACKGROUND_DELAY).await;
+    let mut interval = tokio::time::interval(BACKGROUND_PERIOD);
+    loop {
+        interval.tick().await;
+        let ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM media ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap_or_default();
+
+        for id in ids {
+            let Ok(row) = video(&pool, id).await else { continue };
+            if manifest(id, Path::new(&row.file_path)).await.is_some() || check_eligible(&pool, id, &row).await.is_err() {
+                continue;
+            }
+            if let Err(e) = generate(&pool, id).await {
+                tracing::warn!("Trickplay for media {} failed: {}", id, e);
+            }
+        }
+    }
+}
@@ -2,11 +2,15 @@ pub mod models;
 
 use sqlx::sqlite::{Sqlite, SqlitePool, SqlitePoolOptions, SqliteConnectOptions};
 use sqlx::migrate::MigrateDatabase;
+use sqlx::Connection;
 use std::str::FromStr;
 
 pub async fn init_db() -> SqlitePool {
-    let database_url = "sqlite:vortex_server.db";
+    connect("sqlite:vortex_server.db").await
+}
 
+/// Open (creating if needed) and migrate the database at `database_url`
+pub async fn connect(database_url: &str) -> SqlitePool {
     if !Sqlite::database_exists(database_url).await.unwrap_or(false) {
         println!("Creating database {}", database_url);
         Sqlite::create_database(database_url).await.unwrap();
@@ -60,11 +64,25 @@ pub async fn init_db() -> SqlitePool {
     .await
     .expect("Failed to create playback_progress table");
 
+    // Create Metadata Retry Queue table (items whose metadata fetch failed transiently)
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS metadata_retry_queue (
+            media_id INTEGER PRIMARY KEY,
+            attempts INTEGER NOT NULL DEFAULT 1,
+            last_error TEXT,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY(media_id) REFERENCES media(id)
+        );"
+    )
+    .execute(&pool)
+    .await
+    .expect("Failed to create metadata_retry_queue table");
+
     // Create Media table (initial creation)
     sqlx::query(
         "CREATE TABLE IF NOT EXISTS media (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
-            file_path TEXT NOT NULL UNIQUE,
+            file_path TEXT NOT NULL,
             title TEXT,
             year INTEGER,
             poster_url TEXT,
@@ -181,5 +199,236 @@ pub async fn init_db() -> SqlitePool {
         let _ = sqlx::query("ALTER TABLE media ADD COLUMN genres TEXT").execute(&pool).await;
     }
 
+    // Migration: Add original_title if it doesn't exist
+    let has_original_title: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM pragma_table_info('media') WHERE name = 'original_title'"
+    )
+    .fetch_optional(&pool)
+    .await
+    .unwrap_or(None);
+
+    if has_original_title.is_none() {
+        println!("Migrating database: Adding original_title to media table");
+        let _ = sqlx::query("ALTER TABLE media ADD COLUMN original_title TEXT").execute(&pool).await;
+    }
+
+    // Migration: Add per-library metadata provider chain and merge policy
+    let has_metadata_providers: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM pragma_table_info('libraries') WHERE name = 'metadata_providers'"
+    )
+    .fetch_optional(&pool)
+    .await
+    .unwrap_or(None);
+
+    if has_metadata_providers.is_none() {
+        println!("Migrating database: Adding metadata provider chain to libraries table");
+        let _ = sqlx::query("ALTER TABLE libraries ADD COLUMN metadata_providers TEXT").execute(&pool).await;
+        let _ = sqlx::query("ALTER TABLE libraries ADD COLUMN metadata_merge_policy TEXT").execute(&pool).await;
+    }
+
+    // Migration: Add per-library episode ordering (aired/dvd/absolute/...)
+    let has_episode_order: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM pragma_table_info('libraries') WHERE name = 'episode_order'"
+    )
+    .fetch_optional(&pool)
+    .await
+    .unwrap_or(None);
+
+    if has_episode_order.is_none() {
+        println!("Migrating database: Adding episode_order to libraries table");
+        let _ = sqlx::query("ALTER TABLE libraries ADD COLUMN episode_order TEXT").execute(&pool).await;
+    }
+
+    // Migration: Add per-library episode numbering in filenames (standard/absolute/date)
+    let has_episode_numbering: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM pragma_table_info('libraries') WHERE name = 'episode_numbering'"
+    )
+    .fetch_optional(&pool)
+    .await
+    .unwrap_or(None);
+
+    if has_episode_numbering.is_none() {
+        println!("Migrating database: Adding episode_numbering to libraries table");
+        let _ = sqlx::query("ALTER TABLE libraries ADD COLUMN episode_numbering TEXT").execute(&pool).await;
+    }
+
+    // Migration: Add per-library custom filename parsing rules
+    let has_parsing_rules: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM pragma_table_info('libraries') WHERE name = 'parsing_rules'"
+    )
+    .fetch_optional(&pool)
+    .await
+    .unwrap_or(None);
+
+    if has_parsing_rules.is_none() {
+        println!("Migrating database: Adding parsing_rules to libraries table");
+        let _ = sqlx::query("ALTER TABLE libraries ADD COLUMN parsing_rules TEXT").execute(&pool).await;
+    }
+
+    // Migration: Add movie version grouping (primary row id, resolution and edition per file)
+    let has_version_of: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM pragma_table_info('media') WHERE name = 'version_of'"
+    )
+    .fetch_optional(&pool)
+    .await
+    .unwrap_or(None);
+
+    if has_version_of.is_none() {
+        println!("Migrating database: Adding version columns to media table");
+        let _ = sqlx::query("ALTER TABLE media ADD COLUMN version_of INTEGER").execute(&pool).await;
+        let _ = sqlx::query("ALTER TABLE media ADD COLUMN resolution TEXT").execute(&pool).await;
+        let _ = sqlx::query("ALTER TABLE media ADD COLUMN edition TEXT").execute(&pool).await;
+    }
+
+    // Migration: Add extras (trailers, featurettes, ...) and the movie they belong to
+    let has_extra_type: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM pragma_table_info('media') WHERE name = 'extra_type'"
+    )
+    .fetch_optional(&pool)
+    .await
+    .unwrap_or(None);
+
+    if has_extra_type.is_none() {
+        println!("Migrating database: Adding extra columns to media table");
+        let _ = sqlx::query("ALTER TABLE media ADD COLUMN extra_of INTEGER").execute(&pool).await;
+        let _ = sqlx::query("ALTER TABLE media ADD COLUMN extra_type TEXT").execute(&pool).await;
+    }
+
+    // Migration: Add multi-part stacking (primary part id, part number and duration per file)
+    let has_part_of: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM pragma_table_info('media') WHERE name = 'part_of'"
+    )
+    .fetch_optional(&pool)
+    .await
+    .unwrap_or(None);
+
+    if has_part_of.is_none() {
+        println!("Migrating database: Adding part columns to media table");
+        let _ = sqlx::query("ALTER TABLE media ADD COLUMN part_of INTEGER").execute(&pool).await;
+        let _ = sqlx::query("ALTER TABLE media ADD COLUMN part_number INTEGER").execute(&pool).await;
+        let _ = sqlx::query("ALTER TABLE media ADD COLUMN duration REAL").execute(&pool).await;
+    }
+
+    // Migration: Add disc_type for DVD/Blu-ray folder rips and images
+    let has_disc_type: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM pragma_table_info('media') WHERE name = 'disc_type'"
+    )
+    .fetch_optional(&pool)
+    .await
+    .unwrap_or(None);
+
+    if has_disc_type.is_none() {
+        println!("Migrating database: Adding disc_type to media table");
+        let _ = sqlx::query("ALTER TABLE media ADD COLUMN disc_type TEXT").execute(&pool).await;
+    }
+
+    // Migration: Add bitrate, probed with FFprobe along with the streams in media_streams
+    let has_bitrate: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM pragma_table_info('media') WHERE name = 'bitrate'"
+    )
+    .fetch_optional(&pool)
+    .await
+    .unwrap_or(None);
+
+    if has_bitrate.is_none() {
+        println!("Migrating database: Adding bitrate to media table");
+        let _ = sqlx::query("ALTER TABLE media ADD COLUMN bitrate INTEGER").execute(&pool).await;
+    }
+
+    // Migration: Drop the UNIQUE constraint on media.file_path so a multi-episode file gets one row
+    // per episode. SQLite can't drop a constraint in place, so the table is rebuilt from its own
+    // stored schema (which already includes every column added above).
+    let media_sql: Option<String> = sqlx::query_scalar(
+        "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'media'"
+    )
+    .fetch_optional(&pool)
+    .await
+    .unwrap_or(None);
+
+    if let Some(sql) = media_sql.filter(|s| s.contains("file_path TEXT NOT NULL UNIQUE")) {
+        println!("Migrating database: Allowing multiple episodes per media file");
+        let new_sql = sql
+            .replacen("CREATE TABLE media", "CREATE TABLE media_new", 1)
+            .replacen("file_path TEXT NOT NULL UNIQUE", "file_path TEXT NOT NULL", 1);
+
+        // foreign_keys must be off (outside the transaction) or dropping media would touch its referrers
+        let mut conn = pool.acquire().await.expect("Failed to acquire connection for migration");
+        sqlx::query("PRAGMA foreign_keys = OFF").execute(&mut *conn).await.expect("Failed to disable foreign keys");
+        let mut tx = conn.begin().await.expect("Failed to start migration");
+        sqlx::query(&new_sql).execute(&mut *tx).await.expect("Failed to create media_new table");
+        sqlx::query("INSERT INTO media_new SELECT * FROM media").execute(&mut *tx).await.expect("Failed to copy media rows");
+        sqlx::query("DROP TABLE media").execute(&mut *tx).await.expect("Failed to drop old media table");
+        sqlx::query("ALTER TABLE media_new RENAME TO media").execute(&mut *tx).await.expect("Failed to rename media_new");
+        tx.commit().await.expect("Failed to commit media table migration");
+        sqlx::query("PRAGMA foreign_keys = ON").execute(&mut *conn).await.expect("Failed to re-enable foreign keys");
+    }
+
+    // One row per file, or per episode of a multi-episode file
+    sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS idx_media_file_episode ON media(file_path, COALESCE(episode_number, -1))")
+        .execute(&pool)
+        .await
+        .expect("Failed to create media file index");
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_media_version_of ON media(version_of)")
+        .execute(&pool)
+        .await
+        .expect("Failed to create media version index");
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_media_extra_of ON media(extra_of)")
+        .execute(&pool)
+        .await
+        .expect("Failed to create media extra index");
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_media_part_of ON media(part_of)")
+        .execute(&pool)
+        .await
+        .expect("Failed to create media part index");
+
+    // Create Media Streams table (video, audio and subtitle streams probed with FFprobe)
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS media_streams (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            media_id INTEGER NOT NULL,
+            stream_index INTEGER NOT NULL,
+            stream_type TEXT NOT NULL,
+            codec TEXT,
+            profile TEXT,
+            language TEXT,
+            title TEXT,
+            channels INTEGER,
+            channel_layout TEXT,
+            width INTEGER,
+            height INTEGER,
+            bitrate INTEGER,
+            frame_rate REAL,
+            hdr TEXT,
+            is_default INTEGER NOT NULL DEFAULT 0,
+            is_forced INTEGER NOT NULL DEFAULT 0,
+            is_hearing_impaired INTEGER NOT NULL DEFAULT 0,
+            FOREIGN KEY(media_id) REFERENCES media(id)
+        );"
+    )
+    .execute(&pool)
+    .await
+    .expect("Failed to create media_streams table");
+
+    // Migration: Add is_hearing_impaired (SDH subtitle streams) to media_streams
+    let has_hearing_impaired: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM pragma_table_info('media_streams') WHERE name = 'is_hearing_impaired'"
+    )
+    .fetch_optional(&pool)
+    .await
+    .unwrap_or(None);
+
+    if has_hearing_impaired.is_none() {
+        println!("Migrating database: Adding is_hearing_impaired to media_streams table");
+        let _ = sqlx::query("ALTER TABLE media_streams ADD COLUMN is_hearing_impaired INTEGER NOT NULL DEFAULT 0").execute(&pool).await;
+    }
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_media_streams_media_id ON media_streams(media_id)")
+        .execute(&pool)
+        .await
+        .expect("Failed to create media streams index");
+
     pool
 }
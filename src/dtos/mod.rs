@@ -1,2 +1,4 @@
 pub mod tmdb;
+pub mod tvdb;
+pub mod nfo;
 pub mod requests;
@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+
+/// Kodi NFO document. The same shape covers `<movie>`, `<tvshow>` and `<episodedetails>`;
+/// the root element name is not checked when reading and unknown elements are ignored.
+/// Empty fields are left out when writing.
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct NfoDocument {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub originaltitle: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub showtitle: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub season: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub episode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub premiered: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aired: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plot: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outline: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<String>,
+    /// Pre-Kodi 17 single rating
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rating: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ratings: Option<NfoRatings>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub genre: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub uniqueid: Vec<NfoUniqueId>,
+    /// Legacy ID tags written by older scrapers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub imdbid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tmdbid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tvdbid: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub thumb: Vec<NfoThumb>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fanart: Option<NfoFanart>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct NfoUniqueId {
+    #[serde(rename = "@type")]
+    pub kind: Option<String>,
+    #[serde(rename = "@default", skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    #[serde(rename = "$text", default)]
+    pub value: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct NfoThumb {
+    #[serde(rename = "@aspect", skip_serializing_if = "Option::is_none")]
+    pub aspect: Option<String>,
+    #[serde(rename = "$text", default)]
+    pub url: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct NfoFanart {
+    #[serde(default)]
+    pub thumb: Vec<NfoThumb>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct NfoRatings {
+    #[serde(default)]
+    pub rating: Vec<NfoRating>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct NfoRating {
+    #[serde(rename = "@name", skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(rename = "@default", skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+/// Episode NFOs for multi-episode files hold one `<episodedetails>` per episode, one after another.
+/// The reader wraps the file in a synthetic root to read them all.
+#[derive(Deserialize, Debug, Default)]
+pub struct NfoEpisodeList {
+    #[serde(default)]
+    pub episodedetails: Vec<NfoDocument>,
+}
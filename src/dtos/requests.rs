@@ -13,3 +13,36 @@ pub struct SearchQuery {
     pub query: String,
     pub media_type: Option<String>,
 }
+
+/// Request for a playback plan: a built-in device profile by name, or the device's own
+/// capabilities. `version` picks another version of the movie; `subtitle` is the selected
+/// subtitle track ID, if any.
+#[derive(Deserialize)]
+pub struct PlaybackRequest {
+    pub profile: Option<String>,
+    pub device: Option<crate::core::device_profile::DeviceProfile>,
+    pub version: Option<i64>,
+    pub subtitle: Option<String>,
+}
+
+/// Quality filters for library listings. `resolution` is a minimum ("720p", "1080p", "4k").
+#[derive(Deserialize)]
+pub struct QualityFilter {
+    pub resolution: Option<String>,
+    pub video_codec: Option<String>,
+    pub hdr: Option<bool>,
+}
+
+/// Request to download a subtitle found by a search. `language`, `forced` and `sdh` name the
+/// stored file; `location` is "media" (next to the video, the default) or "cache".
+#[derive(Deserialize)]
+pub struct DownloadSubtitleRequest {
+    pub provider: Option<String>,
+    pub id: String,
+    pub language: Option<String>,
+    #[serde(default)]
+    pub forced: bool,
+    #[serde(default)]
+    pub sdh: bool,
+    pub location: Option<String>,
+}
@@ -23,6 +23,8 @@ pub struct TmdbResult {
     pub id: i64,
     #[serde(alias = "title", alias = "name")]
     pub title: String,
+    #[serde(alias = "original_title", alias = "original_name")]
+    pub original_title: Option<String>,
     #[serde(alias = "overview")]
     pub overview: Option<String>,
     #[serde(alias = "poster_path")]
@@ -46,4 +48,5 @@ pub struct TmdbEpisode {
     pub name: String,
     pub overview: String,
     pub still_path: Option<String>,
+    pub air_date: Option<String>,
 }
@@ -0,0 +1,104 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Every TheTVDB v4 response wraps its payload in `data`
+#[derive(Deserialize, Debug)]
+pub struct TvdbEnvelope<T> {
+    pub data: T,
+    pub links: Option<TvdbLinks>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TvdbLinks {
+    pub next: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TvdbLoginData {
+    pub token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TvdbSearchResult {
+    pub tvdb_id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub overview: Option<String>,
+    pub image_url: Option<String>,
+    pub year: Option<String>,
+    /// Translated names keyed by ISO 639-2 code
+    pub translations: Option<HashMap<String, String>>,
+    /// Translated overviews keyed by ISO 639-2 code
+    pub overviews: Option<HashMap<String, String>>,
+}
+
+/// Extended series or movie record (`/series/{id}/extended`, `/movies/{id}/extended`)
+#[derive(Deserialize, Debug)]
+pub struct TvdbRecord {
+    pub id: i64,
+    pub name: Option<String>,
+    pub overview: Option<String>,
+    pub image: Option<String>,
+    pub year: Option<String>,
+    #[serde(rename = "averageRuntime", alias = "runtime")]
+    pub runtime: Option<i32>,
+    pub genres: Option<Vec<TvdbGenre>>,
+    pub artworks: Option<Vec<TvdbArtwork>>,
+    #[serde(rename = "remoteIds")]
+    pub remote_ids: Option<Vec<TvdbRemoteId>>,
+    pub translations: Option<TvdbTranslations>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TvdbGenre {
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TvdbArtwork {
+    pub image: String,
+    #[serde(rename = "type")]
+    pub kind: i64,
+    pub language: Option<String>,
+    pub score: Option<f64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TvdbRemoteId {
+    pub id: String,
+    #[serde(rename = "sourceName")]
+    pub source_name: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TvdbTranslations {
+    #[serde(rename = "nameTranslations")]
+    pub name_translations: Option<Vec<TvdbTranslation>>,
+    #[serde(rename = "overviewTranslations")]
+    pub overview_translations: Option<Vec<TvdbTranslation>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TvdbTranslation {
+    pub language: String,
+    pub name: Option<String>,
+    pub overview: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TvdbEpisodePage {
+    pub episodes: Vec<TvdbEpisode>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TvdbEpisode {
+    pub id: i64,
+    pub name: Option<String>,
+    pub overview: Option<String>,
+    pub number: i32,
+    #[serde(rename = "seasonNumber")]
+    pub season_number: i32,
+    pub aired: Option<String>,
+    pub image: Option<String>,
+}
@@ -47,6 +47,12 @@ async fn main() {
     // Background scan removed to prevent load on startup
     // Scan is now triggered manually via API or on library creation
 
+    // Stop HLS transcodes nobody is watching anymore
+    tokio::spawn(crate::core::hls::cleanup_idle_sessions());
+
+    // Seek-preview sprite sheets for videos that don't have them yet
+    tokio::spawn(crate::core::trickplay::run_background(pool.clone()));
+
     // Router with static file serving and request logging
     let app = app(pool)
         .nest_service("/", ServeDir::new("static"))
@@ -18,4 +18,14 @@ pub struct Library {
     pub name: String,
     pub path: String,
     pub library_type: LibraryType,
+    /// Ordered, comma separated provider names (e.g. "tmdb,tvdb"); NULL uses the default provider
+    pub metadata_providers: Option<String>,
+    /// JSON object of per-field merge rules, see `core::metadata_merge`
+    pub metadata_merge_policy: Option<String>,
+    /// Episode ordering for providers that support several (TheTVDB): aired, dvd, absolute, ...
+    pub episode_order: Option<String>,
+    /// How filenames number episodes: standard (SxxEyy), absolute (anime) or date (daily shows)
+    pub episode_numbering: Option<String>,
+    /// JSON array of filename regexes tried before the built-in parsing, see `core::parsing_rules`
+    pub parsing_rules: Option<String>,
 }
@@ -31,6 +31,33 @@ pub struct Media {
     pub still_url: Option<String>,
     pub runtime: Option<i32>,
     pub genres: Option<String>,
+    pub original_title: Option<String>,
+    // Movie versions: the primary row's id (NULL for the primary itself), resolution and edition
+    #[sqlx(default)]
+    pub version_of: Option<i64>,
+    #[sqlx(default)]
+    pub resolution: Option<String>,
+    #[sqlx(default)]
+    pub edition: Option<String>,
+    // Extras: the movie an extra belongs to (series extras use series_name) and its type
+    #[sqlx(default)]
+    pub extra_of: Option<i64>,
+    #[sqlx(default)]
+    pub extra_type: Option<String>,
+    // Stacked movies: the primary part's id (NULL for the primary itself), this file's part
+    // number and its duration in seconds
+    #[sqlx(default)]
+    pub part_of: Option<i64>,
+    #[sqlx(default)]
+    pub part_number: Option<i32>,
+    #[sqlx(default)]
+    pub duration: Option<f64>,
+    // DVD/Blu-ray rips: "dvd" or "bluray" when file_path is a disc folder or image
+    #[sqlx(default)]
+    pub disc_type: Option<String>,
+    // Overall bitrate in bits per second, from FFprobe
+    #[sqlx(default)]
+    pub bitrate: Option<i64>,
     pub library_type: Option<LibraryType>,
 }
 
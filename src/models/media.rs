@@ -0,0 +1,124 @@
+use serde::Serialize;
+use super::db::media::Media;
+
+/// One file of a movie that exists in several versions (resolutions, editions).
+#[derive(Debug, Serialize, Clone)]
+pub struct MediaVersionDto {
+    pub id: i64,
+    pub file_path: String,
+    pub resolution: Option<String>,
+    pub edition: Option<String>,
+    /// Display name, e.g. "Director's Cut 2160p"
+    pub label: String,
+    pub is_primary: bool,
+}
+
+/// One file of a movie split across several files (cd1, cd2, ...).
+#[derive(Debug, Serialize, Clone)]
+pub struct MediaPartDto {
+    pub id: i64,
+    pub file_path: String,
+    pub part_number: i32,
+    /// Length of this part in seconds, when known
+    pub duration: Option<f64>,
+    /// Where this part starts on the combined timeline, in seconds
+    pub offset: Option<f64>,
+}
+
+/// Media details: the media row plus its related files.
+#[derive(Debug, Serialize, Clone)]
+pub struct MediaDetailDto {
+    #[serde(flatten)]
+    pub media: Media,
+    /// Every version of this item, primary first (a single entry for most media)
+    pub versions: Vec<MediaVersionDto>,
+    /// The parts of a stacked movie in playback order (empty unless the movie is split)
+    pub parts: Vec<MediaPartDto>,
+    /// Combined duration of all parts in seconds, when every part's duration is known
+    pub total_duration: Option<f64>,
+    /// Video, audio and subtitle streams of the file, once probed
+    pub streams: Vec<MediaStreamDto>,
+}
+
+/// One video, audio or subtitle stream of a file, as FFprobe reported it.
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct MediaStreamDto {
+    pub stream_index: i64,
+    /// video, audio or subtitle
+    pub stream_type: String,
+    pub codec: Option<String>,
+    pub profile: Option<String>,
+    /// ISO 639-2 code, e.g. "eng"
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub channels: Option<i64>,
+    /// e.g. "5.1(side)"
+    pub channel_layout: Option<String>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    /// Bits per second
+    pub bitrate: Option<i64>,
+    pub frame_rate: Option<f64>,
+    /// HDR10, HLG or Dolby Vision; null for SDR
+    pub hdr: Option<String>,
+    pub is_default: bool,
+    pub is_forced: bool,
+    /// SDH/closed captions
+    pub is_hearing_impaired: bool,
+}
+
+/// A trailer, featurette or other extra attached to a movie or series.
+#[derive(Debug, Serialize, Clone)]
+pub struct ExtraDto {
+    pub id: i64,
+    pub title: Option<String>,
+    /// trailer, behind_the_scenes, deleted_scene, featurette, interview, scene, short, clip or other
+    pub extra_type: String,
+    /// Display name of the type, e.g. "Behind the Scenes"
+    pub type_label: String,
+    pub file_path: String,
+    pub runtime: Option<i32>,
+}
+
+/// How a device should play a media item, from `core::device_profile::decide`.
+#[derive(Debug, Serialize, Clone)]
+pub struct PlaybackPlanDto {
+    pub media_id: i64,
+    /// direct_play, remux or transcode
+    pub method: String,
+    pub url: String,
+    /// Why this method was chosen, e.g. "Video codec hevc is not supported"
+    pub reason: String,
+    pub container: Option<String>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    /// Whether the audio is converted to AAC
+    pub transcode_audio: bool,
+    /// The selected subtitle track when it is images the device can't render, forcing a
+    /// transcode with it burned in
+    pub burn_in_subtitle: Option<String>,
+}
+
+/// Seek-preview thumbnails of a video: sprite sheets and where each thumbnail is on them.
+#[derive(Debug, Serialize, Clone)]
+pub struct TrickplayDto {
+    pub media_id: i64,
+    /// Seconds between thumbnails
+    pub interval: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub columns: u32,
+    pub rows: u32,
+    pub sheets: Vec<String>,
+    pub tiles: Vec<TrickplayTileDto>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TrickplayTileDto {
+    pub start: f64,
+    pub end: f64,
+    /// Sprite sheet URL
+    pub url: String,
+    pub x: u32,
+    pub y: u32,
+}
@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NormalizedMetadata {
     pub title: String,
+    pub original_title: Option<String>,
     pub year: Option<String>,
     pub plot: Option<String>,
     pub poster_url: Option<String>,
@@ -24,3 +25,77 @@ pub struct EpisodeMetadata {
     pub still_path: Option<String>,
     pub air_date: Option<String>,
 }
+
+/// Language preferences passed to providers on every lookup.
+/// `language` is a BCP 47 style tag such as "de-DE"; `region` is an ISO 3166-1 code such as "DE".
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MetadataLocale {
+    pub language: String,
+    pub region: Option<String>,
+    /// Used to fill titles/overviews that have no translation in `language`
+    pub fallback_language: Option<String>,
+}
+
+impl Default for MetadataLocale {
+    fn default() -> Self {
+        Self {
+            language: "en-US".to_string(),
+            region: None,
+            fallback_language: Some("en-US".to_string()),
+        }
+    }
+}
+
+impl MetadataLocale {
+    /// The fallback language, if one is set and differs from the preferred language.
+    pub fn fallback(&self) -> Option<&str> {
+        self.fallback_language.as_deref().filter(|f| !f.eq_ignore_ascii_case(&self.language))
+    }
+
+    /// Full language tag, combining a bare language ("de") with the region ("AT") when set.
+    pub fn language_tag(&self) -> String {
+        match &self.region {
+            Some(region) if !self.language.contains('-') => format!("{}-{}", self.language, region),
+            _ => self.language.clone(),
+        }
+    }
+}
+
+/// What subtitle providers are asked for: the file's OpenSubtitles hash and the media's
+/// provider IDs (the series' for an episode, with its season and episode numbers).
+#[derive(Debug, Clone, Default)]
+pub struct SubtitleSearch {
+    pub file_hash: Option<String>,
+    /// "tmdb" -> "438631", "imdb" -> "tt1160419"
+    pub provider_ids: std::collections::HashMap<String, String>,
+    pub title: Option<String>,
+    pub year: Option<i64>,
+    pub season_number: Option<i32>,
+    pub episode_number: Option<i32>,
+    /// ISO 639-1 codes, with a region where it matters ("pt-BR")
+    pub languages: Vec<String>,
+}
+
+/// A subtitle a provider has for a search.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubtitleCandidate {
+    pub provider: String,
+    /// Provider specific ID to download it by
+    pub id: String,
+    pub language: Option<String>,
+    pub release: Option<String>,
+    /// File extension: "srt", "ass", ...
+    pub format: String,
+    pub forced: bool,
+    pub sdh: bool,
+    pub downloads: Option<i64>,
+    /// Made for this exact file (matched by hash), so in sync
+    pub hash_match: bool,
+}
+
+/// A downloaded subtitle file.
+#[derive(Debug, Clone)]
+pub struct SubtitleFile {
+    pub data: Vec<u8>,
+    pub format: String,
+}
@@ -0,0 +1,167 @@
+//! Local subtitle provider backed by a directory of subtitle files, a stand-in for OpenSubtitles
+//! in air-gapped installs and hermetic tests. Selected with `subtitle_provider = "local"`; the
+//! directory comes from the `local_subtitles_path` setting (default `subtitle_catalog`).
+//!
+//! Files are named by what they match, then language and flags like sidecar files:
+//!
+//! ```text
+//! 8e245d9679d31e12.en.srt        OpenSubtitles hash of the video file
+//! tmdb-438631.de.forced.srt      a movie by provider ID
+//! imdb-tt0903747-s01e02.en.srt   an episode, by its series' ID
+//! ```
+
+use crate::core::subtitles::TrackInfo;
+use crate::providers::traits::SubtitleProvider;
+use crate::models::metadata::{SubtitleSearch, SubtitleCandidate, SubtitleFile};
+use crate::error::AppError;
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_PATH: &str = "subtitle_catalog";
+
+pub struct LocalSubtitleProvider {
+    path: PathBuf,
+}
+
+impl LocalSubtitleProvider {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.to_path_buf() }
+    }
+
+    /// Fetch the directory from database settings
+    pub async fn fetch_path(pool: &sqlx::SqlitePool) -> PathBuf {
+        PathBuf::from(crate::core::metadata::get_setting(pool, "local_subtitles_path").await.unwrap_or_else(|| DEFAULT_PATH.to_string()))
+    }
+}
+
+/// The names a search is known by: its hash and "<provider>-<id>" for each provider ID.
+fn keys(query: &SubtitleSearch) -> Vec<String> {
+    let episode = match (query.season_number, query.episode_number) {
+        (Some(season), Some(episode)) => format!("-s{:02}e{:02}", season, episode),
+        _ => String::new(),
+    };
+    query.file_hash.iter().cloned()
+        .chain(query.provider_ids.iter().map(|(provider, id)| format!("{}-{}{}", provider, id, episode).to_lowercase()))
+        .collect()
+}
+
+#[async_trait]
+impl SubtitleProvider for LocalSubtitleProvider {
+    async fn search(&self, query: &SubtitleSearch) -> Result<Vec<SubtitleCandidate>, AppError> {
+        let keys = keys(query);
+        let mut read_dir = tokio::fs::read_dir(&self.path).await
+            .map_err(|e| AppError::Internal(format!("Could not read subtitle catalog {}: {}", self.path.display(), e)))?;
+
+        let mut candidates = Vec::new();
+        while let Ok(Some(entry)) = read_dir.next_entry().await {
+            let path = entry.path();
+            let (Some(stem), Some(format)) = (path.file_stem(), path.extension()) else { continue };
+            let stem = stem.to_string_lossy();
+            let (key, suffix) = stem.split_once('.').unwrap_or((&stem, ""));
+            let Some(key) = keys.iter().find(|k| k.eq_ignore_ascii_case(key)) else { continue };
+
+            let info = TrackInfo::from_suffix(suffix);
+            let wanted = query.languages.is_empty() || info.language.as_deref().is_some_and(|language| {
+                let base = |code: &str| code.split('-').next().unwrap_or(code).to_string();
+                query.languages.iter().any(|l| base(l) == base(language))
+            });
+            if !wanted {
+                continue;
+            }
+            candidates.push(SubtitleCandidate {
+                provider: "local".to_string(),
+                id: entry.file_name().to_string_lossy().to_string(),
+                language: info.language.clone(),
+                release: Some(stem.to_string()),
+                format: format.to_string_lossy().to_lowercase(),
+                forced: info.forced,
+                sdh: info.sdh,
+                downloads: None,
+                hash_match: query.file_hash.as_ref() == Some(key),
+            });
+        }
+        candidates.sort_by(|a, b| b.hash_match.cmp(&a.hash_match).then_with(|| a.id.cmp(&b.id)));
+        Ok(candidates)
+    }
+
+    async fn download(&self, id: &str) -> Result<SubtitleFile, AppError> {
+        // IDs are plain file names within the catalog
+        if Path::new(id).file_name() != Some(std::ffi::OsStr::new(id)) {
+            return Err(AppError::BadRequest(format!("Invalid subtitle ID: {}", id)));
+        }
+        let path = self.path.join(id);
+        let data = tokio::fs::read(&path).await
+            .map_err(|_| AppError::NotFound(format!("Subtitle catalog has no file '{}'", id)))?;
+        Ok(SubtitleFile {
+            data,
+            format: path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_else(|| "srt".to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> LocalSubtitleProvider {
+        LocalSubtitleProvider::new(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/subtitle_catalog"))
+    }
+
+    fn ids(candidates: &[SubtitleCandidate]) -> Vec<&str> {
+        candidates.iter().map(|c| c.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn search_matches_by_hash_before_provider_id() {
+        let query = SubtitleSearch {
+            file_hash: Some("8e245d9679d31e12".to_string()),
+            provider_ids: [("tmdb".to_string(), "603".to_string())].into(),
+            ..SubtitleSearch::default()
+        };
+        let results = catalog().search(&query).await.unwrap();
+        assert_eq!(ids(&results), ["8e245d9679d31e12.en.srt", "tmdb-603.de.forced.srt", "tmdb-603.en.2.srt", "tmdb-603.en.srt"]);
+        assert!(results[0].hash_match);
+        assert!(results[1..].iter().all(|c| !c.hash_match));
+        assert!(results[1].forced);
+    }
+
+    #[tokio::test]
+    async fn search_matches_episodes_by_series_id_and_numbers() {
+        let query = SubtitleSearch {
+            provider_ids: [("imdb".to_string(), "tt0903747".to_string())].into(),
+            season_number: Some(1),
+            episode_number: Some(2),
+            ..SubtitleSearch::default()
+        };
+        let results = catalog().search(&query).await.unwrap();
+        assert_eq!(ids(&results), ["imdb-tt0903747-s01e02.en.sdh.srt"]);
+        assert!(results[0].sdh);
+
+        // Without episode numbers nothing of the series matches
+        let query = SubtitleSearch { season_number: None, episode_number: None, ..query };
+        assert!(catalog().search(&query).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn search_keeps_only_the_wanted_languages() {
+        let query = SubtitleSearch {
+            provider_ids: [("tmdb".to_string(), "603".to_string())].into(),
+            languages: vec!["de-AT".to_string()],
+            ..SubtitleSearch::default()
+        };
+        let results = catalog().search(&query).await.unwrap();
+        assert_eq!(ids(&results), ["tmdb-603.de.forced.srt"]);
+        assert_eq!(results[0].language.as_deref(), Some("de"));
+    }
+
+    #[tokio::test]
+    async fn download_reads_only_files_in_the_catalog() {
+        let file = catalog().download("tmdb-603.en.srt").await.unwrap();
+        assert_eq!(file.format, "srt");
+        assert!(String::from_utf8(file.data).unwrap().contains("tmdb-603.en.srt"));
+
+        assert!(matches!(catalog().download("../offline_catalog/movies.json").await, Err(AppError::BadRequest(_))));
+        assert!(matches!(catalog().download("/etc/passwd").await, Err(AppError::BadRequest(_))));
+        assert!(matches!(catalog().download("missing.en.srt").await, Err(AppError::NotFound(_))));
+    }
+}
@@ -1,2 +1,8 @@
 pub mod traits;
+pub mod rate_limit;
 pub mod tmdb;
+pub mod tvdb;
+pub mod offline;
+pub mod nfo;
+pub mod opensubtitles;
+pub mod local_subtitles;
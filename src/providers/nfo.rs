@@ -0,0 +1,418 @@
+//! Local metadata from Kodi-style NFO files and sidecar artwork left beside media files by
+//! other tools. Unlike the remote providers this reads by file path rather than by search
+//! query, so it sits in front of the library's provider chain (see `fetch_metadata`).
+//!
+//! Files read:
+//! - movies: `<name>.nfo`, then `movie.nfo`; artwork `<name>-poster.jpg`, `poster.jpg`,
+//!   `folder.jpg`, `<name>-fanart.jpg`, `fanart.jpg`. The names without `<name>` are only
+//!   read from a folder the movie has to itself (see `movie_dir`).
+//! - series: `tvshow.nfo`, `poster.jpg`, `folder.jpg`, `fanart.jpg` in the series folder
+//! - episodes: `<name>.nfo` (several `<episodedetails>` for multi-episode files), `<name>-thumb.jpg`
+
+use crate::core::{extras, media_parts, media_versions};
+use crate::core::scanner::VIDEO_EXTENSIONS;
+use crate::dtos::nfo::{NfoDocument, NfoEpisodeList};
+use crate::models::metadata::NormalizedMetadata;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::{json, Map, Value};
+use std::path::{Path, PathBuf};
+
+/// Name the NFO result is recorded under in a provider chain / merge policy
+pub const NFO_PROVIDER: &str = "nfo";
+
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp"];
+
+// NFOs that only contain a link to the scraper site instead of XML
+static RE_IMDB_ID: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b(tt\d{7,8})\b").unwrap());
+static RE_TMDB_URL: Lazy<Regex> = Lazy::new(|| Regex::new(r"themoviedb\.org/(?:movie|tv)/(\d+)").unwrap());
+static RE_TVDB_URL: Lazy<Regex> = Lazy::new(|| Regex::new(r"thetvdb\.com/\S*?(?:[?&]id=|/series/|/movies/)(\d+)").unwrap());
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArtworkKind {
+    Poster,
+    Backdrop,
+    Still,
+}
+
+impl ArtworkKind {
+    pub fn parse(s: &str) -> Option<ArtworkKind> {
+        match s {
+            "poster" => Some(ArtworkKind::Poster),
+            "backdrop" => Some(ArtworkKind::Backdrop),
+            "still" => Some(ArtworkKind::Still),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArtworkKind::Poster => "poster",
+            ArtworkKind::Backdrop => "backdrop",
+            ArtworkKind::Still => "still",
+        }
+    }
+}
+
+/// Episode details from an episode NFO. Season/episode numbers override the ones parsed from the path.
+#[derive(Debug, Clone, Default)]
+pub struct EpisodeNfo {
+    pub season_number: Option<i32>,
+    pub episode_number: Option<i32>,
+    pub title: Option<String>,
+    pub plot: Option<String>,
+    pub still_url: Option<String>,
+}
+
+/// Folder holding `tvshow.nfo` and series artwork: the first folder below the library root,
+/// or the library root itself for files stored directly in it.
+pub fn series_dir(video: &Path, library_path: &str) -> Option<PathBuf> {
+    let relative = video.strip_prefix(library_path).ok()?;
+    let mut components = relative.components();
+    let first = components.next()?;
+    if components.next().is_some() {
+        Some(Path::new(library_path).join(first))
+    } else {
+        Some(PathBuf::from(library_path))
+    }
+}
+
+/// URL the API serves a local artwork file from (see `get_media_artwork`)
+pub fn artwork_url(media_id: i64, kind: ArtworkKind) -> String {
+    format!("/api/v1/media/{}/artwork/{}", media_id, kind.as_str())
+}
+
+/// The folder whose `movie.nfo`, `poster.jpg`, ... belong to the movie at `video`: a disc rip's
+/// own root, or the folder of a file that is the only movie in it. Other versions, parts and
+/// extras of the same movie don't count. The library folder itself is never one movie's.
+pub fn movie_dir(video: &Path, library_path: &str) -> Option<PathBuf> {
+    if video.is_dir() {
+        return Some(video.to_path_buf());
+    }
+    let dir = video.parent()?;
+    if dir == Path::new(library_path) {
+        return None;
+    }
+    let key = movie_key(video)?;
+    let alone = std::fs::read_dir(dir).ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.extension().is_some_and(|e| {
+            let ext = e.to_string_lossy().to_lowercase();
+            VIDEO_EXTENSIONS.contains(&ext.as_str()) || ext == "iso"
+        }))
+        .filter(|p| extras::detect(p, library_path).is_none())
+        .all(|p| movie_key(&p).as_deref() == Some(key.as_str()));
+    alone.then(|| dir.to_path_buf())
+}
+
+/// The title a movie file shares with its other versions and parts
+fn movie_key(video: &Path) -> Option<String> {
+    let stem = video.file_stem()?.to_string_lossy();
+    let title = media_parts::part_info(&stem).map(|p| p.title).unwrap_or_else(|| stem.to_string());
+    Some(media_versions::version_info(&title).key)
+}
+
+/// Metadata from a movie's `<name>.nfo`, or `movie.nfo` in its own folder
+pub fn movie_nfo(video: &Path, library_path: &str) -> Option<NormalizedMetadata> {
+    let stem = video.file_stem()?.to_string_lossy();
+    let named = video.parent().map(|dir| dir.join(format!("{}.nfo", stem)));
+    let shared = movie_dir(video, library_path).map(|dir| dir.join("movie.nfo"));
+    named.into_iter().chain(shared).find_map(|p| read_metadata(&p, "movie"))
+}
+
+/// Metadata from a series folder's `tvshow.nfo`
+pub fn series_nfo(series_dir: &Path) -> Option<NormalizedMetadata> {
+    read_metadata(&series_dir.join("tvshow.nfo"), "series")
+}
+
+/// Episode details from an episode's `<name>.nfo`; one entry per `<episodedetails>` block,
+/// so multi-episode files yield several.
+pub fn episode_nfos(video: &Path) -> Vec<EpisodeNfo> {
+    let Some(content) = read_text(&video.with_extension("nfo")) else { return Vec::new() };
+    let body = match content.find("?>") {
+        Some(end) if content.starts_with("<?xml") => &content[end + 2..],
+        _ => content.as_str(),
+    };
+
+    let list: NfoEpisodeList = match quick_xml::de::from_str(&format!("<episodes>{}</episodes>", body)) {
+        Ok(list) => list,
+        Err(e) => {
+            tracing::warn!("Ignoring unreadable NFO {}: {}", video.with_extension("nfo").display(), e);
+            return Vec::new();
+        }
+    };
+
+    list.episodedetails.into_iter().map(|doc| EpisodeNfo {
+        season_number: doc.season.as_deref().and_then(|s| s.trim().parse().ok()),
+        episode_number: doc.episode.as_deref().and_then(|s| s.trim().parse().ok()),
+        title: non_empty(doc.title.as_deref()),
+        plot: non_empty(doc.plot.as_deref()).or_else(|| non_empty(doc.outline.as_deref())),
+        still_url: doc.thumb.iter().map(|t| t.url.trim()).find(|u| is_remote(u)).map(str::to_string),
+    }).collect()
+}
+
+/// Pick the NFO entry for one episode row. A file shared by several episode rows only matches
+/// by episode number; a single-episode file takes its only entry, which may renumber it.
+pub fn select_episode(nfos: Vec<EpisodeNfo>, episode_number: Option<i32>, shared_file: bool) -> Option<EpisodeNfo> {
+    if !shared_file && nfos.len() == 1 {
+        return nfos.into_iter().next();
+    }
+    nfos.into_iter().find(|e| e.episode_number.is_some() && e.episode_number == episode_number)
+}
+
+/// Find a sidecar image. Posters and backdrops come from `series_dir` for episodes; for movies
+/// from `<name>-poster.jpg` beside the file, then `poster.jpg` in its own folder (see
+/// `movie_dir`). Stills are always per file.
+pub fn find_artwork(video: &Path, library_path: &str, series_dir: Option<&Path>, kind: ArtworkKind) -> Option<PathBuf> {
+    if let (Some(dir), ArtworkKind::Poster | ArtworkKind::Backdrop) = (series_dir, kind) {
+        return find_series_artwork(dir, kind);
+    }
+
+    let stem = video.file_stem()?.to_string_lossy().to_lowercase();
+    let dir = video.parent()?;
+    let (named, shared): (String, &[&str]) = match kind {
+        ArtworkKind::Poster => (format!("{}-poster", stem), &["poster", "folder", "cover"]),
+        ArtworkKind::Backdrop => (format!("{}-fanart", stem), &["fanart", "backdrop"]),
+        ArtworkKind::Still => return find_still(video),
+    };
+    find_image(dir, &[&named]).or_else(|| find_image(&movie_dir(video, library_path)?, shared))
+}
+
+/// Find an episode's `<name>-thumb.jpg`
+pub fn find_still(video: &Path) -> Option<PathBuf> {
+    let stem = video.file_stem()?.to_string_lossy().to_lowercase();
+    find_image(video.parent()?, &[&format!("{}-thumb", stem)])
+}
+
+/// Find a series-level poster or backdrop in the series folder
+pub fn find_series_artwork(series_dir: &Path, kind: ArtworkKind) -> Option<PathBuf> {
+    match kind {
+        ArtworkKind::Poster => find_image(series_dir, &["poster", "folder"]),
+        ArtworkKind::Backdrop => find_image(series_dir, &["fanart", "backdrop"]),
+        ArtworkKind::Still => None,
+    }
+}
+
+/// First image in `dir` whose name (case-insensitive, without extension) matches, in `names` order
+fn find_image(dir: &Path, names: &[&str]) -> Option<PathBuf> {
+    let files: Vec<PathBuf> = std::fs::read_dir(dir).ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .collect();
+
+    names.iter().find_map(|name| {
+        files.iter().find(|f| {
+            let file_stem = f.file_stem().map(|s| s.to_string_lossy().to_lowercase());
+            let ext = f.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+            file_stem.as_deref() == Some(*name) && IMAGE_EXTENSIONS.contains(&ext.as_str())
+        }).cloned()
+    })
+}
+
+/// Replace remote artwork with local sidecar images where they exist
+pub fn apply_local_artwork(meta: &mut NormalizedMetadata, media_id: i64, video: &Path, library_path: &str, series_dir: Option<&Path>) {
+    if find_artwork(video, library_path, series_dir, ArtworkKind::Poster).is_some() {
+        meta.poster_url = Some(artwork_url(media_id, ArtworkKind::Poster));
+    }
+    if find_artwork(video, library_path, series_dir, ArtworkKind::Backdrop).is_some() {
+        meta.backdrop_url = Some(artwork_url(media_id, ArtworkKind::Backdrop));
+    }
+}
+
+fn read_text(path: &Path) -> Option<String> {
+    let bytes = std::fs::read(path).ok()?;
+    Some(String::from_utf8_lossy(&bytes).trim_start_matches('\u{feff}').trim().to_string())
+}
+
+fn read_document(path: &Path) -> Option<NfoDocument> {
+    let content = read_text(path)?;
+
+    match quick_xml::de::from_str::<NfoDocument>(&content) {
+        Ok(doc) => Some(doc),
+        Err(e) => {
+            // Link-only NFOs still identify the item
+            let ids = ids_from_text(&content);
+            if ids.is_empty() {
+                tracing::warn!("Ignoring unreadable NFO {}: {}", path.display(), e);
+                return None;
+            }
+            Some(NfoDocument {
+                imdbid: ids.get("imdb").and_then(|v| v.as_str()).map(str::to_string),
+                tmdbid: ids.get("tmdb").map(|v| v.to_string()),
+                tvdbid: ids.get("tvdb").map(|v| v.to_string()),
+                ..Default::default()
+            })
+        }
+    }
+}
+
+fn read_metadata(path: &Path, media_type: &str) -> Option<NormalizedMetadata> {
+    let doc = read_document(path)?;
+    let provider_ids = provider_ids(&doc, media_type);
+
+    let year = non_empty(doc.year.as_deref())
+        .or_else(|| doc.premiered.as_deref().or(doc.aired.as_deref()).and_then(|d| d.get(..4)).map(str::to_string))
+        .filter(|y| y.chars().all(|c| c.is_ascii_digit()));
+
+    let rating = doc.ratings.as_ref()
+        .and_then(|r| r.rating.iter().find(|r| r.default.as_deref() == Some("true")).or(r.rating.first()))
+        .and_then(|r| r.value.as_deref())
+        .or(doc.rating.as_deref())
+        .and_then(|v| v.trim().parse::<f32>().ok());
+
+    let genres: Vec<String> = doc.genre.iter()
+        .flat_map(|g| g.split(" / "))
+        .map(|g| g.trim().to_string())
+        .filter(|g| !g.is_empty())
+        .collect();
+
+    let poster_url = doc.thumb.iter()
+        .find(|t| t.aspect.as_deref() == Some("poster") && is_remote(t.url.trim()))
+        .or_else(|| doc.thumb.iter().find(|t| t.aspect.is_none() && is_remote(t.url.trim())))
+        .map(|t| t.url.trim().to_string());
+    let backdrop_url = doc.fanart.as_ref()
+        .and_then(|f| f.thumb.iter().find(|t| is_remote(t.url.trim())))
+        .or_else(|| doc.thumb.iter().find(|t| matches!(t.aspect.as_deref(), Some("fanart" | "landscape")) && is_remote(t.url.trim())))
+        .map(|t| t.url.trim().to_string());
+
+    Some(NormalizedMetadata {
+        title: non_empty(doc.title.as_deref()).unwrap_or_default(),
+        original_title: non_empty(doc.originaltitle.as_deref()),
+        year,
+        plot: non_empty(doc.plot.as_deref()).or_else(|| non_empty(doc.outline.as_deref())),
+        poster_url,
+        backdrop_url,
+        media_type: Some(media_type.to_string()),
+        provider_ids: Some(Value::Object(provider_ids)),
+        genres: Some(genres).filter(|g| !g.is_empty()),
+        runtime: doc.runtime.as_deref().and_then(|r| r.trim().parse().ok()).filter(|r: &i32| *r > 0),
+        rating,
+    })
+}
+
+/// Collect TMDB/IMDb/TVDB IDs from `<uniqueid>` and the legacy ID tags
+fn provider_ids(doc: &NfoDocument, media_type: &str) -> Map<String, Value> {
+    let mut ids = Map::new();
+    let mut insert = |provider: &str, value: &str| {
+        let value = value.trim();
+        if value.is_empty() || ids.contains_key(provider) { return; }
+        let v = match value.parse::<i64>() {
+            Ok(n) if provider != "imdb" => json!(n),
+            _ => json!(value),
+        };
+        ids.insert(provider.to_string(), v);
+    };
+
+    for uid in &doc.uniqueid {
+        match uid.kind.as_deref().map(str::to_lowercase).as_deref() {
+            Some("tmdb" | "themoviedb") => insert("tmdb", &uid.value),
+            Some("imdb") => insert("imdb", &uid.value),
+            Some("tvdb" | "thetvdb") => insert("tvdb", &uid.value),
+            _ => {}
+        }
+    }
+    if let Some(v) = &doc.tmdbid { insert("tmdb", v); }
+    if let Some(v) = &doc.imdbid { insert("imdb", v); }
+    if let Some(v) = &doc.tvdbid { insert("tvdb", v); }
+    // Old scrapers wrote the IMDb ID for movies and the TVDB ID for shows into <id>
+    if let Some(v) = &doc.id {
+        if v.trim().starts_with("tt") {
+            insert("imdb", v);
+        } else if media_type == "series" {
+            insert("tvdb", v);
+        }
+    }
+    ids
+}
+
+fn ids_from_text(text: &str) -> Map<String, Value> {
+    let mut ids = Map::new();
+    if let Some(c) = RE_IMDB_ID.captures(text) {
+        ids.insert("imdb".into(), json!(c[1].to_string()));
+    }
+    if let Some(id) = RE_TMDB_URL.captures(text).and_then(|c| c[1].parse::<i64>().ok()) {
+        ids.insert("tmdb".into(), json!(id));
+    }
+    if let Some(id) = RE_TVDB_URL.captures(text).and_then(|c| c[1].parse::<i64>().ok()) {
+        ids.insert("tvdb".into(), json!(id));
+    }
+    ids
+}
+
+fn non_empty(s: Option<&str>) -> Option<String> {
+    s.map(str::trim).filter(|s| !s.is_empty()).map(str::to_string)
+}
+
+fn is_remote(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOVIE_NFO: &str = "<movie><title>From the NFO</title></movie>";
+
+    fn library(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vortex-nfo-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(path: PathBuf, content: &str) -> PathBuf {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn folder_wide_files_are_not_read_from_the_library_folder() {
+        let root = library("flat");
+        let lib = root.to_string_lossy().to_string();
+        let heat = write(root.join("Heat (1995).mkv"), "");
+        write(root.join("Alien (1979).mkv"), "");
+        write(root.join("movie.nfo"), MOVIE_NFO);
+        write(root.join("folder.jpg"), "");
+
+        assert_eq!(movie_dir(&heat, &lib), None);
+        assert!(movie_nfo(&heat, &lib).is_none());
+        assert_eq!(find_artwork(&heat, &lib, None, ArtworkKind::Poster), None);
+
+        // The movie's own names still count
+        let poster = write(root.join("Heat (1995)-poster.jpg"), "");
+        write(root.join("Heat (1995).nfo"), MOVIE_NFO);
+        assert_eq!(find_artwork(&heat, &lib, None, ArtworkKind::Poster), Some(poster));
+        assert_eq!(movie_nfo(&heat, &lib).unwrap().title, "From the NFO");
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn folder_wide_files_need_a_folder_with_one_movie() {
+        let root = library("folders");
+        let lib = root.to_string_lossy().to_string();
+        // Versions, parts and extras of the same movie share its folder
+        let heat = write(root.join("Heat (1995)/Heat (1995) - 1080p.mkv"), "");
+        write(root.join("Heat (1995)/Heat (1995) - 2160p.mkv"), "");
+        write(root.join("Heat (1995)/Heat (1995)-trailer.mkv"), "");
+        write(root.join("Heat (1995)/movie.nfo"), MOVIE_NFO);
+        let poster = write(root.join("Heat (1995)/poster.jpg"), "");
+        assert_eq!(movie_dir(&heat, &lib), Some(root.join("Heat (1995)")));
+        assert_eq!(movie_nfo(&heat, &lib).unwrap().title, "From the NFO");
+        assert_eq!(find_artwork(&heat, &lib, None, ArtworkKind::Poster), Some(poster));
+
+        let alien = write(root.join("Collection/Alien (1979).mkv"), "");
+        write(root.join("Collection/Aliens (1986).mkv"), "");
+        write(root.join("Collection/movie.nfo"), MOVIE_NFO);
+        assert_eq!(movie_dir(&alien, &lib), None);
+        assert!(movie_nfo(&alien, &lib).is_none());
+
+        // A disc rip's own root is its folder
+        let disc = root.join("Ran (1985)");
+        write(disc.join("VIDEO_TS/VIDEO_TS.IFO"), "");
+        assert_eq!(movie_dir(&disc, &lib), Some(disc.clone()));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
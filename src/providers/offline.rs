@@ -0,0 +1,373 @@
+//! Offline metadata provider backed by a local catalog directory, for air-gapped installs
+//! and hermetic tests. Selected with `metadata_provider = "offline"`; the directory comes
+//! from the `offline_catalog_path` setting (default `catalog`).
+//!
+//! Every `.json`, `.yaml` or `.yml` file in the directory holds one entry or a list of entries:
+//!
+//! ```yaml
+//! id: "breaking-bad"
+//! media_type: series          # or "movie"
+//! title: Breaking Bad
+//! year: "2008"
+//! plot: ...
+//! poster_url: https://...
+//! genres: [Drama, Crime]
+//! aliases: [BB]               # extra search terms
+//! provider_ids: {tmdb: 1396}  # cross references for provider chains
+//! translations:
+//!   de: {title: ..., plot: ...}
+//! seasons:
+//!   - season_number: 1
+//!     episodes:
+//!       - {episode_number: 1, name: Pilot, overview: ..., air_date: "2008-01-20"}
+//! ```
+
+use crate::providers::traits::MetadataProvider;
+use crate::models::metadata::{NormalizedMetadata, EpisodeMetadata, MetadataLocale};
+use crate::error::AppError;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+const DEFAULT_CATALOG_PATH: &str = "catalog";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CatalogEntry {
+    pub id: String,
+    pub media_type: Option<String>,
+    pub title: String,
+    pub original_title: Option<String>,
+    pub year: Option<String>,
+    pub plot: Option<String>,
+    pub poster_url: Option<String>,
+    pub backdrop_url: Option<String>,
+    #[serde(default)]
+    pub genres: Vec<String>,
+    pub runtime: Option<i32>,
+    pub rating: Option<f32>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    pub provider_ids: Option<serde_json::Map<String, Value>>,
+    #[serde(default)]
+    pub translations: HashMap<String, CatalogTranslation>,
+    #[serde(default)]
+    pub seasons: Vec<CatalogSeason>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CatalogTranslation {
+    pub title: Option<String>,
+    pub plot: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CatalogSeason {
+    pub season_number: i32,
+    #[serde(default)]
+    pub episodes: Vec<CatalogEpisode>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CatalogEpisode {
+    pub episode_number: i32,
+    pub name: String,
+    #[serde(default)]
+    pub overview: String,
+    pub still_url: Option<String>,
+    pub air_date: Option<String>,
+}
+
+/// A file may hold a single entry or a list of them
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CatalogFile {
+    Many(Vec<CatalogEntry>),
+    One(Box<CatalogEntry>),
+}
+
+/// Parsed catalog plus the (file count, newest mtime) it was loaded from
+struct CachedCatalog {
+    path: PathBuf,
+    fingerprint: (usize, Option<SystemTime>),
+    entries: Arc<Vec<CatalogEntry>>,
+}
+
+// get_provider builds a new provider per lookup, so keep the parsed catalog around
+// and only re-read it when files are added, removed or modified.
+static CATALOG_CACHE: Lazy<Mutex<Option<CachedCatalog>>> = Lazy::new(|| Mutex::new(None));
+
+pub struct OfflineProvider {
+    entries: Arc<Vec<CatalogEntry>>,
+}
+
+impl OfflineProvider {
+    /// Load (or reuse) the catalog at `path`
+    pub fn new(path: &Path) -> Result<Self, AppError> {
+        let files = catalog_files(path)?;
+        let fingerprint = (
+            files.len(),
+            files.iter().filter_map(|f| f.metadata().and_then(|m| m.modified()).ok()).max(),
+        );
+
+        let mut cache = CATALOG_CACHE.lock().map_err(|_| AppError::Internal("Catalog cache poisoned".into()))?;
+        if let Some(cached) = cache.as_ref() {
+            if cached.path == path && cached.fingerprint == fingerprint {
+                return Ok(Self { entries: cached.entries.clone() });
+            }
+        }
+
+        let mut entries = Vec::new();
+        for file in &files {
+            entries.extend(parse_file(file)?);
+        }
+        tracing::info!("Loaded {} offline catalog entries from {}", entries.len(), path.display());
+
+        let entries = Arc::new(entries);
+        *cache = Some(CachedCatalog {
+            path: path.to_path_buf(),
+            fingerprint,
+            entries: entries.clone(),
+        });
+        Ok(Self { entries })
+    }
+
+    /// Fetch the catalog directory from database settings
+    pub async fn fetch_catalog_path(pool: &sqlx::SqlitePool) -> Result<PathBuf, AppError> {
+        let path: Option<String> = sqlx::query_scalar("SELECT value FROM settings WHERE key = 'offline_catalog_path'")
+            .fetch_optional(pool)
+            .await?;
+        Ok(PathBuf::from(path.filter(|p| !p.trim().is_empty()).unwrap_or_else(|| DEFAULT_CATALOG_PATH.to_string())))
+    }
+
+    fn find(&self, id: &str) -> Option<&CatalogEntry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+
+    fn to_metadata(&self, entry: &CatalogEntry, locale: &MetadataLocale) -> NormalizedMetadata {
+        let translation = |lang: &str| {
+            let base = lang.split('-').next().unwrap_or(lang).to_lowercase();
+            entry.translations.get(lang).or_else(|| entry.translations.get(&base))
+        };
+        let preferred = translation(&locale.language_tag());
+        let fallback = locale.fallback().and_then(translation);
+
+        let mut ids = entry.provider_ids.clone().unwrap_or_default();
+        ids.insert("offline".to_string(), json!(entry.id));
+
+        NormalizedMetadata {
+            title: preferred.and_then(|t| t.title.clone())
+                .or_else(|| fallback.and_then(|t| t.title.clone()))
+                .unwrap_or_else(|| entry.title.clone()),
+            original_title: entry.original_title.clone().or_else(|| Some(entry.title.clone())),
+            year: entry.year.clone(),
+            plot: preferred.and_then(|t| t.plot.clone())
+                .or_else(|| entry.plot.clone())
+                .or_else(|| fallback.and_then(|t| t.plot.clone())),
+            poster_url: entry.poster_url.clone(),
+            backdrop_url: entry.backdrop_url.clone(),
+            media_type: Some(entry.media_type.clone().unwrap_or_else(|| "movie".to_string())),
+            provider_ids: Some(Value::Object(ids)),
+            genres: Some(entry.genres.clone()).filter(|g| !g.is_empty()),
+            runtime: entry.runtime,
+            rating: entry.rating,
+        }
+    }
+}
+
+fn catalog_files(path: &Path) -> Result<Vec<PathBuf>, AppError> {
+    let read_dir = std::fs::read_dir(path)
+        .map_err(|e| AppError::BadRequest(format!("Offline catalog not readable at {}: {}", path.display(), e)))?;
+
+    let mut files: Vec<PathBuf> = read_dir
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            let ext = p.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+            p.is_file() && ["json", "yaml", "yml"].contains(&ext.as_str())
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn parse_file(file: &Path) -> Result<Vec<CatalogEntry>, AppError> {
+    let content = std::fs::read_to_string(file)
+        .map_err(|e| AppError::Internal(format!("Failed to read {}: {}", file.display(), e)))?;
+
+    let is_json = file.extension().and_then(|e| e.to_str()).map(|e| e.eq_ignore_ascii_case("json")).unwrap_or(false);
+    let parsed: CatalogFile = if is_json {
+        serde_json::from_str(&content).map_err(|e| AppError::Internal(format!("Invalid catalog file {}: {}", file.display(), e)))?
+    } else {
+        serde_yaml::from_str(&content).map_err(|e| AppError::Internal(format!("Invalid catalog file {}: {}", file.display(), e)))?
+    };
+
+    Ok(match parsed {
+        CatalogFile::Many(entries) => entries,
+        CatalogFile::One(entry) => vec![*entry],
+    })
+}
+
+/// Lowercase, strip punctuation/separators and collapse whitespace ("The.Matrix_1999" -> "the matrix 1999")
+fn normalize(s: &str) -> String {
+    s.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Pull a plausible release year (1900-2099) out of a search string
+fn extract_year(query: &str) -> Option<String> {
+    query.split_whitespace()
+        .find(|w| w.len() == 4 && (w.starts_with("19") || w.starts_with("20")) && w.chars().all(|c| c.is_ascii_digit()))
+        .map(|w| w.to_string())
+}
+
+/// Score how well an entry matches a normalized query; 0 means no match
+fn match_score(entry: &CatalogEntry, query: &str, year: Option<&str>) -> u32 {
+    let query_without_year = match year {
+        Some(y) => normalize(&query.replace(y, " ")),
+        None => query.to_string(),
+    };
+
+    let names = std::iter::once(&entry.title)
+        .chain(entry.original_title.iter())
+        .chain(entry.aliases.iter())
+        .chain(entry.translations.values().filter_map(|t| t.title.as_ref()))
+        .map(|n| normalize(n))
+        .filter(|n| !n.is_empty());
+
+    let mut best = 0;
+    for name in names {
+        let score = if name == query_without_year {
+            100
+        } else if format!(" {} ", query).contains(&format!(" {} ", name)) {
+            // Title appears as whole words in a release-style name ("the matrix 1999 1080p bluray")
+            60 + name.len().min(30) as u32
+        } else if name.contains(&query_without_year) && !query_without_year.is_empty() {
+            40
+        } else {
+            0
+        };
+        best = best.max(score);
+    }
+
+    if best > 0 {
+        if let (Some(y), Some(entry_year)) = (year, entry.year.as_deref()) {
+            if entry_year.starts_with(y) { best += 50; } else { best = best.saturating_sub(30).max(1); }
+        }
+    }
+    best
+}
+
+#[async_trait]
+impl MetadataProvider for OfflineProvider {
+    async fn search(&self, query: &str, locale: &MetadataLocale) -> Result<Vec<NormalizedMetadata>, AppError> {
+        let normalized = normalize(query);
+        let year = extract_year(&normalized);
+
+        let mut scored: Vec<(u32, &CatalogEntry)> = self.entries.iter()
+            .map(|e| (match_score(e, &normalized, year.as_deref()), e))
+            .filter(|(score, _)| *score > 0)
+            .collect();
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+
+        Ok(scored.into_iter().map(|(_, e)| self.to_metadata(e, locale)).collect())
+    }
+
+    async fn get_details(&self, id: &str, media_type: Option<&str>, locale: &MetadataLocale) -> Result<NormalizedMetadata, AppError> {
+        let entry = self.find(id)
+            .filter(|e| match (media_type, e.media_type.as_deref()) {
+                (Some(wanted), Some(actual)) => wanted == actual || (wanted == "tv" && actual == "series"),
+                _ => true,
+            })
+            .ok_or_else(|| AppError::NotFound(format!("Offline catalog has no entry '{}'", id)))?;
+        Ok(self.to_metadata(entry, locale))
+    }
+
+    async fn get_season_episodes(&self, series_id: &str, season_number: i32, _locale: &MetadataLocale) -> Result<Vec<EpisodeMetadata>, AppError> {
+        let entry = self.find(series_id)
+            .ok_or_else(|| AppError::NotFound(format!("Offline catalog has no entry '{}'", series_id)))?;
+
+        Ok(entry.seasons.iter()
+            .filter(|s| s.season_number == season_number)
+            .flat_map(|s| s.episodes.iter())
+            .map(|ep| EpisodeMetadata {
+                id: format!("{}:{}:{}", entry.id, season_number, ep.episode_number),
+                episode_number: ep.episode_number,
+                season_number,
+                name: ep.name.clone(),
+                overview: ep.overview.clone(),
+                still_path: ep.still_url.clone(),
+                air_date: ep.air_date.clone(),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> OfflineProvider {
+        OfflineProvider::new(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/offline_catalog")).unwrap()
+    }
+
+    fn locale(language: &str) -> MetadataLocale {
+        MetadataLocale { language: language.to_string(), ..MetadataLocale::default() }
+    }
+
+    #[tokio::test]
+    async fn search_matches_release_names_and_prefers_the_year() {
+        let provider = catalog();
+        let results = provider.search("The.Matrix.1999.1080p.BluRay", &locale("en-US")).await.unwrap();
+        assert_eq!(results[0].title, "The Matrix");
+        assert_eq!(results[0].provider_ids.as_ref().unwrap()["offline"], "the-matrix");
+
+        let results = provider.search("The Matrix 2021", &locale("en-US")).await.unwrap();
+        assert_eq!(results[0].title, "The Matrix Resurrections");
+
+        let results = provider.search("BB", &locale("en-US")).await.unwrap();
+        assert_eq!(results[0].title, "Breaking Bad");
+        assert!(provider.search("Nothing Like It", &locale("en-US")).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_details_translates_and_checks_the_type() {
+        let provider = catalog();
+        let series = provider.get_details("breaking-bad", Some("tv"), &locale("de-DE")).await.unwrap();
+        assert_eq!(series.title, "Breaking Bad (DE)");
+        assert_eq!(series.original_title.as_deref(), Some("Breaking Bad"));
+        assert_eq!(series.media_type.as_deref(), Some("series"));
+        assert_eq!(series.provider_ids.as_ref().unwrap()["tmdb"], 1396);
+
+        let movie = provider.get_details("the-matrix", None, &locale("en-US")).await.unwrap();
+        assert_eq!(movie.runtime, Some(136));
+        assert_eq!(movie.genres, Some(vec!["Action".to_string(), "Science Fiction".to_string()]));
+
+        assert!(matches!(provider.get_details("the-matrix", Some("tv"), &locale("en-US")).await, Err(AppError::NotFound(_))));
+        assert!(matches!(provider.get_details("missing", None, &locale("en-US")).await, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn get_season_episodes_returns_one_season() {
+        let provider = catalog();
+        let episodes = provider.get_season_episodes("breaking-bad", 1, &locale("en-US")).await.unwrap();
+        assert_eq!(episodes.len(), 2);
+        assert_eq!(episodes[0].name, "Pilot");
+        assert_eq!(episodes[1].episode_number, 2);
+        assert_eq!(episodes[1].air_date.as_deref(), Some("2008-01-27"));
+        assert_eq!(episodes[0].id, "breaking-bad:1:1");
+
+        assert_eq!(provider.get_season_episodes("breaking-bad", 2, &locale("en-US")).await.unwrap().len(), 1);
+        assert!(provider.get_season_episodes("breaking-bad", 3, &locale("en-US")).await.unwrap().is_empty());
+        assert!(provider.get_season_episodes("missing", 1, &locale("en-US")).await.is_err());
+    }
+}
@@ -0,0 +1,233 @@
+//! OpenSubtitles (REST API v1) subtitle provider. Any server speaking the same API can be used
+//! by pointing `opensubtitles_url` at it.
+//!
+//! Settings: `opensubtitles_api_key` (required), `opensubtitles_url`, and optionally
+//! `opensubtitles_username`/`opensubtitles_password`, which raise the daily download quota.
+
+use crate::providers::traits::SubtitleProvider;
+use crate::providers::rate_limit::{RateLimiter, RetryPolicy, send_with_retry};
+use crate::models::metadata::{SubtitleSearch, SubtitleCandidate, SubtitleFile};
+use crate::error::AppError;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use once_cell::sync::Lazy;
+
+const DEFAULT_URL: &str = "https://api.opensubtitles.com/api/v1";
+
+/// The only languages OpenSubtitles tells apart by region
+const REGIONAL_LANGUAGES: &[&str] = &["pt-br", "pt-pt", "zh-cn", "zh-tw"];
+
+// OpenSubtitles allows 5 requests per second per IP
+static OPENSUBTITLES_LIMITER: Lazy<RateLimiter> = Lazy::new(|| RateLimiter::new(5, 4.0));
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    #[serde(default)]
+    data: Vec<SearchResult>,
+}
+
+#[derive(Deserialize)]
+struct SearchResult {
+    attributes: SearchAttributes,
+}
+
+#[derive(Deserialize)]
+struct SearchAttributes {
+    language: Option<String>,
+    download_count: Option<i64>,
+    #[serde(default)]
+    hearing_impaired: bool,
+    #[serde(default)]
+    foreign_parts_only: bool,
+    release: Option<String>,
+    #[serde(default)]
+    moviehash_match: bool,
+    #[serde(default)]
+    files: Vec<SearchFile>,
+}
+
+#[derive(Deserialize)]
+struct SearchFile {
+    file_id: i64,
+    file_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DownloadResponse {
+    link: String,
+    file_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LoginResponse {
+    token: String,
+}
+
+pub struct OpenSubtitlesProvider {
+    base_url: String,
+    api_key: String,
+    credentials: Option<(String, String)>,
+    client: reqwest::Client,
+}
+
+impl OpenSubtitlesProvider {
+    pub fn new(base_url: String, api_key: String, credentials: Option<(String, String)>) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            credentials,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Build a provider from database settings
+    pub async fn from_settings(pool: &sqlx::SqlitePool) -> Result<Self, AppError> {
+        let setting = |key: &'static str| crate::core::metadata::get_setting(pool, key);
+        let api_key = setting("opensubtitles_api_key").await
+            .ok_or_else(|| AppError::BadRequest("OpenSubtitles API Key not found in settings".to_string()))?;
+        let base_url = setting("opensubtitles_url").await.unwrap_or_else(|| DEFAULT_URL.to_string());
+        let credentials = match (setting("opensubtitles_username").await, setting("opensubtitles_password").await) {
+            (Some(user), Some(password)) => Some((user, password)),
+            _ => None,
+        };
+        Ok(Self::new(base_url, api_key, credentials))
+    }
+
+    fn request(&self, method: reqwest::Method, endpoint: &str) -> reqwest::RequestBuilder {
+        self.client.request(method, format!("{}/{}", self.base_url, endpoint))
+            .header("Api-Key", &self.api_key)
+            .header(reqwest::header::USER_AGENT, concat!("VortexServer v", env!("CARGO_PKG_VERSION")))
+            .header(reqwest::header::ACCEPT, "application/json")
+    }
+
+    async fn login(&self) -> Result<Option<String>, AppError> {
+        let Some((username, password)) = &self.credentials else { return Ok(None) };
+        let body = json!({ "username": username, "password": password });
+        let resp = send_with_retry(&OPENSUBTITLES_LIMITER, &RetryPolicy::default(), || {
+            self.request(reqwest::Method::POST, "login").json(&body)
+        }).await?;
+        if !resp.status().is_success() {
+            return Err(AppError::External(format!("OpenSubtitles login failed: {}", resp.status())));
+        }
+        let login = resp.json::<LoginResponse>().await.map_err(|e| AppError::External(e.to_string()))?;
+        Ok(Some(login.token))
+    }
+}
+
+#[async_trait]
+impl SubtitleProvider for OpenSubtitlesProvider {
+    async fn search(&self, query: &SubtitleSearch) -> Result<Vec<SubtitleCandidate>, AppError> {
+        let mut params: Vec<(String, String)> = Vec::new();
+        if let Some(hash) = &query.file_hash {
+            params.push(("moviehash".to_string(), hash.clone()));
+        }
+        // An episode is found through its series' IDs
+        let episode = query.season_number.zip(query.episode_number);
+        let id_param = |name: &str| if episode.is_some() { format!("parent_{}", name) } else { name.to_string() };
+        if let Some(imdb) = query.provider_ids.get("imdb") {
+            params.push((id_param("imdb_id"), imdb.trim_start_matches("tt").to_string()));
+        }
+        if let Some(tmdb) = query.provider_ids.get("tmdb") {
+            params.push((id_param("tmdb_id"), tmdb.clone()));
+        }
+        // Without a hash or an ID, fall back to searching by name
+        if params.is_empty() {
+            let title = query.title.clone()
+                .ok_or_else(|| AppError::BadRequest("Nothing to search subtitles by".to_string()))?;
+            params.push(("query".to_string(), title));
+            if let Some(year) = query.year {
+                params.push(("year".to_string(), year.to_string()));
+            }
+        }
+        if let Some((season, episode)) = episode {
+            params.push(("season_number".to_string(), season.to_string()));
+            params.push(("episode_number".to_string(), episode.to_string()));
+        }
+        if !query.languages.is_empty() {
+            // In alphabetical order, as the API expects
+            let mut languages: Vec<String> = query.languages.iter().map(|l| api_language(l)).collect();
+            languages.sort();
+            languages.dedup();
+            params.push(("languages".to_string(), languages.join(",")));
+        }
+
+        let resp = send_with_retry(&OPENSUBTITLES_LIMITER, &RetryPolicy::default(), || {
+            self.request(reqwest::Method::GET, "subtitles").query(&params)
+        }).await?;
+        if !resp.status().is_success() {
+            return Err(AppError::External(format!("OpenSubtitles search failed: {}", resp.status())));
+        }
+        let resp = resp.json::<SearchResponse>().await.map_err(|e| AppError::External(e.to_string()))?;
+
+        Ok(resp.data.into_iter()
+            .flat_map(|result| {
+                let a = result.attributes;
+                a.files.into_iter().map(move |file| SubtitleCandidate {
+                    provider: "opensubtitles".to_string(),
+                    id: file.file_id.to_string(),
+                    language: a.language.clone(),
+                    release: a.release.clone().or(file.file_name.clone()),
+                    format: file.file_name.as_deref().and_then(extension).unwrap_or_else(|| "srt".to_string()),
+                    forced: a.foreign_parts_only,
+                    sdh: a.hearing_impaired,
+                    downloads: a.download_count,
+                    hash_match: a.moviehash_match,
+                })
+            })
+            .collect())
+    }
+
+    async fn download(&self, id: &str) -> Result<SubtitleFile, AppError> {
+        let file_id: i64 = id.parse().map_err(|_| AppError::BadRequest(format!("Invalid OpenSubtitles file ID: {}", id)))?;
+        let token = self.login().await?;
+        let body = json!({ "file_id": file_id });
+        let resp = send_with_retry(&OPENSUBTITLES_LIMITER, &RetryPolicy::default(), || {
+            let request = self.request(reqwest::Method::POST, "download").json(&body);
+            match &token {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            }
+        }).await?;
+        if !resp.status().is_success() {
+            return Err(AppError::External(format!("OpenSubtitles download failed: {}", resp.status())));
+        }
+        let download = resp.json::<DownloadResponse>().await.map_err(|e| AppError::External(e.to_string()))?;
+
+        // The link is a plain file URL, outside the API's rate limit
+        let file = self.client.get(&download.link).send().await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| AppError::External(format!("Could not fetch subtitle file: {}", e)))?;
+        let data = file.bytes().await.map_err(|e| AppError::External(e.to_string()))?.to_vec();
+        Ok(SubtitleFile {
+            data,
+            format: download.file_name.as_deref().and_then(extension).unwrap_or_else(|| "srt".to_string()),
+        })
+    }
+}
+
+fn extension(file_name: &str) -> Option<String> {
+    std::path::Path::new(file_name).extension().map(|e| e.to_string_lossy().to_lowercase())
+}
+
+/// A language code as OpenSubtitles takes it: lowercase, and without a region unless it is one
+/// of the few it tells apart ("en-US" -> "en", "pt-BR" -> "pt-br").
+fn api_language(code: &str) -> String {
+    let code = code.trim().to_lowercase().replace('_', "-");
+    if REGIONAL_LANGUAGES.contains(&code.as_str()) {
+        return code;
+    }
+    code.split('-').next().unwrap_or_default().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_language_keeps_only_the_regions_opensubtitles_knows() {
+        for (code, expected) in [("en-US", "en"), ("en", "en"), ("de_AT", "de"), ("pt-BR", "pt-br"), ("pt_PT", "pt-pt"), ("pt", "pt"), ("zh-TW", "zh-tw"), ("zh-HK", "zh"), ("TE", "te")] {
+            assert_eq!(api_language(code), expected, "{}", code);
+        }
+    }
+}
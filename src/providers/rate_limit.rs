@@ -0,0 +1,137 @@
+//! Shared HTTP helpers for metadata providers: a token-bucket rate limiter and
+//! a retry loop with exponential backoff that honors `Retry-After`.
+
+use std::time::{Duration, Instant};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use tokio::sync::Mutex;
+use crate::error::AppError;
+
+/// Token bucket limiter. Each request consumes one token; tokens refill continuously.
+/// Providers keep one instance in a `static` so every `Provider::new()` shares the same budget.
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(capacity: u32, refill_per_sec: f64) -> Self {
+        Self {
+            capacity: capacity as f64,
+            refill_per_sec,
+            state: Mutex::new(BucketState {
+                tokens: capacity as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Wait until a token is available and consume it.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                let now = Instant::now();
+                let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+                state.tokens = (state.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+                state.last_refill = now;
+
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - state.tokens) / self.refill_per_sec)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// How many times to retry a request and how long to wait between attempts.
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self.base_delay.saturating_mul(2u32.saturating_pow(attempt));
+        delay.min(self.max_delay)
+    }
+}
+
+/// 429 and transient 5xx responses are worth retrying; everything else is returned to the caller.
+fn is_retryable(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Parse `Retry-After` in its delta-seconds form. HTTP-date values fall back to backoff.
+fn retry_after(resp: &Response) -> Option<Duration> {
+    resp.headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+/// Send a request through the limiter, retrying 429/5xx and connection errors.
+/// `build` is called once per attempt since a `RequestBuilder` can't be reused.
+/// Non-retryable responses (including 404) are returned as-is so callers can inspect the status.
+pub async fn send_with_retry<F>(
+    limiter: &RateLimiter,
+    policy: &RetryPolicy,
+    build: F,
+) -> Result<Response, AppError>
+where
+    F: Fn() -> RequestBuilder,
+{
+    let mut attempt = 0;
+    loop {
+        limiter.acquire().await;
+
+        let (delay, error) = match build().send().await {
+            Ok(resp) if is_retryable(resp.status()) => {
+                let delay = retry_after(&resp)
+                    .map(|d| d.min(policy.max_delay))
+                    .unwrap_or_else(|| policy.backoff(attempt));
+                (delay, format!("Provider returned status {}", resp.status()))
+            }
+            Ok(resp) => return Ok(resp),
+            Err(e) if e.is_timeout() || e.is_connect() || e.is_request() => {
+                (policy.backoff(attempt), e.to_string())
+            }
+            Err(e) => return Err(AppError::External(e.to_string())),
+        };
+
+        if attempt >= policy.max_retries {
+            return Err(AppError::External(error));
+        }
+
+        tracing::warn!("{} - retrying in {:?} (attempt {}/{})", error, delay, attempt + 1, policy.max_retries);
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
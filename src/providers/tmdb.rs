@@ -1,9 +1,15 @@
 use crate::providers::traits::MetadataProvider;
-use crate::models::metadata::NormalizedMetadata;
+use crate::providers::rate_limit::{RateLimiter, RetryPolicy, send_with_retry};
+use crate::models::metadata::{NormalizedMetadata, EpisodeMetadata, MetadataLocale};
 use crate::dtos::tmdb::{TmdbResponse, TmdbFullResponse, TmdbSeasonResponse};
 use crate::error::AppError;
 use async_trait::async_trait;
 use serde_json::json;
+use once_cell::sync::Lazy;
+
+// TMDB allows roughly 50 requests/second per IP; stay under it with some headroom.
+// Shared across all TmdbProvider instances since get_provider builds a new one per call.
+static TMDB_LIMITER: Lazy<RateLimiter> = Lazy::new(|| RateLimiter::new(40, 40.0));
 
 pub struct TmdbProvider {
     api_key: String,
@@ -37,22 +43,43 @@ impl TmdbProvider {
     fn build_url(&self, endpoint: &str) -> String {
         format!("https://api.themoviedb.org/3/{}", endpoint)
     }
+
+    /// GET an endpoint with the API key, going through the shared rate limiter and retry policy.
+    async fn get(&self, endpoint: &str, params: &[(&str, &str)]) -> Result<reqwest::Response, AppError> {
+        let url = self.build_url(endpoint);
+        send_with_retry(&TMDB_LIMITER, &RetryPolicy::default(), || {
+            self.client.get(&url)
+                .query(&[("api_key", self.api_key.as_str())])
+                .query(params)
+        }).await
+    }
     
     // Helper to keep the existing logic accessible if needed, or used by trait impl
 }
 
+/// Error responses as errors. Only what is left of 429/5xx after retrying is transient; a
+/// rejected API key or bad request fails the same way every time, so it isn't retried.
+fn check_status(resp: reqwest::Response) -> Result<reqwest::Response, AppError> {
+    let status = resp.status();
+    match status {
+        s if s.is_success() => Ok(resp),
+        reqwest::StatusCode::UNAUTHORIZED => Err(AppError::BadRequest("TMDB rejected the API key (status 401)".to_string())),
+        s if s.is_client_error() && s != reqwest::StatusCode::TOO_MANY_REQUESTS => {
+            Err(AppError::BadRequest(format!("TMDB returned status {}", s)))
+        }
+        s => Err(AppError::External(format!("TMDB returned status {}", s))),
+    }
+}
+
 #[async_trait]
 impl MetadataProvider for TmdbProvider {
-    async fn search(&self, query: &str) -> Result<Vec<NormalizedMetadata>, AppError> {
+    async fn search(&self, query: &str, locale: &MetadataLocale) -> Result<Vec<NormalizedMetadata>, AppError> {
         if self.api_key.trim().is_empty() {
             return Err(AppError::BadRequest("TMDB API Key not set".into()));
         }
+        let language = locale.language_tag();
         // Let's use search/multi strictly to find everything.
-        let url = self.build_url("search/multi"); 
-        
-        let resp = self.client.get(&url)
-            .query(&[("api_key", self.api_key.as_str()), ("query", query)])
-            .send().await.map_err(|e| AppError::External(e.to_string()))?
+        let resp = check_status(self.get("search/multi", &[("query", query), ("language", &language)]).await?)?
             .json::<TmdbResponse>().await.map_err(|e| AppError::External(e.to_string()))?;
 
         let mut results = Vec::new();
@@ -65,6 +92,7 @@ impl MetadataProvider for TmdbProvider {
             
             results.push(NormalizedMetadata {
                 title: r.title.clone(),
+                original_title: r.original_title.clone(),
                 year: r.date.clone().map(|d| d.chars().take(4).collect()),
                 plot: r.overview.clone(),
                 poster_url: r.poster_path.clone().map(|p| format!("https://image.tmdb.org/t/p/w500{}", p)),
@@ -79,24 +107,23 @@ impl MetadataProvider for TmdbProvider {
         Ok(results)
     }
 
-    async fn get_details(&self, id: &str, media_type: Option<&str>) -> Result<NormalizedMetadata, AppError> {
+    async fn get_details(&self, id: &str, media_type: Option<&str>, locale: &MetadataLocale) -> Result<NormalizedMetadata, AppError> {
         if self.api_key.trim().is_empty() {
             return Err(AppError::BadRequest("TMDB API Key not set".into()));
         }
 
-
+        // Translations let us fill untranslated fields from the fallback language without a second request
+        let language = locale.language_tag();
+        let params = [("language", language.as_str()), ("append_to_response", "translations")];
 
         // If hint provided, try that specific one. If generic, try movie then tv?
         if let Some(t) = media_type {
             let endpoint = if t == "movie" { "movie" } else { "tv" };
-            let url = self.build_url(&format!("{}/{}", endpoint, id));
-            let resp = self.client.get(&url)
-                .query(&[("api_key", self.api_key.as_str())])
-                .send().await.map_err(|e| AppError::External(e.to_string()))?;
-            
-            if resp.status().is_success() {
+            let resp = self.get(&format!("{}/{}", endpoint, id), &params).await?;
+            if resp.status() != reqwest::StatusCode::NOT_FOUND {
+                let resp = check_status(resp)?;
                 let json: serde_json::Value = resp.json().await.map_err(|e| AppError::External(e.to_string()))?;
-                return self.parse_details(json, if endpoint == "movie" { "movie" } else { "series" }, id).await;
+                return self.parse_details(json, if endpoint == "movie" { "movie" } else { "series" }, id, locale).await;
             } else if t == "series" || t == "tv" {
                  // return error if explicit type failed
                  return Err(AppError::NotFound("TMDB ID not found for series".to_string()));
@@ -107,62 +134,88 @@ impl MetadataProvider for TmdbProvider {
         }
 
         // Fallback: Try Movie, then TV
-        let url = self.build_url(&format!("movie/{}", id));
-        let resp = self.client.get(&url)
-            .query(&[("api_key", self.api_key.as_str())])
-            .send().await.map_err(|e| AppError::External(e.to_string()))?;
-
-        if resp.status().is_success() {
+        let resp = self.get(&format!("movie/{}", id), &params).await?;
+        if resp.status() != reqwest::StatusCode::NOT_FOUND {
+            let resp = check_status(resp)?;
              let json: serde_json::Value = resp.json().await.map_err(|e| AppError::External(e.to_string()))?;
-             return self.parse_details(json, "movie", id).await;
+             return self.parse_details(json, "movie", id, locale).await;
         }
 
         // Try TV
-        let url_tv = self.build_url(&format!("tv/{}", id));
-        let resp_tv = self.client.get(&url_tv)
-            .query(&[("api_key", self.api_key.as_str())])
-            .send().await.map_err(|e| AppError::External(e.to_string()))?;
-            
-        if resp_tv.status().is_success() {
+        let resp_tv = self.get(&format!("tv/{}", id), &params).await?;
+        if resp_tv.status() != reqwest::StatusCode::NOT_FOUND {
+            let resp_tv = check_status(resp_tv)?;
             let json: serde_json::Value = resp_tv.json().await.map_err(|e| AppError::External(e.to_string()))?;
-            return self.parse_details(json, "series", id).await;
+            return self.parse_details(json, "series", id, locale).await;
         }
 
         Err(AppError::NotFound("TMDB ID not found".to_string()))
     }
 
-    async fn get_season_episodes(&self, series_id: &str, season_number: i32) -> Result<Vec<crate::models::metadata::EpisodeMetadata>, AppError> {
+    async fn get_season_episodes(&self, series_id: &str, season_number: i32, locale: &MetadataLocale) -> Result<Vec<EpisodeMetadata>, AppError> {
         if self.api_key.trim().is_empty() {
             return Err(AppError::BadRequest("TMDB API Key not set".into()));
         }
-        let url = self.build_url(&format!("tv/{}/season/{}", series_id, season_number));
-        let resp = self.client.get(&url)
-            .query(&[("api_key", self.api_key.as_str())])
-            .send().await.map_err(|e| AppError::External(e.to_string()))?
-            .json::<TmdbSeasonResponse>().await.map_err(|e| AppError::External(e.to_string()))?;
+        let endpoint = format!("tv/{}/season/{}", series_id, season_number);
+        let language = locale.language_tag();
+        let resp = self.get(&endpoint, &[("language", &language)]).await?;
+        // Seasons past the last one are a 404, not a provider failure
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        let mut resp = check_status(resp)?.json::<TmdbSeasonResponse>().await.map_err(|e| AppError::External(e.to_string()))?;
+
+        // Season responses have no translations block, so fetch again in the fallback language
+        // only when some episode is missing its name or overview.
+        if let Some(fallback) = locale.fallback() {
+            if resp.episodes.iter().any(|ep| ep.name.is_empty() || ep.overview.is_empty()) {
+                let fallback_resp = match self.get(&endpoint, &[("language", fallback)]).await {
+                    Ok(r) => r.json::<TmdbSeasonResponse>().await.ok(),
+                    Err(_) => None,
+                };
+                if let Some(fallback_resp) = fallback_resp {
+                    for ep in resp.episodes.iter_mut() {
+                        if let Some(fb) = fallback_resp.episodes.iter().find(|f| f.episode_number == ep.episode_number) {
+                            if ep.name.is_empty() { ep.name = fb.name.clone(); }
+                            if ep.overview.is_empty() { ep.overview = fb.overview.clone(); }
+                        }
+                    }
+                }
+            }
+        }
             
-        Ok(resp.episodes.into_iter().map(|ep| crate::models::metadata::EpisodeMetadata {
+        Ok(resp.episodes.into_iter().map(|ep| EpisodeMetadata {
             id: ep.episode_number.to_string(), // TMDB doesn't usually use separate IDs for episodes in this context easily, or we can use episode_number as ID for now within the season
             episode_number: ep.episode_number,
-            season_number: season_number,
+            season_number,
             name: ep.name,
             overview: ep.overview,
             still_path: ep.still_path.map(|p| format!("https://image.tmdb.org/t/p/w500{}", p)),
-            air_date: None, // DTO doesn't have it yet, we can add later if crucial
+            air_date: ep.air_date.filter(|d| !d.is_empty()),
         }).collect())
     }
 }
 
 impl TmdbProvider {
-    async fn parse_details(&self, json: serde_json::Value, media_type: &str, id: &str) -> Result<NormalizedMetadata, AppError> {
+    async fn parse_details(&self, json: serde_json::Value, media_type: &str, id: &str, locale: &MetadataLocale) -> Result<NormalizedMetadata, AppError> {
           let full_info: TmdbFullResponse = serde_json::from_value(json.clone()).unwrap_or(TmdbFullResponse {
             runtime: None,
             episode_run_time: None,
             genres: None,
         });
 
-        let title = json.get("title").or(json.get("name"))
-            .and_then(|v| v.as_str()).unwrap_or("Unknown").to_string();
+        let original_title = json.get("original_title").or(json.get("original_name"))
+            .and_then(|v| v.as_str()).map(|s| s.to_string());
+
+        // TMDB silently substitutes the original title and an empty overview when no translation
+        // exists for the requested language, so check the translations block explicitly.
+        let preferred = find_translation(&json, &locale.language_tag());
+        let fallback = locale.fallback().and_then(|lang| find_translation(&json, lang));
+
+        let title = translated_field(preferred, &["title", "name"])
+            .or_else(|| translated_field(fallback, &["title", "name"]))
+            .or_else(|| json.get("title").or(json.get("name")).and_then(|v| v.as_str()).map(|s| s.to_string()))
+            .unwrap_or_else(|| "Unknown".to_string());
         let year = json.get("release_date").or(json.get("first_air_date"))
             .and_then(|v| v.as_str()).map(|d| d.chars().take(4).collect());
         let poster = json.get("poster_path")
@@ -170,7 +223,8 @@ impl TmdbProvider {
         let backdrop = json.get("backdrop_path")
             .and_then(|v| v.as_str()).map(|p| format!("https://image.tmdb.org/t/p/original{}", p));
         let plot = json.get("overview")
-            .and_then(|v| v.as_str()).map(|s| s.to_string());
+            .and_then(|v| v.as_str()).filter(|s| !s.is_empty()).map(|s| s.to_string())
+            .or_else(|| translated_field(fallback, &["overview"]));
             
         let runtime = full_info.runtime.or_else(|| {
             full_info.episode_run_time.as_ref().and_then(|v| v.first().copied())
@@ -180,6 +234,7 @@ impl TmdbProvider {
 
         Ok(NormalizedMetadata {
             title,
+            original_title,
             year,
             plot,
             poster_url: poster,
@@ -192,3 +247,47 @@ impl TmdbProvider {
         })
     }
 }
+
+/// Find the entry in an `append_to_response=translations` block matching a language tag
+/// like "de-DE" (exact region match preferred) or "de".
+fn find_translation<'a>(json: &'a serde_json::Value, tag: &str) -> Option<&'a serde_json::Value> {
+    let mut parts = tag.split('-');
+    let lang = parts.next()?.to_lowercase();
+    let region = parts.next().map(|r| r.to_uppercase());
+
+    let translations = json.get("translations")?.get("translations")?.as_array()?;
+    let same_lang = |t: &&serde_json::Value| t.get("iso_639_1").and_then(|v| v.as_str()) == Some(lang.as_str());
+
+    translations.iter()
+        .filter(same_lang)
+        .find(|t| region.is_some() && t.get("iso_3166_1").and_then(|v| v.as_str()) == region.as_deref())
+        .or_else(|| translations.iter().find(same_lang))
+        .and_then(|t| t.get("data"))
+}
+
+/// First non-empty string among `keys` in a translation's `data` object.
+fn translated_field(data: Option<&serde_json::Value>, keys: &[&str]) -> Option<String> {
+    let data = data?;
+    keys.iter()
+        .filter_map(|k| data.get(*k).and_then(|v| v.as_str()))
+        .find(|s| !s.is_empty())
+        .map(|s| s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16) -> reqwest::Response {
+        axum::http::Response::builder().status(status).body("").unwrap().into()
+    }
+
+    #[test]
+    fn check_status_only_treats_rate_limits_and_server_errors_as_transient() {
+        assert!(check_status(response(200)).is_ok());
+        assert!(matches!(check_status(response(401)), Err(AppError::BadRequest(_))));
+        assert!(matches!(check_status(response(422)), Err(AppError::BadRequest(_))));
+        assert!(matches!(check_status(response(429)), Err(AppError::External(_))));
+        assert!(matches!(check_status(response(503)), Err(AppError::External(_))));
+    }
+}
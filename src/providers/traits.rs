@@ -1,10 +1,17 @@
-use crate::models::metadata::{NormalizedMetadata, EpisodeMetadata};
+use crate::models::metadata::{NormalizedMetadata, EpisodeMetadata, MetadataLocale, SubtitleSearch, SubtitleCandidate, SubtitleFile};
 use crate::error::AppError;
 use async_trait::async_trait;
 
 #[async_trait]
 pub trait MetadataProvider: Send + Sync {
-    async fn search(&self, query: &str) -> Result<Vec<NormalizedMetadata>, AppError>;
-    async fn get_details(&self, id: &str, media_type: Option<&str>) -> Result<NormalizedMetadata, AppError>;
-    async fn get_season_episodes(&self, series_id: &str, season_number: i32) -> Result<Vec<EpisodeMetadata>, AppError>;
+    async fn search(&self, query: &str, locale: &MetadataLocale) -> Result<Vec<NormalizedMetadata>, AppError>;
+    async fn get_details(&self, id: &str, media_type: Option<&str>, locale: &MetadataLocale) -> Result<NormalizedMetadata, AppError>;
+    async fn get_season_episodes(&self, series_id: &str, season_number: i32, locale: &MetadataLocale) -> Result<Vec<EpisodeMetadata>, AppError>;
+}
+
+/// Online (or local) subtitle sources, searched by file hash and provider IDs.
+#[async_trait]
+pub trait SubtitleProvider: Send + Sync {
+    async fn search(&self, query: &SubtitleSearch) -> Result<Vec<SubtitleCandidate>, AppError>;
+    async fn download(&self, id: &str) -> Result<SubtitleFile, AppError>;
 }
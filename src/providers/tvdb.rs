@@ -0,0 +1,355 @@
+use crate::providers::traits::MetadataProvider;
+use crate::providers::rate_limit::{RateLimiter, RetryPolicy, send_with_retry};
+use crate::models::metadata::{NormalizedMetadata, EpisodeMetadata, MetadataLocale};
+use crate::dtos::tvdb::{
+    TvdbEnvelope, TvdbLoginData, TvdbSearchResult, TvdbRecord, TvdbTranslation, TvdbArtwork, TvdbEpisodePage,
+};
+use crate::core::language;
+use crate::error::AppError;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Map, Value};
+use once_cell::sync::Lazy;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const API_BASE: &str = "https://api4.thetvdb.com/v4";
+const ARTWORK_BASE: &str = "https://artworks.thetvdb.com";
+
+/// Tokens are valid for a month; refresh well before that.
+const TOKEN_LIFETIME: Duration = Duration::from_secs(25 * 24 * 60 * 60);
+
+// Artwork type IDs from /artwork/types
+const ARTWORK_SERIES_POSTER: i64 = 2;
+const ARTWORK_SERIES_BACKGROUND: i64 = 3;
+const ARTWORK_MOVIE_POSTER: i64 = 14;
+const ARTWORK_MOVIE_BACKGROUND: i64 = 15;
+
+/// Episode orderings accepted in `libraries.episode_order` and the `tvdb_episode_order` setting.
+pub const EPISODE_ORDERS: &[&str] = &["aired", "dvd", "absolute", "alternate", "regional"];
+
+static TVDB_LIMITER: Lazy<RateLimiter> = Lazy::new(|| RateLimiter::new(20, 20.0));
+
+// Shared across TvdbProvider instances so we only log in once per key.
+static TOKEN_CACHE: Lazy<Mutex<Option<CachedToken>>> = Lazy::new(|| Mutex::new(None));
+
+struct CachedToken {
+    api_key: String,
+    token: String,
+    obtained: Instant,
+}
+
+pub struct TvdbProvider {
+    api_key: String,
+    pin: Option<String>,
+    episode_order: String,
+    client: reqwest::Client,
+}
+
+impl TvdbProvider {
+    pub fn new(api_key: String, pin: Option<String>, episode_order: String) -> Self {
+        Self {
+            api_key,
+            pin,
+            episode_order,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Fetch API key and optional subscriber PIN from database settings
+    pub async fn fetch_credentials(pool: &sqlx::SqlitePool) -> Result<(String, Option<String>), AppError> {
+        let key: Option<String> = sqlx::query_scalar("SELECT value FROM settings WHERE key = 'tvdb_api_key'")
+            .fetch_optional(pool)
+            .await?;
+        let key = key.filter(|k| !k.trim().is_empty())
+            .ok_or_else(|| AppError::BadRequest("TVDB API Key not found in settings".to_string()))?;
+
+        let pin: Option<String> = sqlx::query_scalar("SELECT value FROM settings WHERE key = 'tvdb_pin'")
+            .fetch_optional(pool)
+            .await?;
+
+        Ok((key, pin.filter(|p| !p.trim().is_empty())))
+    }
+
+    /// TheTVDB season-type path segment for an episode order name
+    fn season_type(&self) -> &'static str {
+        match self.episode_order.as_str() {
+            "dvd" => "dvd",
+            "absolute" => "absolute",
+            "alternate" => "alternate",
+            "regional" => "regional",
+            _ => "default",
+        }
+    }
+
+    async fn login(&self) -> Result<String, AppError> {
+        let mut body = json!({ "apikey": self.api_key });
+        if let Some(pin) = &self.pin {
+            body["pin"] = json!(pin);
+        }
+
+        let resp = send_with_retry(&TVDB_LIMITER, &RetryPolicy::default(), || {
+            self.client.post(format!("{}/login", API_BASE)).json(&body)
+        }).await?;
+
+        if !resp.status().is_success() {
+            return Err(AppError::BadRequest(format!("TVDB login failed with status {}", resp.status())));
+        }
+
+        let login = resp.json::<TvdbEnvelope<TvdbLoginData>>().await
+            .map_err(|e| AppError::External(e.to_string()))?;
+        Ok(login.data.token)
+    }
+
+    /// Get a bearer token, logging in if there is no valid cached token for this key.
+    async fn token(&self, force_refresh: bool) -> Result<String, AppError> {
+        let mut cache = TOKEN_CACHE.lock().await;
+        if let Some(cached) = cache.as_ref() {
+            if !force_refresh && cached.api_key == self.api_key && cached.obtained.elapsed() < TOKEN_LIFETIME {
+                return Ok(cached.token.clone());
+            }
+        }
+
+        let token = self.login().await?;
+        *cache = Some(CachedToken {
+            api_key: self.api_key.clone(),
+            token: token.clone(),
+            obtained: Instant::now(),
+        });
+        Ok(token)
+    }
+
+    /// GET an endpoint and unwrap the `data` envelope. Returns `None` on 404.
+    /// A 401 forces one re-login in case the cached token was revoked.
+    async fn get<T: DeserializeOwned>(&self, endpoint: &str, params: &[(&str, &str)]) -> Result<Option<TvdbEnvelope<T>>, AppError> {
+        let url = format!("{}/{}", API_BASE, endpoint);
+        let mut token = self.token(false).await?;
+
+        for attempt in 0..2 {
+            let resp = send_with_retry(&TVDB_LIMITER, &RetryPolicy::default(), || {
+                self.client.get(&url).bearer_auth(&token).query(params)
+            }).await?;
+
+            match resp.status() {
+                s if s.is_success() => {
+                    let body = resp.json::<TvdbEnvelope<T>>().await
+                        .map_err(|e| AppError::External(e.to_string()))?;
+                    return Ok(Some(body));
+                }
+                reqwest::StatusCode::NOT_FOUND => return Ok(None),
+                reqwest::StatusCode::UNAUTHORIZED if attempt == 0 => {
+                    token = self.token(true).await?;
+                }
+                s => return Err(AppError::External(format!("TVDB returned status {}", s))),
+            }
+        }
+        Err(AppError::External("TVDB authorization failed".into()))
+    }
+
+    fn parse_record(&self, record: TvdbRecord, media_type: &str, locale: &MetadataLocale) -> NormalizedMetadata {
+        let (poster_type, background_type) = if media_type == "movie" {
+            (ARTWORK_MOVIE_POSTER, ARTWORK_MOVIE_BACKGROUND)
+        } else {
+            (ARTWORK_SERIES_POSTER, ARTWORK_SERIES_BACKGROUND)
+        };
+
+        let preferred = language::to_iso639_2(&locale.language);
+        let fallback = locale.fallback().and_then(language::to_iso639_2);
+
+        let (names, overviews) = match &record.translations {
+            Some(t) => (
+                t.name_translations.as_deref().unwrap_or_default(),
+                t.overview_translations.as_deref().unwrap_or_default(),
+            ),
+            None => (&[][..], &[][..]),
+        };
+
+        let title = pick_translation(names, &[preferred, fallback], |t| t.name.as_deref())
+            .or_else(|| record.name.clone())
+            .unwrap_or_else(|| "Unknown".to_string());
+        let plot = pick_translation(overviews, &[preferred, fallback], |t| t.overview.as_deref())
+            .or_else(|| record.overview.clone().filter(|o| !o.is_empty()));
+
+        let artworks = record.artworks.as_deref().unwrap_or_default();
+        let poster_url = best_artwork(artworks, poster_type, preferred)
+            .or_else(|| record.image.as_deref().map(artwork_url));
+        let backdrop_url = best_artwork(artworks, background_type, preferred);
+
+        // Collect cross-references so later providers in a chain can look up by ID
+        let mut ids = Map::new();
+        ids.insert("tvdb".to_string(), json!(record.id));
+        for remote in record.remote_ids.iter().flatten() {
+            match remote.source_name.as_str() {
+                "IMDB" => { ids.insert("imdb".to_string(), json!(remote.id)); }
+                "TheMovieDB.com" => {
+                    if let Ok(tmdb) = remote.id.parse::<i64>() {
+                        ids.insert("tmdb".to_string(), json!(tmdb));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        NormalizedMetadata {
+            title,
+            // The base record name is in the show's original language
+            original_title: record.name,
+            year: record.year.filter(|y| !y.is_empty()),
+            plot,
+            poster_url,
+            backdrop_url,
+            media_type: Some(media_type.to_string()),
+            provider_ids: Some(Value::Object(ids)),
+            genres: record.genres.map(|gs| gs.into_iter().map(|g| g.name).collect()),
+            runtime: record.runtime.filter(|r| *r > 0),
+            rating: None,
+        }
+    }
+
+    async fn fetch_episode_page(&self, series_id: &str, season_number: i32, lang: &str) -> Result<Vec<EpisodeMetadata>, AppError> {
+        let endpoint = format!("series/{}/episodes/{}/{}", series_id, self.season_type(), lang);
+        let season = season_number.to_string();
+        let mut episodes = Vec::new();
+
+        // Seasons rarely exceed one page (500 episodes), but absolute orderings can.
+        for page in 0..10 {
+            let page_str = page.to_string();
+            let Some(resp) = self.get::<TvdbEpisodePage>(&endpoint, &[("season", &season), ("page", &page_str)]).await? else {
+                break;
+            };
+
+            episodes.extend(resp.data.episodes.into_iter()
+                .filter(|ep| ep.season_number == season_number)
+                .map(|ep| EpisodeMetadata {
+                    id: ep.id.to_string(),
+                    episode_number: ep.number,
+                    season_number: ep.season_number,
+                    name: ep.name.unwrap_or_default(),
+                    overview: ep.overview.unwrap_or_default(),
+                    still_path: ep.image.filter(|i| !i.is_empty()).map(|i| artwork_url(&i)),
+                    air_date: ep.aired,
+                }));
+
+            if resp.links.and_then(|l| l.next).is_none() {
+                break;
+            }
+        }
+        Ok(episodes)
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for TvdbProvider {
+    async fn search(&self, query: &str, locale: &MetadataLocale) -> Result<Vec<NormalizedMetadata>, AppError> {
+        let Some(resp) = self.get::<Vec<TvdbSearchResult>>("search", &[("query", query)]).await? else {
+            return Ok(Vec::new());
+        };
+
+        let preferred = language::to_iso639_2(&locale.language);
+        let fallback = locale.fallback().and_then(language::to_iso639_2);
+        let localized = |map: &Option<std::collections::HashMap<String, String>>| {
+            let map = map.as_ref()?;
+            [preferred, fallback].iter().flatten()
+                .find_map(|lang| map.get(*lang).filter(|v| !v.is_empty()).cloned())
+        };
+
+        Ok(resp.data.into_iter()
+            .filter(|r| matches!(r.kind.as_deref(), Some("series") | Some("movie")))
+            .map(|r| {
+                let media_type = if r.kind.as_deref() == Some("movie") { "movie" } else { "series" };
+                let id = r.tvdb_id.parse::<i64>().map(|i| json!(i)).unwrap_or_else(|_| json!(r.tvdb_id));
+                NormalizedMetadata {
+                    title: localized(&r.translations).unwrap_or_else(|| r.name.clone()),
+                    original_title: Some(r.name.clone()),
+                    year: r.year.clone(),
+                    plot: localized(&r.overviews).or(r.overview.clone()),
+                    poster_url: r.image_url.clone(),
+                    backdrop_url: None,
+                    media_type: Some(media_type.to_string()),
+                    provider_ids: Some(json!({ "tvdb": id })),
+                    genres: None,
+                    runtime: None,
+                    rating: None,
+                }
+            })
+            .collect())
+    }
+
+    async fn get_details(&self, id: &str, media_type: Option<&str>, locale: &MetadataLocale) -> Result<NormalizedMetadata, AppError> {
+        let params = [("meta", "translations")];
+
+        // TheTVDB is mostly used for series, so try that first when no type is given
+        let candidates: &[&str] = match media_type {
+            Some("movie") => &["movie"],
+            Some(_) => &["series"],
+            None => &["series", "movie"],
+        };
+
+        for kind in candidates {
+            let endpoint = if *kind == "movie" { format!("movies/{}/extended", id) } else { format!("series/{}/extended", id) };
+            if let Some(resp) = self.get::<TvdbRecord>(&endpoint, &params).await? {
+                return Ok(self.parse_record(resp.data, kind, locale));
+            }
+        }
+
+        Err(AppError::NotFound("TVDB ID not found".to_string()))
+    }
+
+    async fn get_season_episodes(&self, series_id: &str, season_number: i32, locale: &MetadataLocale) -> Result<Vec<EpisodeMetadata>, AppError> {
+        let preferred = language::to_iso639_2(&locale.language).unwrap_or("eng");
+        let mut episodes = self.fetch_episode_page(series_id, season_number, preferred).await?;
+
+        if let Some(fallback) = locale.fallback().and_then(language::to_iso639_2).filter(|f| *f != preferred) {
+            if episodes.iter().any(|ep| ep.name.is_empty() || ep.overview.is_empty()) {
+                if let Ok(fallback_eps) = self.fetch_episode_page(series_id, season_number, fallback).await {
+                    for ep in episodes.iter_mut() {
+                        if let Some(fb) = fallback_eps.iter().find(|f| f.episode_number == ep.episode_number) {
+                            if ep.name.is_empty() { ep.name = fb.name.clone(); }
+                            if ep.overview.is_empty() { ep.overview = fb.overview.clone(); }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(episodes)
+    }
+}
+
+/// First non-empty translation, trying each language in order
+fn pick_translation(
+    translations: &[TvdbTranslation],
+    languages: &[Option<&str>],
+    field: impl Fn(&TvdbTranslation) -> Option<&str>,
+) -> Option<String> {
+    languages.iter().flatten().find_map(|lang| {
+        translations.iter()
+            .find(|t| t.language == *lang)
+            .and_then(&field)
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_string())
+    })
+}
+
+/// Highest scored artwork of a type, preferring the given language, then language-neutral art
+fn best_artwork(artworks: &[TvdbArtwork], kind: i64, language: Option<&str>) -> Option<String> {
+    let score = |a: &&TvdbArtwork| a.score.unwrap_or(0.0);
+    let best_for = |lang: Option<&str>| {
+        artworks.iter()
+            .filter(|a| a.kind == kind && a.language.as_deref() == lang)
+            .max_by(|a, b| score(a).total_cmp(&score(b)))
+    };
+
+    best_for(language)
+        .or_else(|| best_for(None))
+        .or_else(|| artworks.iter().filter(|a| a.kind == kind).max_by(|a, b| score(a).total_cmp(&score(b))))
+        .map(|a| artwork_url(&a.image))
+}
+
+/// Artwork paths are sometimes relative ("/banners/...")
+fn artwork_url(path: &str) -> String {
+    if path.starts_with('/') {
+        format!("{}{}", ARTWORK_BASE, path)
+    } else {
+        path.to_string()
+    }
+}